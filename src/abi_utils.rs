@@ -1,10 +1,9 @@
 use core::{
-    mem::{align_of, size_of},
+    mem::{align_of, size_of, size_of_val},
     slice,
 };
-use std::{fs::File, io::Read};
 
-use crate::Error;
+use crate::{Error, ErrorKind};
 
 #[repr(transparent)]
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
@@ -21,7 +20,7 @@ impl LE32 {
 
     pub fn from(slice: &[u8]) -> Result<(Self, &[u8]), Error> {
         if slice.len() < size_of::<LE32>() {
-            return Err(Error::BufferTooSmall);
+            return Err(ErrorKind::BufferTooSmall.into());
         }
         let (le32, tail) = slice.split_at(size_of::<LE32>());
         Ok((LE32(u32::from_ne_bytes(le32.try_into().unwrap())), tail))
@@ -37,13 +36,21 @@ impl From<u32> for LE32 {
 unsafe impl TransmuteSafe for LE32 {}
 unsafe impl TransmuteSafe for u8 {}
 
+/// Plain-old-data types that can be viewed directly from the on-disk byte representation.
+///
+/// # Safety
+///
+/// Implementors must be `#[repr(C)]` or `#[repr(transparent)]`, contain no padding,
+/// and be valid for any bit pattern.
+// `usize::is_multiple_of` would raise the MSRV to 1.87 for the alignment checks.
+#[allow(dead_code, clippy::manual_is_multiple_of)]
 pub(crate) unsafe trait TransmuteSafe: Default + Clone {
     fn from_buf(buf: &[u8]) -> Result<(&Self, &[u8]), Error> {
         if buf.len() < size_of::<Self>() {
-            return Err(Error::Transmute);
+            return Err(ErrorKind::Transmute.into());
         }
         if buf.as_ptr() as usize % align_of::<Self>() != 0 {
-            return Err(Error::Transmute);
+            return Err(ErrorKind::Transmute.into());
         }
         let (me, tail) = buf.split_at(size_of::<Self>());
         let me = unsafe { &*(me.as_ptr() as *const Self) };
@@ -52,10 +59,10 @@ pub(crate) unsafe trait TransmuteSafe: Default + Clone {
 
     fn slice_from_buf(buf: &[u8], n: usize) -> Result<(&[Self], &[u8]), Error> {
         if buf.len() < n * size_of::<Self>() {
-            return Err(Error::Transmute);
+            return Err(ErrorKind::Transmute.into());
         }
         if buf.as_ptr() as usize % align_of::<Self>() != 0 {
-            return Err(Error::Transmute);
+            return Err(ErrorKind::Transmute.into());
         }
         let tail = &buf[n * size_of::<Self>()..];
         let us: &[Self] = unsafe { slice::from_raw_parts(buf.as_ptr() as *const Self, n) };
@@ -63,18 +70,11 @@ pub(crate) unsafe trait TransmuteSafe: Default + Clone {
     }
 
     fn slice_as_bytes_mut(slice: &mut [Self]) -> &mut [u8] {
-        unsafe {
-            slice::from_raw_parts_mut(
-                slice.as_mut_ptr() as *mut u8,
-                slice.len() * size_of::<Self>(),
-            )
-        }
+        unsafe { slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut u8, size_of_val(slice)) }
     }
 
     fn slice_as_bytes(slice: &[Self]) -> &[u8] {
-        unsafe {
-            slice::from_raw_parts(slice.as_ptr() as *const u8, slice.len() * size_of::<Self>())
-        }
+        unsafe { slice::from_raw_parts(slice.as_ptr() as *const u8, size_of_val(slice)) }
     }
 
     fn as_bytes_mut(&mut self) -> &mut [u8] {
@@ -85,14 +85,3 @@ pub(crate) unsafe trait TransmuteSafe: Default + Clone {
         Self::slice_as_bytes(slice::from_ref(self))
     }
 }
-
-pub(crate) fn read_vec<T: TransmuteSafe>(file: &mut File, start: usize, end: usize) -> Result<Option<Vec<T>>, Error> {
-    if start == 0 || end == 0 {
-        return Ok(None);
-    }
-    // Replace this with div_ceil once it stabilizes
-    let size = (end - start + size_of::<T>() - 1) / size_of::<T>();
-    let mut buf = vec![T::default(); size];
-    file.read_exact(T::slice_as_bytes_mut(&mut buf))?;
-    Ok(Some(buf))
-}
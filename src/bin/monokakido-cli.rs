@@ -1,17 +1,28 @@
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::Path;
+use std::sync::Arc;
 
-use monokakido::{Error, MonokakidoDict};
+use monokakido::{
+    diagnostics::{self, Level, StderrSubscriber},
+    DictionarySet, Error, ErrorKind, HtmlRenderer, MonokakidoDict, OpenOptions, TextRenderer,
+};
 
 fn print_help() {
     println!("Monokakido CLI. Supported subcommands:");
     println!("  --dir <path>  Use custom dictionary directory (optional, before subcommand)");
+    println!("  --verbose     Print diagnostics to stderr (optional, before subcommand)");
     println!("  list          Lists all dictionaries installed in the standard path");
-    println!("  list_items <dict> <keyword>   Lists all items");
+    println!("  list_items <dict> <keyword>   Prints all items as text");
+    println!("  list_prefix <dict> <prefix>   Lists all headwords starting with the prefix");
+    println!("  search_all <keyword>          Lists the dictionaries that have the headword");
+    println!("  list_suffix <dict> <suffix>   Lists all headwords ending with the suffix");
+    println!("  search_text <dict> <query>    Lists the items whose text matches the query");
+    println!("  index_text <dict>             Builds the full-text index used by search_text");
     println!("  list_audio <dict> <keyword>   Lists all audio files");
     println!("  get_audio <dict> <id>         Writes an audio file to stdout");
     println!("  dump <dict>   Dumps all dictionary entries in XML format");
+    println!("  dump_html <dict>              Dumps all dictionary entries as an HTML document");
     println!("  help          This help");
 }
 
@@ -27,6 +38,11 @@ fn parse_args() -> (Option<String>, Vec<String>) {
         if args[i] == "--dir" && i + 1 < args.len() {
             custom_dir = Some(args[i + 1].clone());
             i += 2; // Skip both --dir and its value
+        } else if args[i] == "--verbose" {
+            diagnostics::set_subscriber(Arc::new(StderrSubscriber {
+                min_level: Level::Debug,
+            }));
+            i += 1;
         } else {
             remaining_args.push(args[i].clone());
             i += 1;
@@ -37,18 +53,20 @@ fn parse_args() -> (Option<String>, Vec<String>) {
 }
 
 fn list_items(dict_name: &str, keyword: &str, custom_dir: Option<&str>) -> Result<(), Error> {
-    let mut dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
+    let dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
     let (_, items) = dict.keys.search_exact(keyword)?;
+    let mut renderer = TextRenderer::for_terminal(dict.profile());
+    renderer.gaiji(dict.gaiji().clone());
 
     for id in items {
         let item = dict.pages.get_item(id)?;
-        println!("{item}");
+        println!("{}", renderer.render(&item)?);
     }
     Ok(())
 }
 
 fn list_pages(dict_name: &str, keyword: &str, custom_dir: Option<&str>) -> Result<(), Error> {
-    let mut dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
+    let dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
     let (_, items) = dict.keys.search_exact(keyword)?;
 
     for id in items {
@@ -58,8 +76,62 @@ fn list_pages(dict_name: &str, keyword: &str, custom_dir: Option<&str>) -> Resul
     Ok(())
 }
 
-fn list_audio(dict_name: &str, keyword: &str, custom_dir: Option<&str>) -> Result<(), Error> {
+fn list_prefix(dict_name: &str, prefix: &str, custom_dir: Option<&str>) -> Result<(), Error> {
+    let dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
+
+    for res in dict.keys.search_prefix(prefix, None)? {
+        let (word, _) = res?;
+        println!("{word}");
+    }
+    Ok(())
+}
+
+fn search_all(keyword: &str, custom_dir: Option<&str>) -> Result<(), Error> {
+    let set = DictionarySet::open_all(custom_dir, &OpenOptions::new())?;
+    for (name, err) in set.failures() {
+        eprintln!("{name}: {err}");
+    }
+
+    let results = set.lookup(keyword);
+    for (name, err) in &results.errors {
+        eprintln!("{name}: {err}");
+    }
+    for (name, (lemma, _, pages)) in results.hits {
+        let pages: Vec<_> = pages.map(|id| format!("{}-{}", id.page, id.item)).collect();
+        println!("{name}\t{}\t{}", lemma.word, pages.join(" "));
+    }
+    Ok(())
+}
+
+fn list_suffix(dict_name: &str, suffix: &str, custom_dir: Option<&str>) -> Result<(), Error> {
+    let dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
+
+    for res in dict.keys.search_suffix(suffix, None)? {
+        let (word, _) = res?;
+        println!("{word}");
+    }
+    Ok(())
+}
+
+fn search_text(dict_name: &str, query: &str, custom_dir: Option<&str>) -> Result<(), Error> {
+    let dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
+
+    for hit in dict.search_text(query, 20)? {
+        println!(
+            "{}-{}\t{:.2}\t{}",
+            hit.id.page, hit.id.item, hit.score, hit.snippet
+        );
+    }
+    Ok(())
+}
+
+fn index_text(dict_name: &str, custom_dir: Option<&str>) -> Result<(), Error> {
     let mut dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
+    dict.build_fulltext_index()
+}
+
+fn list_audio(dict_name: &str, keyword: &str, custom_dir: Option<&str>) -> Result<(), Error> {
+    let dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
     let (_, items) = dict.keys.search_exact(keyword)?;
 
     for id in items {
@@ -76,11 +148,15 @@ fn list_audio(dict_name: &str, keyword: &str, custom_dir: Option<&str>) -> Resul
 
 fn get_audio(dict_name: &str, id: &str, custom_dir: Option<&str>) -> Result<(), Error> {
     let id = id.strip_suffix(".aac").unwrap_or(id);
-    let mut dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
-    let aac = dict.audio.as_mut().ok_or(Error::MissingAudio)?.get(id)?;
+    let dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
+    let aac = dict
+        .audio
+        .as_ref()
+        .ok_or(ErrorKind::MissingAudio)?
+        .get(id)?;
     let mut stdout = std::io::stdout().lock();
     // TODO: for ergonomics/failsafe, check if stdout is a TTY
-    stdout.write_all(aac)?;
+    stdout.write_all(&aac)?;
     Ok(())
 }
 
@@ -92,7 +168,7 @@ fn list_dicts(custom_dir: Option<&str>) -> Result<(), Error> {
 }
 
 fn dump_dict(dict_name: &str, custom_dir: Option<&str>) -> Result<(), Error> {
-    let mut dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
+    let dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
 
     // Create output directory
     let output_dir = if let Some(base_dir) = custom_dir {
@@ -102,11 +178,11 @@ fn dump_dict(dict_name: &str, custom_dir: Option<&str>) -> Result<(), Error> {
     };
 
     // Ensure output directory exists
-    fs::create_dir_all(&output_dir).map_err(|_| Error::IOError)?;
+    fs::create_dir_all(&output_dir)?;
 
     // Create output file
     let output_file_path = output_dir.join(format!("{}_dump.xml", dict_name));
-    let mut output_file = File::create(&output_file_path).map_err(|_| Error::IOError)?;
+    let mut output_file = File::create(&output_file_path)?;
 
     println!("Dumping {} to: {}", dict_name, output_file_path.display());
 
@@ -126,7 +202,7 @@ fn dump_dict(dict_name: &str, custom_dir: Option<&str>) -> Result<(), Error> {
         let (page_id, page_xml) = dict.pages.page_by_idx(idx)?;
 
         // Write raw entry with minimal wrapper
-        write_raw_entry(&mut output_file, page_id, page_xml)?;
+        write_raw_entry(&mut output_file, page_id, &page_xml)?;
 
         processed += 1;
         if processed % 1000 == 0 {
@@ -143,6 +219,35 @@ fn dump_dict(dict_name: &str, custom_dir: Option<&str>) -> Result<(), Error> {
     Ok(())
 }
 
+fn dump_html(dict_name: &str, custom_dir: Option<&str>) -> Result<(), Error> {
+    let dict = MonokakidoDict::open_with_dir(dict_name, custom_dir)?;
+
+    let output_dir = if let Some(base_dir) = custom_dir {
+        Path::new(base_dir).join("outputhtml")
+    } else {
+        Path::new("outputhtml").to_path_buf()
+    };
+    fs::create_dir_all(&output_dir)?;
+    let output_file_path = output_dir.join(format!("{}.html", dict_name));
+
+    println!("Rendering {} to: {}", dict_name, output_file_path.display());
+
+    let pages = dict
+        .pages
+        .idx_iter()?
+        .map(|idx| Ok(dict.pages.page_by_idx(idx)?.1))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let html = HtmlRenderer::new(dict.profile())
+        .gaiji(dict.gaiji().clone())
+        .keys(&dict.keys)
+        .render_document(dict_name, &pages)?;
+    fs::write(&output_file_path, html)?;
+
+    println!("Rendered {} pages", pages.len());
+
+    Ok(())
+}
+
 fn write_raw_entry(file: &mut File, page_id: u32, page_xml: &str) -> Result<(), Error> {
     // Just wrap the raw content in a simple d:entry with page_id
     writeln!(
@@ -163,33 +268,68 @@ fn main() {
     let (custom_dir, args) = parse_args();
     let custom_dir_ref = custom_dir.as_deref();
 
-    let res = match args.get(0).map(|s| s.as_str()) {
+    let res = match args.first().map(|s| s.as_str()) {
         Some("list_audio") => {
             if let (Some(dict_name), Some(keyword)) = (args.get(1), args.get(2)) {
                 list_audio(dict_name, keyword, custom_dir_ref)
             } else {
-                Err(Error::InvalidArg)
+                Err(ErrorKind::InvalidArg.into())
             }
         }
         Some("get_audio") => {
             if let (Some(dict_name), Some(id)) = (args.get(1), args.get(2)) {
                 get_audio(dict_name, id, custom_dir_ref)
             } else {
-                Err(Error::InvalidArg)
+                Err(ErrorKind::InvalidArg.into())
             }
         }
         Some("list_items") => {
             if let (Some(dict_name), Some(keyword)) = (args.get(1), args.get(2)) {
                 list_items(dict_name, keyword, custom_dir_ref)
             } else {
-                Err(Error::InvalidArg)
+                Err(ErrorKind::InvalidArg.into())
             }
         }
         Some("list_pages") => {
             if let (Some(dict_name), Some(keyword)) = (args.get(1), args.get(2)) {
                 list_pages(dict_name, keyword, custom_dir_ref)
             } else {
-                Err(Error::InvalidArg)
+                Err(ErrorKind::InvalidArg.into())
+            }
+        }
+        Some("list_prefix") => {
+            if let (Some(dict_name), Some(prefix)) = (args.get(1), args.get(2)) {
+                list_prefix(dict_name, prefix, custom_dir_ref)
+            } else {
+                Err(ErrorKind::InvalidArg.into())
+            }
+        }
+        Some("search_all") => {
+            if let Some(keyword) = args.get(1) {
+                search_all(keyword, custom_dir_ref)
+            } else {
+                Err(ErrorKind::InvalidArg.into())
+            }
+        }
+        Some("list_suffix") => {
+            if let (Some(dict_name), Some(suffix)) = (args.get(1), args.get(2)) {
+                list_suffix(dict_name, suffix, custom_dir_ref)
+            } else {
+                Err(ErrorKind::InvalidArg.into())
+            }
+        }
+        Some("search_text") => {
+            if let (Some(dict_name), Some(query)) = (args.get(1), args.get(2)) {
+                search_text(dict_name, query, custom_dir_ref)
+            } else {
+                Err(ErrorKind::InvalidArg.into())
+            }
+        }
+        Some("index_text") => {
+            if let Some(dict_name) = args.get(1) {
+                index_text(dict_name, custom_dir_ref)
+            } else {
+                Err(ErrorKind::InvalidArg.into())
             }
         }
         Some("list") => list_dicts(custom_dir_ref),
@@ -197,18 +337,25 @@ fn main() {
             if let Some(dict_name) = args.get(1) {
                 dump_dict(dict_name, custom_dir_ref)
             } else {
-                Err(Error::InvalidArg)
+                Err(ErrorKind::InvalidArg.into())
+            }
+        }
+        Some("dump_html") => {
+            if let Some(dict_name) = args.get(1) {
+                dump_html(dict_name, custom_dir_ref)
+            } else {
+                Err(ErrorKind::InvalidArg.into())
             }
         }
         None | Some("help") => {
             print_help();
             Ok(())
         }
-        _ => Err(Error::InvalidSubcommand),
+        _ => Err(ErrorKind::InvalidSubcommand.into()),
     };
 
     if let Err(e) = res {
-        eprintln!("Error: {e:?}");
+        eprintln!("Error: {e}");
         std::process::exit(1)
     }
 }
@@ -1,10 +1,11 @@
 use std::{
     fmt::Write as _,
-    fs::{create_dir_all, File},
+    fs::{self, create_dir_all, File},
     io::Write,
+    path::{Component, Path, PathBuf},
 };
 
-use monokakido::{Error, KeyIndex, MonokakidoDict, PageItemId};
+use monokakido::{Error, ErrorKind, KeyIndex, MediaId, MonokakidoDict, PageItemId};
 
 fn out_dir(dict: &MonokakidoDict) -> String {
     dict.name().to_owned() + "_out/"
@@ -26,6 +27,43 @@ fn write_index(dict: &MonokakidoDict, index: &KeyIndex, tsv_fname: &str) -> Resu
     Ok(())
 }
 
+// Rsc-based resources only have numeric IDs, so guess the extension from the payload.
+fn image_ext(data: &[u8]) -> &'static str {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => ".png",
+        [0xff, 0xd8, 0xff, ..] => ".jpg",
+        [b'G', b'I', b'F', b'8', ..] => ".gif",
+        [b'<', ..] => ".svg",
+        _ => "",
+    }
+}
+
+/// The file name of a graphics item: its ID, with the extension guessed if it has none.
+fn graphics_file_name(id: &MediaId, data: &[u8]) -> String {
+    let name = id.to_string();
+    if name.contains('.') {
+        name
+    } else {
+        name + image_ext(data)
+    }
+}
+
+/// Writes a graphics item under `dir`, creating the directories of nrsc IDs that are
+/// paths, such as `a/b.jpg`. Components that would leave `dir` are dropped.
+fn write_graphics(dir: &Path, id: &MediaId, data: &[u8]) -> Result<PathBuf, Error> {
+    let name = graphics_file_name(id, data);
+    let mut path = dir.to_path_buf();
+    path.extend(Path::new(&name).components().filter_map(|c| match c {
+        Component::Normal(part) => Some(part),
+        _ => None,
+    }));
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    fs::write(&path, data)?;
+    Ok(path)
+}
+
 fn parse_args() -> (Option<String>, Option<String>) {
     let mut args: Vec<String> = std::env::args().collect();
     args.remove(0); // Remove program name
@@ -51,9 +89,9 @@ fn parse_args() -> (Option<String>, Option<String>) {
 
 fn explode() -> Result<(), Error> {
     let (custom_dir, dict_name) = parse_args();
-    let dict_name = dict_name.ok_or(Error::InvalidArg)?;
+    let dict_name = dict_name.ok_or(ErrorKind::InvalidArg)?;
 
-    let mut dict = MonokakidoDict::open_with_dir(&dict_name, custom_dir.as_deref())?;
+    let dict = MonokakidoDict::open_with_dir(&dict_name, custom_dir.as_deref())?;
 
     let pages_dir = out_dir(&dict) + "pages/";
     let audio_dir = out_dir(&dict) + "audio/";
@@ -69,7 +107,7 @@ fn explode() -> Result<(), Error> {
         file.write_all(page.as_bytes())?;
     }
 
-    if let Some(audio) = &mut dict.audio {
+    if let Some(audio) = &dict.audio {
         create_dir_all(&audio_dir)?;
         let mut path = String::from(&audio_dir);
         for idx in audio.idx_iter()? {
@@ -77,19 +115,15 @@ fn explode() -> Result<(), Error> {
             write!(&mut path, "{id}.aac")?;
             let mut file = File::create(&path)?;
             path.truncate(audio_dir.len());
-            file.write_all(audio)?;
+            file.write_all(&audio)?;
         }
     }
 
-    if let Some(graphics) = &mut dict.graphics {
+    if let Some(graphics) = &dict.graphics {
         create_dir_all(&graphics_dir)?;
-        let mut path = String::from(&graphics_dir);
         for idx in graphics.idx_iter()? {
             let (id, graphics) = graphics.get_by_idx(idx)?;
-            write!(&mut path, "{id}")?;
-            let mut file = File::create(&path)?;
-            path.truncate(graphics_dir.len());
-            file.write_all(graphics)?;
+            write_graphics(Path::new(&graphics_dir), &id, &graphics)?;
         }
     }
 
@@ -102,7 +136,41 @@ fn explode() -> Result<(), Error> {
 
 fn main() {
     if let Err(err) = explode() {
-        eprintln!("{err:?}");
+        eprintln!("{err}");
         eprintln!("Usage: monokakido-explode [--dir <directory>] <dict_name>");
     };
 }
+
+#[test]
+fn test_graphics_file_name() {
+    let png = b"\x89PNG\r\n\x1a\n";
+    assert_eq!(graphics_file_name(&MediaId::Num(3), png), "0000000003.png");
+    assert_eq!(
+        graphics_file_name(&MediaId::Num(4), b"\xff\xd8\xff\xe0"),
+        "0000000004.jpg"
+    );
+    assert_eq!(
+        graphics_file_name(&MediaId::Num(5), b"GIF89a"),
+        "0000000005.gif"
+    );
+    assert_eq!(
+        graphics_file_name(&MediaId::Num(6), b"<svg/>"),
+        "0000000006.svg"
+    );
+    assert_eq!(graphics_file_name(&MediaId::Num(7), b"?"), "0000000007");
+    assert_eq!(graphics_file_name(&MediaId::Str("a/b.jpg"), png), "a/b.jpg");
+    assert_eq!(graphics_file_name(&MediaId::Str("b"), png), "b.png");
+}
+
+#[test]
+fn test_write_graphics() {
+    let tmp = tempfile::tempdir().unwrap();
+    let png = b"\x89PNG\r\n\x1a\n";
+    let path = write_graphics(tmp.path(), &MediaId::Str("a/b.jpg"), png).unwrap();
+    assert_eq!(path, tmp.path().join("a").join("b.jpg"));
+    assert_eq!(fs::read(&path).unwrap(), png);
+    let path = write_graphics(tmp.path(), &MediaId::Num(3), png).unwrap();
+    assert_eq!(path, tmp.path().join("0000000003.png"));
+    let path = write_graphics(tmp.path(), &MediaId::Str("../../c.png"), png).unwrap();
+    assert_eq!(path, tmp.path().join("c.png"));
+}
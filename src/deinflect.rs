@@ -0,0 +1,349 @@
+//! Rule-based deinflection of Japanese verbs and adjectives.
+//!
+//! The keystore only holds dictionary forms, so a conjugated query such as 読まなかった
+//! is turned into candidate lemmas (読まない, 読む, ...) by repeatedly replacing an
+//! inflected ending with the ending it was derived from. Each candidate remembers the
+//! chain of inflections that was undone; most candidates are not words, and are
+//! weeded out by looking them up.
+
+mod english;
+pub use english::lemmatize;
+
+use std::{collections::HashSet, fmt, sync::OnceLock};
+
+/// An inflection undone by [`deinflect`] or [`lemmatize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reason {
+    Past,
+    Negative,
+    Polite,
+    TeForm,
+    Volitional,
+    Imperative,
+    Conditional,
+    Provisional,
+    Potential,
+    Passive,
+    Causative,
+    Desiderative,
+    Progressive,
+    Adverbial,
+    /// English third person singular present
+    Present,
+    PastParticiple,
+    Plural,
+    Comparative,
+    Superlative,
+}
+
+impl Reason {
+    fn description(self) -> &'static str {
+        use Reason::*;
+        match self {
+            Past => "past",
+            Negative => "negative",
+            Polite => "polite",
+            TeForm => "te-form",
+            Volitional => "volitional",
+            Imperative => "imperative",
+            Conditional => "conditional",
+            Provisional => "provisional",
+            Potential => "potential",
+            Passive => "passive",
+            Causative => "causative",
+            Desiderative => "desiderative",
+            Progressive => "progressive",
+            Adverbial => "adverbial",
+            Present => "present",
+            PastParticiple => "past participle",
+            Plural => "plural",
+            Comparative => "comparative",
+            Superlative => "superlative",
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// A candidate lemma of a conjugated word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deinflection {
+    pub word: String,
+    /// The inflections that turn `word` back into the query, innermost first:
+    /// 読まなかった is 読む with `[Negative, Past]`.
+    pub reasons: Vec<Reason>,
+    /// What `word` must be for the chain to be valid; 0 for the query itself.
+    kind: u8,
+}
+
+impl Deinflection {
+    fn new(word: String, reasons: Vec<Reason>) -> Self {
+        Deinflection {
+            word,
+            reasons,
+            kind: 0,
+        }
+    }
+}
+
+// Word kinds, as bit sets. A rule's input kind is what the inflected form behaves as:
+// 読まない can be inflected further like an i-adjective.
+const V1: u8 = 1 << 0; // ichidan verb
+const V5: u8 = 1 << 1; // godan verb
+const VK: u8 = 1 << 2; // 来る
+const VS: u8 = 1 << 3; // する
+const ADJ_I: u8 = 1 << 4;
+const MASU: u8 = 1 << 5; // polite form, before its own inflections are undone
+const TE: u8 = 1 << 6; // te-form, before ている is undone
+const LEMMA: u8 = V1 | V5 | VK | VS | ADJ_I;
+
+struct Rule {
+    from: String,
+    to: String,
+    /// Kinds the inflected form can have. 0 means that it can't be inflected further,
+    /// so the rule only applies to the query.
+    kind_in: u8,
+    kind_out: u8,
+    reasons: &'static [Reason],
+}
+
+fn rule(from: &str, to: &str, kind_in: u8, kind_out: u8, reasons: &'static [Reason]) -> Rule {
+    Rule {
+        from: from.to_owned(),
+        to: to.to_owned(),
+        kind_in,
+        kind_out,
+        reasons,
+    }
+}
+
+// Godan verb endings by row: dictionary form, a-row, i-row, e-row, o-row,
+// and the past tense ending.
+const GODAN: [[&str; 6]; 9] = [
+    ["う", "わ", "い", "え", "お", "った"],
+    ["く", "か", "き", "け", "こ", "いた"],
+    ["ぐ", "が", "ぎ", "げ", "ご", "いだ"],
+    ["す", "さ", "し", "せ", "そ", "した"],
+    ["つ", "た", "ち", "て", "と", "った"],
+    ["ぬ", "な", "に", "ね", "の", "んだ"],
+    ["ぶ", "ば", "び", "べ", "ぼ", "んだ"],
+    ["む", "ま", "み", "め", "も", "んだ"],
+    ["る", "ら", "り", "れ", "ろ", "った"],
+];
+
+fn rules() -> &'static [Rule] {
+    static RULES: OnceLock<Vec<Rule>> = OnceLock::new();
+    RULES.get_or_init(build_rules)
+}
+
+fn build_rules() -> Vec<Rule> {
+    use Reason::*;
+    let mut rules = vec![
+        // Polite forms come back to ます first.
+        rule("ました", "ます", 0, MASU, &[Past]),
+        rule("ません", "ます", 0, MASU, &[Negative]),
+        rule("ませんでした", "ます", 0, MASU, &[Negative, Past]),
+        rule("ましょう", "ます", 0, MASU, &[Volitional]),
+        rule("まして", "ます", TE, MASU, &[TeForm]),
+        // ている and its contraction てる follow a te-form.
+        rule("ている", "て", V1, TE, &[Progressive]),
+        rule("でいる", "で", V1, TE, &[Progressive]),
+        rule("てる", "て", V1, TE, &[Progressive]),
+        rule("でる", "で", V1, TE, &[Progressive]),
+        // Ichidan verbs
+        rule("た", "る", 0, V1, &[Past]),
+        rule("て", "る", TE, V1, &[TeForm]),
+        rule("ない", "る", ADJ_I, V1, &[Negative]),
+        rule("ます", "る", MASU, V1, &[Polite]),
+        rule("よう", "る", 0, V1, &[Volitional]),
+        rule("ろ", "る", 0, V1, &[Imperative]),
+        rule("よ", "る", 0, V1, &[Imperative]),
+        rule("たら", "る", 0, V1, &[Conditional]),
+        rule("れば", "る", 0, V1, &[Provisional]),
+        rule("られる", "る", V1, V1, &[Potential]),
+        rule("られる", "る", V1, V1, &[Passive]),
+        rule("させる", "る", V1, V1, &[Causative]),
+        rule("たい", "る", ADJ_I, V1, &[Desiderative]),
+        // i-adjectives
+        rule("かった", "い", 0, ADJ_I, &[Past]),
+        rule("くない", "い", ADJ_I, ADJ_I, &[Negative]),
+        rule("くて", "い", TE, ADJ_I, &[TeForm]),
+        rule("く", "い", 0, ADJ_I, &[Adverbial]),
+        rule("かったら", "い", 0, ADJ_I, &[Conditional]),
+        rule("ければ", "い", 0, ADJ_I, &[Provisional]),
+        // 行く is the only godan verb with an irregular past.
+        rule("いった", "いく", 0, V5, &[Past]),
+        rule("いって", "いく", TE, V5, &[TeForm]),
+        rule("いったら", "いく", 0, V5, &[Conditional]),
+        rule("行った", "行く", 0, V5, &[Past]),
+        rule("行って", "行く", TE, V5, &[TeForm]),
+        rule("行ったら", "行く", 0, V5, &[Conditional]),
+    ];
+
+    for [u, a, i, e, o, past] in GODAN {
+        let te = past.replace('た', "て").replace('だ', "で");
+        let godan = |from: String, kind_in, reasons| rule(&from, u, kind_in, V5, reasons);
+        rules.extend([
+            godan(past.to_owned(), 0, &[Past]),
+            godan(te, TE, &[TeForm]),
+            godan(format!("{past}ら"), 0, &[Conditional]),
+            godan(format!("{a}ない"), ADJ_I, &[Negative]),
+            godan(format!("{i}ます"), MASU, &[Polite]),
+            godan(format!("{o}う"), 0, &[Volitional]),
+            godan(e.to_owned(), 0, &[Imperative]),
+            godan(format!("{e}ば"), 0, &[Provisional]),
+            godan(format!("{e}る"), V1, &[Potential]),
+            godan(format!("{a}れる"), V1, &[Passive]),
+            godan(format!("{a}せる"), V1, &[Causative]),
+            godan(format!("{i}たい"), ADJ_I, &[Desiderative]),
+        ]);
+    }
+
+    // する, which also conjugates the suru-verbs formed from nouns
+    let suru: [(&str, u8, &'static [Reason]); 12] = [
+        ("した", 0, &[Past]),
+        ("して", TE, &[TeForm]),
+        ("したら", 0, &[Conditional]),
+        ("しない", ADJ_I, &[Negative]),
+        ("します", MASU, &[Polite]),
+        ("しよう", 0, &[Volitional]),
+        ("しろ", 0, &[Imperative]),
+        ("せよ", 0, &[Imperative]),
+        ("すれば", 0, &[Provisional]),
+        ("される", V1, &[Passive]),
+        ("させる", V1, &[Causative]),
+        ("したい", ADJ_I, &[Desiderative]),
+    ];
+    rules.extend(
+        suru.iter()
+            .map(|&(from, kind_in, reasons)| rule(from, "する", kind_in, VS, reasons)),
+    );
+
+    // 来る, written in kana or with its kanji
+    let kuru: [(&str, &str, u8, &'static [Reason]); 11] = [
+        ("き", "た", 0, &[Past]),
+        ("き", "て", TE, &[TeForm]),
+        ("き", "たら", 0, &[Conditional]),
+        ("こ", "ない", ADJ_I, &[Negative]),
+        ("き", "ます", MASU, &[Polite]),
+        ("こ", "よう", 0, &[Volitional]),
+        ("こ", "い", 0, &[Imperative]),
+        ("く", "れば", 0, &[Provisional]),
+        ("こ", "られる", V1, &[Potential, Passive]),
+        ("こ", "させる", V1, &[Causative]),
+        ("き", "たい", ADJ_I, &[Desiderative]),
+    ];
+    for (stem, ending, kind_in, reasons) in kuru {
+        rules.push(rule(
+            &format!("{stem}{ending}"),
+            "くる",
+            kind_in,
+            VK,
+            reasons,
+        ));
+        rules.push(rule(&format!("来{ending}"), "来る", kind_in, VK, reasons));
+    }
+    rules
+}
+
+/// Returns the query itself and every form that it may be an inflection of.
+///
+/// Rules match hiragana endings, so the query should be written as it would be in
+/// running text (食べた, not タベタ). Candidates with shorter chains come first.
+pub fn deinflect(query: &str) -> Vec<Deinflection> {
+    let mut candidates = vec![Deinflection::new(query.to_owned(), Vec::new())];
+    // Every rule either shortens the word or yields a kind that no rule takes as
+    // its input, so this terminates.
+    let mut i = 0;
+    while i < candidates.len() {
+        let Deinflection {
+            word,
+            reasons,
+            kind,
+        } = candidates[i].clone();
+        for rule in rules() {
+            let Some(stem) = word.strip_suffix(rule.from.as_str()) else {
+                continue;
+            };
+            if kind != 0 && kind & rule.kind_in == 0 {
+                continue;
+            }
+            candidates.push(Deinflection {
+                word: format!("{stem}{}", rule.to),
+                reasons: rule.reasons.iter().chain(&reasons).copied().collect(),
+                kind: rule.kind_out,
+            });
+        }
+        i += 1;
+    }
+    // Godan and ichidan rules can undo the same ending, as in 食べられる.
+    let mut seen = HashSet::new();
+    candidates.retain(|c| {
+        (c.kind == 0 || c.kind & LEMMA != 0) && seen.insert((c.word.clone(), c.reasons.clone()))
+    });
+    candidates
+}
+
+#[cfg(test)]
+fn chains(query: &str, lemma: &str) -> Vec<Vec<Reason>> {
+    deinflect(query)
+        .into_iter()
+        .filter(|c| c.word == lemma)
+        .map(|c| c.reasons)
+        .collect()
+}
+
+#[test]
+fn test_deinflect() {
+    use Reason::*;
+    let table: &[(&str, &str, &[Reason])] = &[
+        ("食べる", "食べる", &[]),
+        ("食べた", "食べる", &[Past]),
+        ("食べない", "食べる", &[Negative]),
+        ("食べなかった", "食べる", &[Negative, Past]),
+        ("食べました", "食べる", &[Polite, Past]),
+        ("食べませんでした", "食べる", &[Polite, Negative, Past]),
+        ("食べている", "食べる", &[TeForm, Progressive]),
+        ("食べてた", "食べる", &[TeForm, Progressive, Past]),
+        ("食べさせられる", "食べる", &[Causative, Passive]),
+        ("読まなかった", "読む", &[Negative, Past]),
+        ("読んで", "読む", &[TeForm]),
+        ("読んでいます", "読む", &[TeForm, Progressive, Polite]),
+        ("読める", "読む", &[Potential]),
+        ("読みたかった", "読む", &[Desiderative, Past]),
+        ("書こう", "書く", &[Volitional]),
+        ("泳いだ", "泳ぐ", &[Past]),
+        ("話せば", "話す", &[Provisional]),
+        ("待って", "待つ", &[TeForm]),
+        ("死んだら", "死ぬ", &[Conditional]),
+        ("買わない", "買う", &[Negative]),
+        ("行った", "行く", &[Past]),
+        ("高くて", "高い", &[TeForm]),
+        ("高くなかった", "高い", &[Negative, Past]),
+        ("早く", "早い", &[Adverbial]),
+        ("勉強した", "勉強する", &[Past]),
+        ("しなかった", "する", &[Negative, Past]),
+        ("来なかった", "来る", &[Negative, Past]),
+        ("きました", "くる", &[Polite, Past]),
+    ];
+    for &(query, lemma, reasons) in table {
+        assert!(
+            chains(query, lemma).iter().any(|c| c == reasons),
+            "{query}: {:?}",
+            chains(query, lemma)
+        );
+    }
+
+    // 行った is also the past of 行う
+    assert_eq!(chains("行った", "行う"), [[Past]]);
+    assert_eq!(chains("られる", "る"), [[Potential], [Passive]]);
+    // ます is not a dictionary form
+    assert!(chains("見ません", "見ます").is_empty());
+    assert_eq!(deinflect("本")[0].word, "本");
+    assert_eq!(deinflect("本").len(), 1);
+    assert_eq!(Reason::TeForm.to_string(), "te-form");
+}
@@ -0,0 +1,300 @@
+use super::{Deinflection, Reason};
+
+// Irregular verbs: base form, past tense, past participle
+const IRREGULAR_VERBS: &[[&str; 3]] = &[
+    ["arise", "arose", "arisen"],
+    ["awake", "awoke", "awoken"],
+    ["be", "was", "been"],
+    ["be", "were", "been"],
+    ["bear", "bore", "borne"],
+    ["beat", "beat", "beaten"],
+    ["become", "became", "become"],
+    ["begin", "began", "begun"],
+    ["bend", "bent", "bent"],
+    ["bind", "bound", "bound"],
+    ["bite", "bit", "bitten"],
+    ["bleed", "bled", "bled"],
+    ["blow", "blew", "blown"],
+    ["break", "broke", "broken"],
+    ["breed", "bred", "bred"],
+    ["bring", "brought", "brought"],
+    ["build", "built", "built"],
+    ["burn", "burnt", "burnt"],
+    ["buy", "bought", "bought"],
+    ["catch", "caught", "caught"],
+    ["choose", "chose", "chosen"],
+    ["cling", "clung", "clung"],
+    ["come", "came", "come"],
+    ["creep", "crept", "crept"],
+    ["deal", "dealt", "dealt"],
+    ["dig", "dug", "dug"],
+    ["do", "did", "done"],
+    ["draw", "drew", "drawn"],
+    ["dream", "dreamt", "dreamt"],
+    ["drink", "drank", "drunk"],
+    ["drive", "drove", "driven"],
+    ["eat", "ate", "eaten"],
+    ["fall", "fell", "fallen"],
+    ["feed", "fed", "fed"],
+    ["feel", "felt", "felt"],
+    ["fight", "fought", "fought"],
+    ["find", "found", "found"],
+    ["flee", "fled", "fled"],
+    ["fly", "flew", "flown"],
+    ["forbid", "forbade", "forbidden"],
+    ["forget", "forgot", "forgotten"],
+    ["forgive", "forgave", "forgiven"],
+    ["freeze", "froze", "frozen"],
+    ["get", "got", "gotten"],
+    ["give", "gave", "given"],
+    ["go", "went", "gone"],
+    ["grind", "ground", "ground"],
+    ["grow", "grew", "grown"],
+    ["hang", "hung", "hung"],
+    ["have", "had", "had"],
+    ["hear", "heard", "heard"],
+    ["hide", "hid", "hidden"],
+    ["hold", "held", "held"],
+    ["keep", "kept", "kept"],
+    ["kneel", "knelt", "knelt"],
+    ["know", "knew", "known"],
+    ["lay", "laid", "laid"],
+    ["lead", "led", "led"],
+    ["lean", "leant", "leant"],
+    ["leap", "leapt", "leapt"],
+    ["learn", "learnt", "learnt"],
+    ["leave", "left", "left"],
+    ["lend", "lent", "lent"],
+    ["lie", "lay", "lain"],
+    ["light", "lit", "lit"],
+    ["lose", "lost", "lost"],
+    ["make", "made", "made"],
+    ["mean", "meant", "meant"],
+    ["meet", "met", "met"],
+    ["pay", "paid", "paid"],
+    ["ride", "rode", "ridden"],
+    ["ring", "rang", "rung"],
+    ["rise", "rose", "risen"],
+    ["run", "ran", "run"],
+    ["say", "said", "said"],
+    ["see", "saw", "seen"],
+    ["seek", "sought", "sought"],
+    ["sell", "sold", "sold"],
+    ["send", "sent", "sent"],
+    ["shake", "shook", "shaken"],
+    ["shine", "shone", "shone"],
+    ["shoot", "shot", "shot"],
+    ["show", "showed", "shown"],
+    ["shrink", "shrank", "shrunk"],
+    ["sing", "sang", "sung"],
+    ["sink", "sank", "sunk"],
+    ["sit", "sat", "sat"],
+    ["sleep", "slept", "slept"],
+    ["slide", "slid", "slid"],
+    ["speak", "spoke", "spoken"],
+    ["spend", "spent", "spent"],
+    ["spin", "spun", "spun"],
+    ["spring", "sprang", "sprung"],
+    ["stand", "stood", "stood"],
+    ["steal", "stole", "stolen"],
+    ["stick", "stuck", "stuck"],
+    ["sting", "stung", "stung"],
+    ["stink", "stank", "stunk"],
+    ["strike", "struck", "struck"],
+    ["swear", "swore", "sworn"],
+    ["sweep", "swept", "swept"],
+    ["swim", "swam", "swum"],
+    ["swing", "swung", "swung"],
+    ["take", "took", "taken"],
+    ["teach", "taught", "taught"],
+    ["tear", "tore", "torn"],
+    ["tell", "told", "told"],
+    ["think", "thought", "thought"],
+    ["throw", "threw", "thrown"],
+    ["understand", "understood", "understood"],
+    ["wake", "woke", "woken"],
+    ["wear", "wore", "worn"],
+    ["weep", "wept", "wept"],
+    ["win", "won", "won"],
+    ["wind", "wound", "wound"],
+    ["write", "wrote", "written"],
+];
+
+// Other irregular forms: inflected form, lemma, inflection
+const IRREGULAR: &[(&str, &str, Reason)] = &[
+    ("am", "be", Reason::Present),
+    ("is", "be", Reason::Present),
+    ("are", "be", Reason::Present),
+    ("has", "have", Reason::Present),
+    ("does", "do", Reason::Present),
+    ("goes", "go", Reason::Present),
+    ("better", "good", Reason::Comparative),
+    ("best", "good", Reason::Superlative),
+    ("better", "well", Reason::Comparative),
+    ("best", "well", Reason::Superlative),
+    ("worse", "bad", Reason::Comparative),
+    ("worst", "bad", Reason::Superlative),
+    ("more", "many", Reason::Comparative),
+    ("most", "many", Reason::Superlative),
+    ("more", "much", Reason::Comparative),
+    ("most", "much", Reason::Superlative),
+    ("less", "little", Reason::Comparative),
+    ("least", "little", Reason::Superlative),
+    ("further", "far", Reason::Comparative),
+    ("furthest", "far", Reason::Superlative),
+    ("farther", "far", Reason::Comparative),
+    ("farthest", "far", Reason::Superlative),
+    ("children", "child", Reason::Plural),
+    ("men", "man", Reason::Plural),
+    ("women", "woman", Reason::Plural),
+    ("people", "person", Reason::Plural),
+    ("feet", "foot", Reason::Plural),
+    ("teeth", "tooth", Reason::Plural),
+    ("geese", "goose", Reason::Plural),
+    ("mice", "mouse", Reason::Plural),
+    ("lice", "louse", Reason::Plural),
+    ("oxen", "ox", Reason::Plural),
+    ("dice", "die", Reason::Plural),
+    ("data", "datum", Reason::Plural),
+    ("criteria", "criterion", Reason::Plural),
+    ("phenomena", "phenomenon", Reason::Plural),
+    ("analyses", "analysis", Reason::Plural),
+    ("crises", "crisis", Reason::Plural),
+    ("theses", "thesis", Reason::Plural),
+    ("cacti", "cactus", Reason::Plural),
+    ("fungi", "fungus", Reason::Plural),
+    ("nuclei", "nucleus", Reason::Plural),
+    ("indices", "index", Reason::Plural),
+    ("matrices", "matrix", Reason::Plural),
+];
+
+// Suffix rules: inflected ending, lemma ending, inflection
+const RULES: &[(&str, &str, Reason)] = &[
+    ("s", "", Reason::Present),
+    ("es", "", Reason::Present),
+    ("ies", "y", Reason::Present),
+    ("s", "", Reason::Plural),
+    ("es", "", Reason::Plural),
+    ("ies", "y", Reason::Plural),
+    ("ves", "f", Reason::Plural),
+    ("ves", "fe", Reason::Plural),
+    ("men", "man", Reason::Plural),
+    ("ing", "", Reason::Progressive),
+    ("ing", "e", Reason::Progressive),
+    ("ying", "ie", Reason::Progressive),
+    ("ed", "", Reason::Past),
+    ("ed", "e", Reason::Past),
+    ("ied", "y", Reason::Past),
+    ("er", "", Reason::Comparative),
+    ("er", "e", Reason::Comparative),
+    ("ier", "y", Reason::Comparative),
+    ("est", "", Reason::Superlative),
+    ("est", "e", Reason::Superlative),
+    ("iest", "y", Reason::Superlative),
+    ("ly", "", Reason::Adverbial),
+    ("ly", "le", Reason::Adverbial),
+    ("ily", "y", Reason::Adverbial),
+    ("ally", "", Reason::Adverbial),
+];
+
+fn is_consonant(c: char) -> bool {
+    c.is_ascii_alphabetic() && !"aeiou".contains(c)
+}
+
+/// Returns the query itself and every English lemma it may be an inflection of.
+///
+/// Irregular forms come from a table; regular ones are found by stripping suffixes,
+/// which yields many candidates that are not words, to be weeded out by looking them up.
+pub fn lemmatize(query: &str) -> Vec<Deinflection> {
+    let mut candidates = vec![Deinflection::new(query.to_owned(), Vec::new())];
+    let word = query.to_lowercase();
+
+    for [base, past, participle] in IRREGULAR_VERBS {
+        if word == *past {
+            candidates.push(Deinflection::new(base.to_string(), vec![Reason::Past]));
+        }
+        if word == *participle && participle != base {
+            let reasons = vec![Reason::PastParticiple];
+            candidates.push(Deinflection::new(base.to_string(), reasons));
+        }
+    }
+    for (form, lemma, reason) in IRREGULAR {
+        if word == *form {
+            candidates.push(Deinflection::new(lemma.to_string(), vec![*reason]));
+        }
+    }
+
+    for (from, to, reason) in RULES {
+        let Some(stem) = word.strip_suffix(from) else {
+            continue;
+        };
+        let lemma = format!("{stem}{to}");
+        if lemma.chars().count() < 2 {
+            continue;
+        }
+        candidates.push(Deinflection::new(lemma, vec![*reason]));
+        // Doubled final consonants: running, stopped, bigger
+        let mut chars = stem.chars().rev();
+        if let (Some(last), Some(prev), true) = (chars.next(), chars.next(), to.is_empty()) {
+            if last == prev && is_consonant(last) && from.starts_with(['i', 'e']) {
+                let stem = &stem[..stem.len() - last.len_utf8()];
+                candidates.push(Deinflection::new(stem.to_owned(), vec![*reason]));
+            }
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|c| seen.insert((c.word.clone(), c.reasons.clone())));
+    candidates
+}
+
+#[cfg(test)]
+fn lemmas(query: &str) -> Vec<(String, Vec<Reason>)> {
+    lemmatize(query)
+        .into_iter()
+        .map(|c| (c.word, c.reasons))
+        .collect()
+}
+
+#[test]
+fn test_lemmatize() {
+    use Reason::*;
+    let table: &[(&str, &str, &[Reason])] = &[
+        ("running", "run", &[Progressive]),
+        ("making", "make", &[Progressive]),
+        ("lying", "lie", &[Progressive]),
+        ("geese", "goose", &[Plural]),
+        ("better", "good", &[Comparative]),
+        ("best", "good", &[Superlative]),
+        ("cities", "city", &[Plural]),
+        ("wolves", "wolf", &[Plural]),
+        ("knives", "knife", &[Plural]),
+        ("boxes", "box", &[Plural]),
+        ("goes", "go", &[Present]),
+        ("stopped", "stop", &[Past]),
+        ("loved", "love", &[Past]),
+        ("tried", "try", &[Past]),
+        ("went", "go", &[Past]),
+        ("written", "write", &[PastParticiple]),
+        ("bigger", "big", &[Comparative]),
+        ("nicest", "nice", &[Superlative]),
+        ("happier", "happy", &[Comparative]),
+        ("quickly", "quick", &[Adverbial]),
+        ("gently", "gentle", &[Adverbial]),
+        ("Running", "run", &[Progressive]),
+    ];
+    for &(query, lemma, reasons) in table {
+        let found = lemmas(query);
+        assert!(
+            found.iter().any(|(w, r)| w == lemma && r == reasons),
+            "{query}: {found:?}"
+        );
+    }
+
+    assert_eq!(lemmas("dog")[0], ("dog".to_owned(), vec![]));
+    assert_eq!(lemmas("dog").len(), 1);
+    // `become` is its own participle
+    assert!(!lemmas("become").contains(&("become".to_owned(), vec![PastParticiple])));
+    assert!(lemmas("is").iter().all(|(w, _)| w != "i"));
+    assert!(lemmas("filling").iter().any(|(w, _)| w == "fill"));
+}
@@ -0,0 +1,270 @@
+//! Structured diagnostics.
+//!
+//! The library reports what it is doing (loading indexes, discovering resource files,
+//! searching, decompressing) as typed [`Event`]s. Nothing is reported unless a
+//! [`Subscriber`] is installed with [`set_subscriber`].
+
+use std::{
+    cmp::Ordering,
+    fmt,
+    path::Path,
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc, RwLock,
+    },
+};
+
+use crate::IndexStorage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndexKind {
+    Keystore,
+    Headlines,
+    RscIdx,
+    RscMap,
+    Nrsc,
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// A dictionary is being opened.
+    DictOpening { name: &'a str, path: &'a Path },
+    /// A dictionary was opened successfully.
+    DictOpened { name: &'a str, contents: &'a Path },
+    IndexLoaded {
+        kind: IndexKind,
+        path: &'a Path,
+        entries: usize,
+        storage: IndexStorage,
+    },
+    ResourceFileFound {
+        path: &'a Path,
+        seqnum: u32,
+        len: usize,
+    },
+    /// A file in a resource directory that doesn't belong to the resource.
+    ResourceFileIgnored { path: &'a Path },
+    /// The resource files are not numbered consecutively.
+    ResourceFileMissing { dir: &'a Path, seqnum: u32 },
+    /// One step of a binary search over a key index.
+    SearchStep {
+        target: &'a str,
+        low: usize,
+        high: usize,
+        mid: usize,
+        word: &'a str,
+        ordering: Ordering,
+    },
+    SearchFinished {
+        target: &'a str,
+        found: Option<usize>,
+    },
+    Decompressed {
+        compressed_len: usize,
+        decompressed_len: usize,
+    },
+    /// The page list of a keystore entry doesn't have the expected shape.
+    MalformedPageList { pages_offset: usize },
+}
+
+impl Event<'_> {
+    pub fn level(&self) -> Level {
+        match self {
+            Event::DictOpening { .. } | Event::DictOpened { .. } => Level::Info,
+            Event::IndexLoaded { .. } | Event::ResourceFileFound { .. } => Level::Debug,
+            Event::ResourceFileIgnored { .. }
+            | Event::SearchStep { .. }
+            | Event::SearchFinished { .. }
+            | Event::Decompressed { .. } => Level::Trace,
+            Event::ResourceFileMissing { .. } | Event::MalformedPageList { .. } => Level::Warn,
+        }
+    }
+}
+
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::DictOpening { name, path } => {
+                write!(f, "opening {name} at {}", path.display())
+            }
+            Event::DictOpened { name, contents } => {
+                write!(f, "opened {name}, contents at {}", contents.display())
+            }
+            Event::IndexLoaded {
+                kind,
+                path,
+                entries,
+                storage,
+            } => write!(
+                f,
+                "loaded {kind:?} index {} ({entries} entries, {storage:?})",
+                path.display()
+            ),
+            Event::ResourceFileFound { path, seqnum, len } => {
+                write!(
+                    f,
+                    "resource file #{seqnum} {} ({len} bytes)",
+                    path.display()
+                )
+            }
+            Event::ResourceFileIgnored { path } => write!(f, "ignoring {}", path.display()),
+            Event::ResourceFileMissing { dir, seqnum } => {
+                write!(f, "resource file #{seqnum} missing in {}", dir.display())
+            }
+            Event::SearchStep {
+                target,
+                low,
+                high,
+                mid,
+                word,
+                ordering,
+            } => write!(
+                f,
+                "search '{target}': low {low}, high {high}, mid {mid} '{word}' -> {ordering:?}"
+            ),
+            Event::SearchFinished { target, found } => match found {
+                Some(idx) => write!(f, "search '{target}': found at {idx}"),
+                None => write!(f, "search '{target}': not found"),
+            },
+            Event::Decompressed {
+                compressed_len,
+                decompressed_len,
+            } => write!(f, "inflated {compressed_len} -> {decompressed_len} bytes"),
+            Event::MalformedPageList { pages_offset } => {
+                write!(f, "malformed page list at offset {pages_offset}")
+            }
+        }
+    }
+}
+
+pub trait Subscriber: Send + Sync {
+    fn enabled(&self, level: Level) -> bool {
+        let _ = level;
+        true
+    }
+
+    fn event(&self, event: &Event<'_>);
+}
+
+/// Writes events at or above a level to stderr.
+#[derive(Debug, Clone, Copy)]
+pub struct StderrSubscriber {
+    pub min_level: Level,
+}
+
+impl Subscriber for StderrSubscriber {
+    fn enabled(&self, level: Level) -> bool {
+        level >= self.min_level
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        eprintln!("[{:?}] {event}", event.level());
+    }
+}
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static SUBSCRIBER: RwLock<Option<Arc<dyn Subscriber>>> = RwLock::new(None);
+
+/// Installs a process-wide subscriber, replacing the previous one.
+pub fn set_subscriber(subscriber: Arc<dyn Subscriber>) {
+    let mut guard = SUBSCRIBER.write().unwrap_or_else(|e| e.into_inner());
+    *guard = Some(subscriber);
+    ACTIVE.store(true, atomic::Ordering::Release);
+}
+
+pub fn clear_subscriber() {
+    let mut guard = SUBSCRIBER.write().unwrap_or_else(|e| e.into_inner());
+    *guard = None;
+    ACTIVE.store(false, atomic::Ordering::Release);
+}
+
+pub(crate) fn active() -> bool {
+    ACTIVE.load(atomic::Ordering::Acquire)
+}
+
+pub(crate) fn emit(event: Event<'_>) {
+    if !active() {
+        return;
+    }
+    let guard = SUBSCRIBER.read().unwrap_or_else(|e| e.into_inner());
+    if let Some(subscriber) = guard.as_ref() {
+        if subscriber.enabled(event.level()) {
+            subscriber.event(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) struct ThreadRecorder {
+    thread: std::thread::ThreadId,
+    events: std::sync::Mutex<Vec<(Level, String)>>,
+}
+
+#[cfg(test)]
+pub(crate) struct Recording {
+    recorder: Arc<ThreadRecorder>,
+    _guard: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl Recording {
+    /// Records the events emitted from the current thread until dropped.
+    /// Recordings are serialized, since the subscriber is process-wide.
+    pub(crate) fn start() -> Self {
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let recorder = Arc::new(ThreadRecorder {
+            thread: std::thread::current().id(),
+            events: Default::default(),
+        });
+        set_subscriber(recorder.clone());
+        Recording {
+            recorder,
+            _guard: guard,
+        }
+    }
+
+    pub(crate) fn events(&self) -> Vec<(Level, String)> {
+        self.recorder.events.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Drop for Recording {
+    fn drop(&mut self) {
+        clear_subscriber();
+    }
+}
+
+#[cfg(test)]
+impl Subscriber for ThreadRecorder {
+    fn event(&self, event: &Event<'_>) {
+        if std::thread::current().id() == self.thread {
+            let mut events = self.events.lock().unwrap();
+            events.push((event.level(), event.to_string()));
+        }
+    }
+}
+
+#[test]
+fn test_level_filter() {
+    let sub = StderrSubscriber {
+        min_level: Level::Debug,
+    };
+    assert!(!sub.enabled(Level::Trace));
+    assert!(sub.enabled(Level::Debug));
+    assert!(sub.enabled(Level::Warn));
+    assert_eq!(
+        Event::MalformedPageList { pages_offset: 4 }.level(),
+        Level::Warn
+    );
+}
@@ -1,32 +1,80 @@
-use miniserde::{json, Deserialize};
+use miniserde::json;
 use std::{
     ffi::OsStr,
     fs,
     path::{Path, PathBuf},
+    sync::OnceLock,
 };
 use toml::Value;
 
-use crate::{key::Keys, media::Media, pages::Pages, Error};
+use crate::{
+    deinflect::Deinflection,
+    diagnostics::{self, Event},
+    entry::{Entry, MediaRef, Profile},
+    error::ResultExt,
+    fulltext::{self, FullTextIndex, TextHit},
+    gaiji::GaijiTable,
+    headline::Headlines,
+    key::{Keys, PageIter},
+    media::{Media, MediaKind},
+    options::OpenOptions,
+    pages::Pages,
+    resource::Blob,
+    Error, ErrorKind, FileFormat, PageItemId,
+};
+
+/// The language of a dictionary's headwords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    Japanese,
+    English,
+}
+
+impl Language {
+    /// Parses a language code such as `ja` or `en-GB`.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let lang = code.split(['-', '_']).next()?;
+        match lang.to_ascii_lowercase().as_str() {
+            "ja" | "jpn" => Some(Language::Japanese),
+            "en" | "eng" => Some(Language::English),
+            _ => None,
+        }
+    }
+}
 
 pub struct MonokakidoDict {
     paths: Paths,
+    language: Option<Language>,
+    profile: Profile,
+    gaiji: GaijiTable,
     pub pages: Pages,
     pub audio: Option<Media>,
     pub graphics: Option<Media>,
     pub keys: Keys,
+    pub headlines: Option<Headlines>,
+    fulltext: OnceLock<FullTextIndex>,
 }
 
-#[derive(Deserialize, Debug)]
-struct DictJson {
-    #[serde(rename = "DSProductContents")]
-    contents: Vec<DSProductContents>,
-}
+// miniserde's derive expands to impls nested in a const block.
+#[allow(non_local_definitions)]
+mod json_abi {
+    use miniserde::Deserialize;
 
-#[derive(Deserialize, Debug)]
-struct DSProductContents {
-    #[serde(rename = "DSContentDirectory")]
-    dir: String,
+    #[derive(Deserialize, Debug)]
+    pub(super) struct DictJson {
+        #[serde(rename = "DSProductContents")]
+        pub contents: Vec<DSProductContents>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub(super) struct DSProductContents {
+        #[serde(rename = "DSContentDirectory")]
+        pub dir: String,
+        #[serde(rename = "DSContentLanguages")]
+        pub languages: Option<Vec<String>>,
+    }
 }
+use json_abi::DictJson;
 
 pub struct Paths {
     base_path: PathBuf,
@@ -68,7 +116,7 @@ impl Paths {
 
     fn std_dict_path(name: &str, custom_dir: Option<&str>) -> PathBuf {
         let mut path = Paths::list_path(custom_dir);
-        path.push(format!("{name}"));
+        path.push(name);
         path
     }
 
@@ -109,6 +157,13 @@ impl Paths {
         pb.push("headline.headlinestore");
         pb
     }
+
+    /// The full-text index is kept next to the product directory, as `<product>.ftidx`.
+    pub(crate) fn fulltext_path(&self) -> PathBuf {
+        let mut name = self.base_path.file_name().unwrap_or_default().to_owned();
+        name.push(".ftidx");
+        self.base_path.with_file_name(name)
+    }
 }
 
 fn parse_dict_name(fname: &OsStr) -> Option<&str> {
@@ -121,10 +176,11 @@ impl MonokakidoDict {
     pub fn list_with_dir(
         custom_dir: Option<&str>,
     ) -> Result<impl Iterator<Item = Result<String, Error>>, Error> {
-        let iter = fs::read_dir(Paths::list_path(custom_dir)).map_err(|_| Error::IOError)?;
-        Ok(iter.filter_map(|entry| {
+        let list_path = Paths::list_path(custom_dir);
+        let iter = fs::read_dir(&list_path).with_path(&list_path)?;
+        Ok(iter.filter_map(move |entry| {
             entry
-                .map_err(|_| Error::IOError)
+                .with_path(&list_path)
                 .map(|e| parse_dict_name(&e.file_name()).map(ToOwned::to_owned))
                 .transpose()
         }))
@@ -135,62 +191,379 @@ impl MonokakidoDict {
     }
 
     pub fn open_with_dir(name: &str, custom_dir: Option<&str>) -> Result<Self, Error> {
-        let std_path = Paths::std_dict_path(name, custom_dir);
-        Self::open_with_path_name(std_path, name)
+        Self::open_with_options(name, custom_dir, &OpenOptions::new())
     }
 
     pub fn open(name: &str) -> Result<Self, Error> {
         Self::open_with_dir(name, None)
     }
 
+    pub(crate) fn open_with_options(
+        name: &str,
+        custom_dir: Option<&str>,
+        options: &OpenOptions,
+    ) -> Result<Self, Error> {
+        let std_path = Paths::std_dict_path(name, custom_dir);
+        Self::open_with_path_name(std_path, name, options)
+    }
+
     pub fn name(&self) -> &str {
         &self.paths.name
     }
 
+    /// The language of the headwords: the one set in the options, or else the only language
+    /// listed in the product metadata, if it's a known one.
+    pub fn language(&self) -> Option<Language> {
+        self.language
+    }
+
+    /// The profile entries are parsed with.
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// The gaiji mappings set in the options. Empty otherwise, as no mappings are shipped
+    /// with the crate.
+    pub fn gaiji(&self) -> &GaijiTable {
+        &self.gaiji
+    }
+
+    /// Reads the image of a gaiji from the graphics resource, if it's there.
+    ///
+    /// The image is looked up by its path as written, then by its file name, then by its
+    /// file stem, which is the item ID in an rsc resource: `gaiji/12.png` is item 12.
+    pub fn gaiji_image(&self, media: &MediaRef) -> Result<Option<Blob>, Error> {
+        let Some(graphics) = &self.graphics else {
+            return Ok(None);
+        };
+        let href = media.href.as_str();
+        let name = href.rsplit('/').next().unwrap_or(href);
+        let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+        let mut ids = vec![href, name, stem];
+        ids.dedup();
+        for id in ids {
+            match graphics.get(id) {
+                Ok(blob) => return Ok(Some(blob)),
+                Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::InvalidIndex) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads and parses the entry of the item `id`.
+    pub fn entry(&self, id: PageItemId) -> Result<Entry, Error> {
+        Entry::parse(&self.pages.get_item(id)?, &self.profile)
+    }
+
+    /// Looks up `query` and its possible dictionary forms: English inflections are
+    /// undone in English products, and Japanese conjugations in all others.
+    pub fn lookup(&self, query: &str) -> Result<Vec<(Deinflection, usize, PageIter<'_>)>, Error> {
+        match self.language {
+            Some(Language::English) => self.keys.search_lemmatized(query),
+            Some(Language::Japanese) | None => self.keys.search_deinflected(query),
+        }
+    }
+
+    /// Opens a media resource directory other than the standard audio and graphics ones.
+    pub fn media(&self, kind: MediaKind, options: &OpenOptions) -> Result<Option<Media>, Error> {
+        Media::new(&self.paths, kind, options)
+    }
+
     pub fn open_with_path(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        Self::open_with_path_options(path, &OpenOptions::new())
+    }
+
+    pub(crate) fn open_with_path_options(
+        path: impl Into<PathBuf>,
+        options: &OpenOptions,
+    ) -> Result<Self, Error> {
         let path: PathBuf = path.into();
-        let dir_name = path.file_name().ok_or(Error::FopenError)?.to_string_lossy();
+        let dir_name = path
+            .file_name()
+            .ok_or(ErrorKind::FopenError)?
+            .to_string_lossy();
 
-        let dict_name = dir_name.rsplit_once('.').ok_or(Error::FopenError)?.0;
+        let dict_name = dir_name.rsplit_once('.').ok_or(ErrorKind::FopenError)?.0;
 
-        Self::open_with_path_name(&path, dict_name)
+        Self::open_with_path_name(&path, dict_name, options)
     }
 
-    fn open_with_path_name(path: impl Into<PathBuf>, name: &str) -> Result<Self, Error> {
+    fn open_with_path_name(
+        path: impl Into<PathBuf>,
+        name: &str,
+        options: &OpenOptions,
+    ) -> Result<Self, Error> {
         let base_path = path.into();
+        diagnostics::emit(Event::DictOpening {
+            name,
+            path: &base_path,
+        });
         let json_path = Paths::json_path(&base_path, name);
-        println!("DEBUG: Reading JSON from: {:?}", json_path);
-        let json = fs::read_to_string(json_path).map_err(|_| Error::NoDictJsonFound)?;
-        let mut json: DictJson = json::from_str(&json).map_err(|_| Error::InvalidDictJson)?;
-        let contents = json.contents.pop().ok_or(Error::InvalidDictJson)?;
+        let json = fs::read_to_string(&json_path)
+            .map_err(|e| Error::io(ErrorKind::NoDictJsonFound, e).with_path(&json_path))?;
+        let invalid_json = || {
+            Error::new(ErrorKind::InvalidDictJson)
+                .in_format(FileFormat::DictJson)
+                .with_path(&json_path)
+        };
+        let mut json: DictJson = json::from_str(&json).map_err(|_| invalid_json())?;
+        let contents = json.contents.pop().ok_or_else(invalid_json)?;
+        // Bilingual dictionaries list both languages, in no order known to say which one
+        // the headwords are in, so only a single language is taken as theirs.
+        let language =
+            options
+                .language
+                .or_else(|| match contents.languages.as_deref().unwrap_or_default() {
+                    [code] => Language::from_code(code),
+                    _ => None,
+                });
+        let profile = options
+            .profile
+            .clone()
+            .unwrap_or_else(|| Profile::for_product(name));
+        let gaiji = options.gaiji.clone().unwrap_or_default();
         let paths = Paths {
             base_path,
             name: name.to_owned(),
             contents_dir: contents.dir,
         };
 
-        println!("DEBUG: Contents directory: {:?}", paths.contents_path());
-
-        println!("DEBUG: Initializing Pages...");
-        let pages = Pages::new(&paths)?;
+        let pages = Pages::new(&paths, options)?;
+        let audio = Media::new(&paths, MediaKind::Audio, options)?;
+        let graphics = Media::new(&paths, MediaKind::Graphics, options)?;
+        let keys = Keys::new_with_options(paths.key_headword_path(), options)?;
 
-        println!("DEBUG: Initializing Media (audio)...");
-        let audio = Media::new(&paths)?;
-
-        println!("DEBUG: Initializing Media (graphics)...");
-        let graphics = Media::new(&paths)?;
-
-        println!("DEBUG: Initializing Keys...");
-        let keys = Keys::new(paths.key_headword_path())?;
+        let headline_path = paths.headline_long_path();
+        let headlines = if headline_path.exists() {
+            Some(Headlines::new_with_options(headline_path, options)?)
+        } else {
+            None
+        };
 
-        println!("DEBUG: All components initialized successfully!");
+        diagnostics::emit(Event::DictOpened {
+            name,
+            contents: &paths.contents_path(),
+        });
 
         Ok(MonokakidoDict {
             paths,
+            language,
+            profile,
+            gaiji,
             pages,
             audio,
             graphics,
             keys,
+            headlines,
+            fulltext: OnceLock::new(),
         })
     }
+
+    /// Builds the full-text index of the page contents and saves it next to the product.
+    pub fn build_fulltext_index(&mut self) -> Result<(), Error> {
+        let index = FullTextIndex::build(&self.pages)?;
+        index.save(&self.paths.fulltext_path())?;
+        self.fulltext = OnceLock::from(index);
+        Ok(())
+    }
+
+    /// Searches the page contents for `query`, using the index saved by
+    /// [`build_fulltext_index`](Self::build_fulltext_index).
+    pub fn search_text(&self, query: &str, limit: usize) -> Result<Vec<TextHit>, Error> {
+        self.fulltext_index()?
+            .search(query, limit)
+            .into_iter()
+            .map(|(id, score)| {
+                let (page, xml) = (id.page, self.pages.get_page(id)?);
+                let text = fulltext::item_texts(page, &xml)?
+                    .into_iter()
+                    .find(|(item, _)| *item == id.item)
+                    .map(|(_, text)| text)
+                    .unwrap_or_default();
+                Ok(TextHit {
+                    id,
+                    score,
+                    snippet: fulltext::snippet(&text, query),
+                })
+            })
+            .collect()
+    }
+
+    fn fulltext_index(&self) -> Result<&FullTextIndex, Error> {
+        if let Some(index) = self.fulltext.get() {
+            return Ok(index);
+        }
+        let path = self.paths.fulltext_path();
+        if !path.exists() {
+            return Err(Error::new(ErrorKind::IndexDoesntExist).with_path(&path));
+        }
+        let _ = self.fulltext.set(FullTextIndex::load(&path)?);
+        Ok(self.fulltext.get().unwrap())
+    }
+}
+
+/// Writes a minimal product, with only a JSON file and a keystore, to `dir/name`.
+#[cfg(test)]
+pub(crate) fn write_test_product(dir: &Path, name: &str, entries: &[(&str, &[(u32, u8)])]) {
+    let contents = dir.join(name).join("Contents");
+    let key_dir = contents.join(name.to_lowercase()).join("key");
+    fs::create_dir_all(&key_dir).unwrap();
+    let json = format!(
+        r#"{{"DSProductContents": [{{"DSContentDirectory": "{}"}}]}}"#,
+        name.to_lowercase()
+    );
+    fs::write(contents.join(format!("{name}.json")), json).unwrap();
+    let keystore = crate::key::build_keystore(entries);
+    fs::write(key_dir.join("headword.keystore"), keystore).unwrap();
+}
+
+/// Adds the pages `pages` to a product written by [`write_test_product`].
+#[cfg(test)]
+pub(crate) fn write_test_pages(dir: &Path, name: &str, pages: &[(u32, &str)]) {
+    let contents = dir.join(name).join("Contents");
+    let pages_dir = contents.join(name.to_lowercase()).join("contents");
+    let pages: Vec<_> = pages
+        .iter()
+        .map(|(id, xml)| (*id, xml.as_bytes()))
+        .collect();
+    crate::resource::write_test_rsc(&pages_dir, "contents", &pages, 2);
+}
+
+#[test]
+fn test_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<MonokakidoDict>();
+    assert_send_sync::<crate::Xml>();
+}
+
+#[test]
+fn test_concurrent_read() {
+    use crate::PageItemId;
+    let tmp = tempfile::tempdir().unwrap();
+    write_test_product(tmp.path(), "Test", &[("テスト", &[(1, 0)])]);
+    let pages: Vec<_> = (1..=20)
+        .map(|id| (id, format!(r#"<d><body id="{id}-1">page {id}</body></d>"#)))
+        .collect();
+    let page_refs: Vec<_> = pages.iter().map(|(id, xml)| (*id, xml.as_str())).collect();
+    write_test_pages(tmp.path(), "Test", &page_refs);
+    let dict = MonokakidoDict::open_with_dir("Test", tmp.path().to_str()).unwrap();
+
+    std::thread::scope(|s| {
+        for t in 0..4 {
+            let (dict, pages) = (&dict, &pages);
+            s.spawn(move || {
+                for (id, xml) in pages.iter().cycle().skip(t * 5).take(40) {
+                    let page = dict.pages.get_page(PageItemId { page: *id, item: 0 });
+                    assert_eq!(&*page.unwrap(), xml);
+                    let item = dict.pages.get_item(PageItemId { page: *id, item: 1 });
+                    assert_eq!(
+                        item.unwrap().to_string(),
+                        format!(r#"<body id="{id}-1">page {id}</body>"#)
+                    );
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn test_dict_entry() {
+    use crate::PageItemId;
+    let tmp = tempfile::tempdir().unwrap();
+    write_test_product(tmp.path(), "SMK8", &[("アイ", &[(1, 0)])]);
+    let xml = "<d><body><midashi>あい</midashi><meaning><imi>愛</imi></meaning></body></d>";
+    write_test_pages(tmp.path(), "SMK8", &[(1, xml)]);
+    let dir = tmp.path().to_str();
+    let id = PageItemId { page: 1, item: 0 };
+
+    let dict = MonokakidoDict::open_with_dir("SMK8", dir).unwrap();
+    assert_eq!(dict.profile(), &Profile::for_product("SMK8"));
+    assert!(dict.entry(id).unwrap().headwords.is_empty());
+
+    let dict = OpenOptions::new()
+        .profile(Profile::fixture("SMK8"))
+        .open_with_dir("SMK8", dir)
+        .unwrap();
+    let entry = dict.entry(id).unwrap();
+    assert_eq!(entry.headwords, ["あい"]);
+    assert_eq!(entry.senses[0].definition, "愛");
+
+    let dict = OpenOptions::new()
+        .profile(Profile::new("none"))
+        .open_with_dir("SMK8", dir)
+        .unwrap();
+    assert_eq!(dict.profile().name(), "none");
+    assert_eq!(dict.entry(id).unwrap().raw.len(), 1);
+}
+
+#[test]
+fn test_dict_gaiji() {
+    let tmp = tempfile::tempdir().unwrap();
+    write_test_product(tmp.path(), "SMK8", &[("アイ", &[(1, 0)])]);
+    let graphics = tmp.path().join("SMK8/Contents/smk8/graphics");
+    crate::resource::write_test_rsc(&graphics, "graphics", &[(7, b"GIF89a")], 2);
+    let dir = tmp.path().to_str();
+
+    let dict = MonokakidoDict::open_with_dir("SMK8", dir).unwrap();
+    assert!(dict.gaiji().is_empty());
+    let image = |href: &str| MediaRef {
+        kind: MediaKind::Graphics,
+        href: href.to_owned(),
+    };
+    let blob = dict.gaiji_image(&image("7")).unwrap().unwrap();
+    assert_eq!(&blob[..], b"GIF89a");
+    let blob = dict.gaiji_image(&image("gaiji/7.png")).unwrap().unwrap();
+    assert_eq!(&blob[..], b"GIF89a");
+    assert!(dict.gaiji_image(&image("8")).unwrap().is_none());
+    assert!(dict.gaiji_image(&image("gaiji/g1.png")).unwrap().is_none());
+
+    let mut table = GaijiTable::new();
+    table.insert("U+E002", "↔").insert("g1", "𠀋");
+    let dict = OpenOptions::new()
+        .gaiji(table)
+        .open_with_dir("SMK8", dir)
+        .unwrap();
+    assert_eq!(dict.gaiji().get("U+E002"), Some("↔"));
+    assert_eq!(dict.gaiji().get("U+E003"), None);
+    assert_eq!(dict.gaiji().get("g1"), Some("𠀋"));
+}
+
+#[test]
+fn test_dict_language() {
+    let tmp = tempfile::tempdir().unwrap();
+    write_test_product(tmp.path(), "TEST", &[("ア", &[(1, 0)])]);
+    let json_path = tmp.path().join("TEST/Contents/TEST.json");
+    let dir = tmp.path().to_str();
+    let open_with = |languages: &str, options: &OpenOptions| {
+        let json = format!(
+            r#"{{"DSProductContents": [{{"DSContentDirectory": "test", "DSContentLanguages": {languages}}}]}}"#
+        );
+        fs::write(&json_path, json).unwrap();
+        options.open_with_dir("TEST", dir).unwrap().language()
+    };
+    let options = OpenOptions::new();
+    assert_eq!(open_with(r#"["en"]"#, &options), Some(Language::English));
+    assert_eq!(
+        open_with(r#"["ja-JP"]"#, &options),
+        Some(Language::Japanese)
+    );
+    assert_eq!(open_with(r#"["en", "ja"]"#, &options), None);
+    assert_eq!(open_with(r#"["ja", "en"]"#, &options), None);
+    assert_eq!(open_with("[]", &options), None);
+    let mut options = OpenOptions::new();
+    options.language(Language::English);
+    assert_eq!(
+        open_with(r#"["ja", "en"]"#, &options),
+        Some(Language::English)
+    );
+}
+
+#[test]
+fn test_language_from_code() {
+    assert_eq!(Language::from_code("en"), Some(Language::English));
+    assert_eq!(Language::from_code("en-GB"), Some(Language::English));
+    assert_eq!(Language::from_code("JA"), Some(Language::Japanese));
+    assert_eq!(Language::from_code("zh-Hans"), None);
+    assert_eq!(Language::from_code(""), None);
 }
@@ -0,0 +1,253 @@
+use crate::{
+    Deinflection, Error, ErrorKind, LinkTarget, MonokakidoDict, OpenOptions, PageItemId, PageIter,
+};
+
+/// A set of dictionaries that are searched together, like the "all dictionaries"
+/// search of the Monokakido app.
+///
+/// Each dictionary has a priority, and results of dictionaries with a higher priority
+/// come first. A dictionary that fails to open or to answer a query is reported
+/// alongside the results of the others.
+#[derive(Default)]
+pub struct DictionarySet {
+    /// Sorted by descending priority, then by insertion order
+    dicts: Vec<(i32, MonokakidoDict)>,
+    failures: Vec<(String, Error)>,
+}
+
+/// The results of a query against every dictionary of a [`DictionarySet`].
+pub struct SetResults<'a, T> {
+    /// Results tagged with the name of their dictionary, in priority order.
+    pub hits: Vec<(&'a str, T)>,
+    /// The dictionaries the query failed on.
+    pub errors: Vec<(&'a str, Error)>,
+}
+
+impl DictionarySet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the products `names`, with priorities decreasing in the given order.
+    /// Products that fail to open are left out, and listed in [`failures`](Self::failures).
+    pub fn open<'n>(
+        names: impl IntoIterator<Item = &'n str>,
+        custom_dir: Option<&str>,
+        options: &OpenOptions,
+    ) -> Self {
+        let mut set = Self::new();
+        let names: Vec<_> = names.into_iter().collect();
+        for (i, name) in names.iter().enumerate() {
+            let priority = (names.len() - i) as i32;
+            match options.open_with_dir(name, custom_dir) {
+                Ok(dict) => set.insert(dict, priority),
+                Err(e) => set.failures.push((name.to_string(), e)),
+            }
+        }
+        set
+    }
+
+    /// Opens every product listed by [`MonokakidoDict::list_with_dir`], with equal priority.
+    pub fn open_all(custom_dir: Option<&str>, options: &OpenOptions) -> Result<Self, Error> {
+        let mut set = Self::new();
+        for name in MonokakidoDict::list_with_dir(custom_dir)? {
+            let name = name?;
+            match options.open_with_dir(&name, custom_dir) {
+                Ok(dict) => set.insert(dict, 0),
+                Err(e) => set.failures.push((name, e)),
+            }
+        }
+        Ok(set)
+    }
+
+    /// Adds a dictionary. It goes after the dictionaries of the same priority.
+    pub fn insert(&mut self, dict: MonokakidoDict, priority: i32) {
+        let pos = self.dicts.partition_point(|(p, _)| *p >= priority);
+        self.dicts.insert(pos, (priority, dict));
+    }
+
+    /// Changes the priority of the dictionary `name`. Returns false if it isn't in the set.
+    pub fn set_priority(&mut self, name: &str, priority: i32) -> bool {
+        let Some(pos) = self.dicts.iter().position(|(_, d)| d.name() == name) else {
+            return false;
+        };
+        let (_, dict) = self.dicts.remove(pos);
+        self.insert(dict, priority);
+        true
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<MonokakidoDict> {
+        let pos = self.dicts.iter().position(|(_, d)| d.name() == name)?;
+        Some(self.dicts.remove(pos).1)
+    }
+
+    /// The dictionaries, in priority order.
+    pub fn dicts(&self) -> impl Iterator<Item = &MonokakidoDict> {
+        self.dicts.iter().map(|(_, d)| d)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MonokakidoDict> {
+        self.dicts().find(|d| d.name() == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut MonokakidoDict> {
+        self.dicts
+            .iter_mut()
+            .map(|(_, d)| d)
+            .find(|d| d.name() == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.dicts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dicts.is_empty()
+    }
+
+    /// The products that failed to open, with their errors.
+    pub fn failures(&self) -> &[(String, Error)] {
+        &self.failures
+    }
+
+    /// Runs `query` against every dictionary, and merges the results in priority order.
+    /// `NotFound` errors count as no results.
+    pub fn search<'a, T>(
+        &'a self,
+        query: impl Fn(&'a MonokakidoDict) -> Result<Vec<T>, Error>,
+    ) -> SetResults<'a, T> {
+        let mut results = SetResults {
+            hits: Vec::new(),
+            errors: Vec::new(),
+        };
+        for dict in self.dicts() {
+            match query(dict) {
+                Ok(hits) => results
+                    .hits
+                    .extend(hits.into_iter().map(|hit| (dict.name(), hit))),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => results.errors.push((dict.name(), e)),
+            }
+        }
+        results
+    }
+
+    /// Finds the headword `key` in every dictionary.
+    /// See [`Keys::search_exact`](crate::Keys::search_exact).
+    pub fn search_exact(&self, key: &str) -> SetResults<'_, (usize, PageIter<'_>)> {
+        self.search(|dict| Ok(vec![dict.keys.search_exact(key)?]))
+    }
+
+    /// Returns up to `limit` headwords per dictionary that start with `prefix`.
+    pub fn search_prefix(
+        &self,
+        prefix: &str,
+        limit: Option<usize>,
+    ) -> SetResults<'_, (&str, PageIter<'_>)> {
+        self.search(|dict| dict.keys.search_prefix(prefix, limit)?.collect())
+    }
+
+    /// Looks up `query` and its dictionary forms in every dictionary.
+    /// See [`MonokakidoDict::lookup`].
+    pub fn lookup(&self, query: &str) -> SetResults<'_, (Deinflection, usize, PageIter<'_>)> {
+        self.search(|dict| dict.lookup(query))
+    }
+
+    /// Resolves a link found in the dictionary `from`, following links to other products
+    /// that are in the set. Returns the name of the dictionary of the target with it.
+    pub fn resolve_link(
+        &self,
+        from: &str,
+        target: &LinkTarget,
+    ) -> Result<Option<(&str, PageItemId)>, Error> {
+        let (dict, target) = match target {
+            LinkTarget::Product { product, target } => {
+                let dict = self
+                    .dicts()
+                    .find(|d| d.name().eq_ignore_ascii_case(product));
+                (dict, &**target)
+            }
+            _ => (self.get(from), target),
+        };
+        let Some(dict) = dict else {
+            return Ok(None);
+        };
+        Ok(target.resolve(&dict.keys)?.map(|id| (dict.name(), id)))
+    }
+}
+
+#[cfg(test)]
+fn test_set(dir: &std::path::Path) -> DictionarySet {
+    use crate::dict::write_test_product;
+    write_test_product(dir, "A", &[("アイ", &[(1, 0)]), ("アイス", &[(2, 0)])]);
+    write_test_product(dir, "B", &[("アイス", &[(3, 0)])]);
+    write_test_product(dir, "C", &[("アイスクリーム", &[(4, 0)])]);
+    std::fs::create_dir(dir.join("Broken")).unwrap();
+    let dir = dir.to_str().unwrap();
+    DictionarySet::open(["B", "Broken", "A", "C"], Some(dir), &OpenOptions::new())
+}
+
+#[test]
+fn test_dictionary_set() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut set = test_set(tmp.path());
+    assert_eq!(set.len(), 3);
+    assert_eq!(
+        set.dicts().map(|d| d.name()).collect::<Vec<_>>(),
+        ["B", "A", "C"]
+    );
+    assert_eq!(set.failures().len(), 1);
+    assert_eq!(set.failures()[0].0, "Broken");
+    assert_eq!(set.failures()[0].1.kind(), ErrorKind::NoDictJsonFound);
+
+    fn pages<'a>(results: SetResults<'a, (usize, PageIter<'a>)>) -> Vec<(&'a str, u32)> {
+        let hits = results.hits.into_iter();
+        hits.map(|(name, (_, mut p))| (name, p.next().unwrap().page))
+            .collect()
+    }
+    assert_eq!(pages(set.search_exact("アイス")), [("B", 3), ("A", 2)]);
+
+    assert!(set.set_priority("A", 10));
+    assert!(!set.set_priority("Broken", 10));
+    assert_eq!(pages(set.search_exact("アイス")), [("A", 2), ("B", 3)]);
+
+    let results = set.search_prefix("アイ", Some(1));
+    let words: Vec<_> = results.hits.iter().map(|(n, (w, _))| (*n, *w)).collect();
+    assert_eq!(
+        words,
+        [("A", "アイ"), ("B", "アイス"), ("C", "アイスクリーム")]
+    );
+    assert!(results.errors.is_empty());
+
+    let results = set.search(|dict| match dict.name() {
+        "B" => Err(ErrorKind::InvalidIndex.into()),
+        _ => Ok(vec![dict.name().len()]),
+    });
+    assert_eq!(results.hits, [("A", 1), ("C", 1)]);
+    assert_eq!(results.errors.len(), 1);
+    assert_eq!(results.errors[0].0, "B");
+
+    let link = LinkTarget::parse("B:key:アイス").unwrap();
+    let resolved = set.resolve_link("A", &link).unwrap();
+    assert_eq!(resolved, Some(("B", PageItemId { page: 3, item: 0 })));
+    let link = LinkTarget::parse("key:アイ").unwrap();
+    let resolved = set.resolve_link("A", &link).unwrap();
+    assert_eq!(resolved, Some(("A", PageItemId { page: 1, item: 0 })));
+    let link = LinkTarget::parse("OTHER:1-1").unwrap();
+    assert_eq!(set.resolve_link("A", &link).unwrap(), None);
+
+    assert!(set.remove("C").is_some());
+    assert!(set.get("C").is_none());
+    assert!(set.get_mut("A").is_some());
+}
+
+#[test]
+fn test_dictionary_set_open_all() {
+    let tmp = tempfile::tempdir().unwrap();
+    test_set(tmp.path());
+    let set = DictionarySet::open_all(tmp.path().to_str(), &OpenOptions::new()).unwrap();
+    let mut names: Vec<_> = set.dicts().map(|d| d.name()).collect();
+    names.sort();
+    assert_eq!(names, ["A", "B", "C"]);
+    assert_eq!(set.failures().len(), 1);
+}
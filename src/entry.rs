@@ -0,0 +1,374 @@
+//! A typed model of dictionary entries, parsed from the page XML.
+//!
+//! Products mark up their entries with different tag vocabularies, so the parsing goes
+//! through a [`Profile`] that maps elements to the parts of an [`Entry`].
+
+mod dom;
+mod profile;
+
+pub(crate) use dom::{escape, unescape};
+pub use dom::{parse_fragment, Element, Node};
+pub use profile::{Profile, Role};
+
+use crate::{pages::parse_item_id, Error, LinkTarget, MediaKind, PageItemId};
+
+/// A dictionary entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// The item ID of the entry element, if it has one.
+    pub id: Option<PageItemId>,
+    pub headwords: Vec<String>,
+    pub reading: Option<String>,
+    pub pitch_accents: Vec<String>,
+    pub parts_of_speech: Vec<String>,
+    pub senses: Vec<Sense>,
+    pub idioms: Vec<Idiom>,
+    pub xrefs: Vec<CrossRef>,
+    /// The media referenced anywhere in the entry, in document order.
+    pub media: Vec<MediaRef>,
+    /// Markup outside of senses and idioms that the profile doesn't know.
+    pub raw: Vec<Element>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sense {
+    /// The sense number as printed, e.g. `1` or `㋐`.
+    pub number: Option<String>,
+    pub definition: String,
+    pub examples: Vec<Example>,
+    pub subsenses: Vec<Sense>,
+    pub xrefs: Vec<CrossRef>,
+    /// Markup within the sense that the profile doesn't know. Its text is kept in the
+    /// loose text of the sense too.
+    pub raw: Vec<Element>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Example {
+    pub text: String,
+    pub translation: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Idiom {
+    pub phrase: String,
+    pub senses: Vec<Sense>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossRef {
+    pub text: String,
+    pub target: LinkTarget,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaRef {
+    pub kind: MediaKind,
+    /// The path of the resource, as written.
+    pub href: String,
+}
+
+impl MediaRef {
+    /// Recognizes references to audio and graphics resources by their file extension.
+    pub(crate) fn from_href(href: &str) -> Option<Self> {
+        let ext = href.rsplit_once('.')?.1.to_ascii_lowercase();
+        let kind = match ext.as_str() {
+            "aac" | "mp3" | "m4a" | "wav" => MediaKind::Audio,
+            "png" | "jpg" | "jpeg" | "gif" | "svg" | "tif" | "tiff" | "heic" => MediaKind::Graphics,
+            _ => return None,
+        };
+        Some(MediaRef {
+            kind,
+            href: href.to_owned(),
+        })
+    }
+}
+
+impl Entry {
+    /// Parses the XML of an item, as returned by [`Pages::get_item`](crate::Pages::get_item).
+    pub fn parse(xml: &str, profile: &Profile) -> Result<Self, Error> {
+        let nodes = parse_fragment(xml)?;
+        let mut entry = Entry::default();
+        for node in &nodes {
+            if let Node::Element(element) = node {
+                if entry.id.is_none() {
+                    entry.id = element.attr("id").and_then(parse_item_id);
+                }
+                collect_media(element, &mut entry.media);
+            }
+        }
+        let mut parser = Parser {
+            profile,
+            entry: &mut entry,
+        };
+        for node in &nodes {
+            if let Node::Element(element) = node {
+                parser.entry_element(element);
+            }
+        }
+        Ok(entry)
+    }
+
+    /// All the senses, with sub-senses following their parent.
+    pub fn all_senses(&self) -> impl Iterator<Item = &Sense> {
+        fn flatten<'a>(senses: &'a [Sense], out: &mut Vec<&'a Sense>) {
+            for sense in senses {
+                out.push(sense);
+                flatten(&sense.subsenses, out);
+            }
+        }
+        let mut all = Vec::new();
+        flatten(&self.senses, &mut all);
+        all.into_iter()
+    }
+}
+
+fn collect_media(element: &Element, media: &mut Vec<MediaRef>) {
+    let href = element.attr("href").or_else(|| element.attr("src"));
+    media.extend(href.and_then(MediaRef::from_href));
+    for child in element.elements() {
+        collect_media(child, media);
+    }
+}
+
+fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+struct Parser<'a> {
+    profile: &'a Profile,
+    entry: &'a mut Entry,
+}
+
+impl Parser<'_> {
+    fn entry_element(&mut self, element: &Element) {
+        match self.profile.role(element) {
+            Some(Role::Container) => {
+                for child in element.elements() {
+                    self.entry_element(child);
+                }
+            }
+            Some(Role::Ignore) => {}
+            Some(Role::Headword) => self.entry.headwords.push(clean(&element.text())),
+            Some(Role::Reading) => {
+                let reading = clean(&element.text());
+                self.entry.reading.get_or_insert(reading);
+            }
+            Some(Role::PitchAccent) => self.entry.pitch_accents.push(clean(&element.text())),
+            Some(Role::PartOfSpeech) => self.entry.parts_of_speech.push(clean(&element.text())),
+            Some(Role::Sense | Role::Definition) => {
+                let sense = self.sense(element);
+                self.entry.senses.push(sense);
+            }
+            Some(Role::Idiom) => {
+                let idiom = self.idiom(element);
+                self.entry.idioms.push(idiom);
+            }
+            Some(Role::CrossRef) => {
+                if let Some(xref) = xref(element) {
+                    self.entry.xrefs.push(xref);
+                }
+            }
+            // Out of place; keep it rather than guess
+            Some(_) | None => self.entry.raw.push(element.clone()),
+        }
+    }
+
+    fn sense(&self, element: &Element) -> Sense {
+        let mut sense = Sense::default();
+        if self.profile.role(element) == Some(Role::Definition) {
+            sense.definition = clean(&element.text());
+            return sense;
+        }
+        let mut loose_text = String::new();
+        self.sense_children(element, &mut sense, &mut loose_text);
+        if sense.definition.is_empty() {
+            sense.definition = clean(&loose_text);
+        }
+        sense
+    }
+
+    fn sense_children(&self, element: &Element, sense: &mut Sense, loose_text: &mut String) {
+        for node in &element.children {
+            self.sense_node(node, sense, loose_text);
+        }
+    }
+
+    fn sense_node(&self, node: &Node, sense: &mut Sense, loose_text: &mut String) {
+        let child = match node {
+            Node::Element(child) => child,
+            Node::Text(text) => return loose_text.push_str(text),
+        };
+        match self.profile.role(child) {
+            Some(Role::Container) => self.sense_children(child, sense, loose_text),
+            Some(Role::Ignore) => {}
+            Some(Role::SenseNumber) => sense.number = Some(clean(&child.text())),
+            Some(Role::Definition) => {
+                if !sense.definition.is_empty() {
+                    sense.definition.push(' ');
+                }
+                sense.definition.push_str(&clean(&child.text()));
+            }
+            Some(Role::Sense) => sense.subsenses.push(self.sense(child)),
+            Some(Role::Example) => sense.examples.push(self.example(child)),
+            Some(Role::CrossRef) => sense.xrefs.extend(xref(child)),
+            _ => {
+                loose_text.push_str(&child.text());
+                sense.raw.push(child.clone());
+            }
+        }
+    }
+
+    fn example(&self, element: &Element) -> Example {
+        let mut text = String::new();
+        let mut translation: Option<String> = None;
+        self.example_text(element, &mut text, &mut translation);
+        Example {
+            text: clean(&text),
+            translation: translation.map(|t| clean(&t)),
+        }
+    }
+
+    fn example_text(&self, element: &Element, text: &mut String, translation: &mut Option<String>) {
+        for node in &element.children {
+            match node {
+                Node::Text(t) => text.push_str(t),
+                Node::Element(child) if self.profile.role(child) == Some(Role::Translation) => {
+                    let translation = translation.get_or_insert_with(String::new);
+                    if !translation.is_empty() {
+                        translation.push(' ');
+                    }
+                    translation.push_str(&child.text());
+                }
+                Node::Element(child) => self.example_text(child, text, translation),
+            }
+        }
+    }
+
+    fn idiom(&self, element: &Element) -> Idiom {
+        let mut idiom = Idiom::default();
+        // What isn't in an explicit sense makes up an implicit first one.
+        let mut implicit = Sense::default();
+        let mut loose_text = String::new();
+        for node in &element.children {
+            let role = match node {
+                Node::Element(child) => self.profile.role(child).map(|role| (child, role)),
+                Node::Text(_) => None,
+            };
+            match role {
+                Some((child, Role::IdiomPhrase | Role::Headword)) => {
+                    idiom.phrase = clean(&child.text())
+                }
+                Some((child, Role::Sense)) => idiom.senses.push(self.sense(child)),
+                _ => self.sense_node(node, &mut implicit, &mut loose_text),
+            }
+        }
+        if implicit.definition.is_empty() {
+            implicit.definition = clean(&loose_text);
+        }
+        if implicit != Sense::default() {
+            idiom.senses.insert(0, implicit);
+        }
+        idiom
+    }
+}
+
+fn xref(element: &Element) -> Option<CrossRef> {
+    Some(CrossRef {
+        text: clean(&element.text()),
+        target: LinkTarget::parse(element.attr("href")?)?,
+    })
+}
+
+#[test]
+fn test_entry_smk8() {
+    let xml = r#"<entry id="12-0"><head><midashi>あい</midashi><hyouki>愛</hyouki><accent>1</accent><hinshi>名</hinshi><a href="12-0.aac">♪</a></head>
+        <meaning><number>1</number><imi>かわいがる気持ち。</imi><yourei>「親の―」</yourei>
+            <subMeaning><number>㋐</number><ruby>慈<rt>いつく</rt></ruby>しむ心。</subMeaning></meaning>
+        <meaning><number>2</number><imi>恋愛。</imi><ref href="99-1">→恋</ref></meaning>
+        <kanyouku><kanyoukuMidashi>愛は盲目</kanyoukuMidashi>恋は分別を失わせる。</kanyouku>
+        <tail>編集部注</tail></entry>"#;
+    let entry = Entry::parse(xml, &Profile::fixture("SMK8")).unwrap();
+    assert_eq!(entry.id, Some(PageItemId { page: 12, item: 0 }));
+    assert_eq!(entry.headwords, ["あい", "愛"]);
+    assert_eq!(entry.pitch_accents, ["1"]);
+    assert_eq!(entry.parts_of_speech, ["名"]);
+    assert_eq!(entry.senses.len(), 2);
+
+    let first = &entry.senses[0];
+    assert_eq!(first.number.as_deref(), Some("1"));
+    assert_eq!(first.definition, "かわいがる気持ち。");
+    assert_eq!(first.examples[0].text, "「親の―」");
+    let sub = &first.subsenses[0];
+    assert_eq!(sub.number.as_deref(), Some("㋐"));
+    assert_eq!(sub.definition, "慈いつくしむ心。");
+    assert_eq!(sub.raw[0].to_string(), "<ruby>慈<rt>いつく</rt></ruby>");
+    assert_eq!(entry.all_senses().count(), 3);
+
+    let xref = &entry.senses[1].xrefs[0];
+    assert_eq!(xref.text, "→恋");
+    assert_eq!(
+        xref.target,
+        LinkTarget::Item(PageItemId { page: 99, item: 1 })
+    );
+    assert_eq!(entry.idioms[0].phrase, "愛は盲目");
+    assert_eq!(entry.idioms[0].senses[0].definition, "恋は分別を失わせる。");
+    assert_eq!(
+        entry.media,
+        [MediaRef {
+            kind: MediaKind::Audio,
+            href: "12-0.aac".to_owned()
+        }]
+    );
+    assert!(entry.xrefs.is_empty());
+    assert_eq!(entry.raw[0].to_string(), "<tail>編集部注</tail>");
+}
+
+#[test]
+fn test_entry_wisdom3() {
+    let xml = r#"<entry><hw>run</hw><pron>rʌ́n</pron><pos>動</pos>
+        <sense><sn>1</sn><def>走る</def><ex>He <b>ran</b> fast. <tr>彼は速く走った.</tr></ex>
+            <img src="run.png"/></sense>
+        <idiom><idm>run out</idm><sense><def>尽きる</def></sense></idiom></entry>"#;
+    let entry = Entry::parse(xml, &Profile::fixture("WISDOM3")).unwrap();
+    assert_eq!(entry.id, None);
+    assert_eq!(entry.headwords, ["run"]);
+    assert_eq!(entry.reading.as_deref(), Some("rʌ́n"));
+    let sense = &entry.senses[0];
+    assert_eq!(sense.definition, "走る");
+    assert_eq!(
+        sense.examples,
+        [Example {
+            text: "He ran fast.".to_owned(),
+            translation: Some("彼は速く走った.".to_owned()),
+        }]
+    );
+    assert_eq!(sense.raw.len(), 1);
+    assert_eq!(entry.media[0].kind, MediaKind::Graphics);
+    assert_eq!(entry.idioms[0].phrase, "run out");
+    assert_eq!(entry.idioms[0].senses[0].definition, "尽きる");
+
+    // The same markup means nothing to another product.
+    let other = Entry::parse(xml, &Profile::fixture("SMK8")).unwrap();
+    assert!(other.headwords.is_empty());
+    assert_eq!(other.raw.len(), 5);
+}
+
+#[test]
+fn test_custom_profile() {
+    let mut profile = Profile::fixture("DAIJISEN2");
+    profile
+        .rule(".mikado", Role::Headword)
+        .rule("span.x", Role::Ignore);
+    let xml = r#"<div class="entry"><span class="headword">あい</span><span class="mikado">愛</span><span class="x">-</span><div class="meaning"><span class="meaning-no">一</span><span class="gogi">慈しむ</span></div></div>"#;
+    let entry = Entry::parse(xml, &profile).unwrap();
+    assert_eq!(profile.name(), "DAIJISEN2");
+    assert_eq!(entry.headwords, ["あい", "愛"]);
+    assert!(entry.raw.is_empty());
+    assert_eq!(entry.senses[0].number.as_deref(), Some("一"));
+    assert_eq!(entry.senses[0].definition, "慈しむ");
+    assert!(Entry::parse("<a>", &profile).is_err());
+
+    let entry = Entry::parse(xml, &Profile::for_product("DAIJISEN2")).unwrap();
+    assert!(entry.headwords.is_empty());
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+
+use crate::{error::ResultExt, Error, ErrorKind, FileFormat};
+
+/// A node of a parsed XML fragment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Element(Element),
+    /// Text, with the entities and CDATA sections resolved
+    Text(String),
+}
+
+/// An XML element, with its attributes and children in document order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    /// The name as written, including any namespace prefix
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    /// The name without its namespace prefix.
+    pub fn local_name(&self) -> &str {
+        self.name.rsplit(':').next().unwrap_or(&self.name)
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the `class` attribute lists `class`.
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attr("class")
+            .is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// The concatenated text of the element and its descendants.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.push_text(&mut text);
+        text
+    }
+
+    fn push_text(&self, out: &mut String) {
+        for node in &self.children {
+            match node {
+                Node::Element(e) => e.push_text(out),
+                Node::Text(t) => out.push_str(t),
+            }
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Element(e) => e.fmt(f),
+            Node::Text(t) => f.write_str(&escape(t, false)),
+        }
+    }
+}
+
+/// Writes the element back as XML.
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}", self.name)?;
+        for (name, value) in &self.attrs {
+            write!(f, r#" {name}="{}""#, escape(value, true))?;
+        }
+        if self.children.is_empty() {
+            return f.write_str("/>");
+        }
+        f.write_str(">")?;
+        for child in &self.children {
+            child.fmt(f)?;
+        }
+        write!(f, "</{}>", self.name)
+    }
+}
+
+pub(crate) fn escape(text: &str, attr: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attr => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Resolves the predefined entities and character references of `text`.
+/// Unknown entities are left as they are.
+pub(crate) fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest.find(';').map(|semi| (&rest[1..semi], semi));
+        let c = entity.and_then(|(name, _)| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = match name.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => name.strip_prefix('#')?.parse().ok(),
+                };
+                code.and_then(char::from_u32)
+            }
+        });
+        match (c, entity) {
+            (Some(c), Some((_, semi))) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Parses an XML fragment, such as a page or an item, into its top-level nodes.
+pub fn parse_fragment(xml: &str) -> Result<Vec<Node>, Error> {
+    use xmlparser::{ElementEnd, Token};
+
+    let mut top = Vec::new();
+    let mut stack: Vec<Element> = Vec::new();
+    let push = |stack: &mut Vec<Element>, top: &mut Vec<Node>, node| match stack.last_mut() {
+        Some(parent) => parent.children.push(node),
+        None => top.push(node),
+    };
+    for token in xmlparser::Tokenizer::from_fragment(xml, 0..xml.len()) {
+        match token.in_format(FileFormat::PageXml)? {
+            Token::ElementStart { prefix, local, .. } => stack.push(Element {
+                name: qualified_name(&prefix, &local),
+                attrs: Vec::new(),
+                children: Vec::new(),
+            }),
+            Token::Attribute {
+                prefix,
+                local,
+                value,
+                ..
+            } => {
+                if let Some(element) = stack.last_mut() {
+                    let name = qualified_name(&prefix, &local);
+                    element.attrs.push((name, unescape(&value)));
+                }
+            }
+            Token::ElementEnd { end, span } => {
+                let closed = match end {
+                    ElementEnd::Open => continue,
+                    ElementEnd::Empty => true,
+                    ElementEnd::Close(prefix, local) => {
+                        let name = qualified_name(&prefix, &local);
+                        if stack.last().map(|e| &e.name) != Some(&name) {
+                            return Err(Error::new(ErrorKind::XmlError)
+                                .at_offset(span.start() as u64)
+                                .in_format(FileFormat::PageXml));
+                        }
+                        true
+                    }
+                };
+                if closed {
+                    let element = stack.pop().ok_or(ErrorKind::XmlError)?;
+                    push(&mut stack, &mut top, Node::Element(element));
+                }
+            }
+            Token::Text { text } => push(&mut stack, &mut top, Node::Text(unescape(&text))),
+            Token::Cdata { text, .. } => push(&mut stack, &mut top, Node::Text(text.to_string())),
+            _ => {}
+        }
+    }
+    if !stack.is_empty() {
+        return Err(Error::new(ErrorKind::XmlError).in_format(FileFormat::PageXml));
+    }
+    Ok(top)
+}
+
+fn qualified_name(prefix: &str, local: &str) -> String {
+    if prefix.is_empty() {
+        local.to_owned()
+    } else {
+        format!("{prefix}:{local}")
+    }
+}
+
+#[test]
+fn test_parse_fragment() {
+    let xml = r#"<d:entry id="1-0"><h class="a b">見出し &amp; 語</h>text<br/><![CDATA[<x>]]></d:entry> tail"#;
+    let nodes = parse_fragment(xml).unwrap();
+    assert_eq!(nodes.len(), 2);
+    let Node::Element(entry) = &nodes[0] else {
+        panic!("{nodes:?}");
+    };
+    assert_eq!(entry.name, "d:entry");
+    assert_eq!(entry.local_name(), "entry");
+    assert_eq!(entry.attr("id"), Some("1-0"));
+    assert_eq!(entry.elements().count(), 2);
+    let head = entry.elements().next().unwrap();
+    assert!(head.has_class("b"));
+    assert!(!head.has_class("a b"));
+    assert_eq!(entry.text(), "見出し & 語text<x>");
+    assert_eq!(nodes[1], Node::Text(" tail".to_owned()));
+    assert_eq!(
+        entry.to_string(),
+        r#"<d:entry id="1-0"><h class="a b">見出し &amp; 語</h>text<br/>&lt;x&gt;</d:entry>"#
+    );
+
+    for bad in ["<a><b></a></b>", "<a>", "</a>", "<a x=y/>"] {
+        let err = parse_fragment(bad).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::XmlError, "{bad}");
+    }
+}
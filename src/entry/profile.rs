@@ -0,0 +1,169 @@
+use super::dom::Element;
+
+/// What an element of the page XML stands for in an [`Entry`](super::Entry).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// A wrapper whose children are parsed as if they were in its place.
+    Container,
+    /// Markup without content worth keeping, such as layout helpers.
+    Ignore,
+    Headword,
+    Reading,
+    PitchAccent,
+    PartOfSpeech,
+    /// A sense. Senses within senses are sub-senses.
+    Sense,
+    SenseNumber,
+    Definition,
+    Example,
+    /// The translation of an example, within the example.
+    Translation,
+    Idiom,
+    /// The idiom itself, within the idiom.
+    IdiomPhrase,
+    /// A link to another entry, the target being in the `href` attribute.
+    CrossRef,
+}
+
+/// The tag vocabulary of a product, mapping elements to their [`Role`].
+///
+/// Rules are tried in order. A selector is an element name (`sense`), a class (`.sense`),
+/// or both (`span.sense`). Elements matching no rule are kept as raw fragments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    name: String,
+    rules: Vec<(String, Role)>,
+}
+
+// The wrappers that all products share.
+const COMMON: &[(&str, Role)] = &[
+    ("body", Role::Container),
+    ("entry", Role::Container),
+    ("div.entry", Role::Container),
+    ("head", Role::Container),
+    ("br", Role::Ignore),
+    ("a", Role::CrossRef),
+];
+
+// Made-up vocabularies for the tests, named after products so that the product-specific
+// code paths run. They aren't the markup of those products.
+
+#[cfg(test)]
+const SMK8: &[(&str, Role)] = &[
+    ("midashi", Role::Headword),
+    ("hyouki", Role::Headword),
+    ("yomi", Role::Reading),
+    ("accent", Role::PitchAccent),
+    ("hinshi", Role::PartOfSpeech),
+    ("meaning", Role::Sense),
+    ("subMeaning", Role::Sense),
+    ("number", Role::SenseNumber),
+    ("imi", Role::Definition),
+    ("yourei", Role::Example),
+    ("kanyouku", Role::Idiom),
+    ("kanyoukuMidashi", Role::IdiomPhrase),
+    ("ref", Role::CrossRef),
+];
+
+#[cfg(test)]
+const DAIJISEN2: &[(&str, Role)] = &[
+    (".headword", Role::Headword),
+    (".hyouki", Role::Headword),
+    (".kana", Role::Reading),
+    (".accent", Role::PitchAccent),
+    (".hinshi", Role::PartOfSpeech),
+    (".meaning", Role::Sense),
+    (".sub-meaning", Role::Sense),
+    (".meaning-no", Role::SenseNumber),
+    (".gogi", Role::Definition),
+    (".example", Role::Example),
+    (".idiom", Role::Idiom),
+    (".idiom-head", Role::IdiomPhrase),
+    (".ref", Role::CrossRef),
+];
+
+#[cfg(test)]
+const WISDOM3: &[(&str, Role)] = &[
+    ("hw", Role::Headword),
+    ("pron", Role::Reading),
+    ("pos", Role::PartOfSpeech),
+    ("sense", Role::Sense),
+    ("subsense", Role::Sense),
+    ("sn", Role::SenseNumber),
+    ("def", Role::Definition),
+    ("ex", Role::Example),
+    ("tr", Role::Translation),
+    ("idiom", Role::Idiom),
+    ("idm", Role::IdiomPhrase),
+    ("xref", Role::CrossRef),
+];
+
+#[cfg(test)]
+const PRODUCTS: &[(&str, &[(&str, Role)])] =
+    &[("SMK", SMK8), ("DAIJISEN", DAIJISEN2), ("WISDOM", WISDOM3)];
+
+impl Profile {
+    /// A profile without rules, to be filled with [`rule`](Self::rule).
+    pub fn new(name: &str) -> Self {
+        Profile {
+            name: name.to_owned(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// The default profile of the product `product`, with only the wrappers that all
+    /// products share. Product vocabularies are added with [`rule`](Self::rule).
+    pub fn for_product(product: &str) -> Self {
+        let mut profile = Profile::new(product);
+        profile.rules(COMMON);
+        profile
+    }
+
+    /// A test profile named `product`, with one of the made-up vocabularies before the
+    /// common rules, so that it can override them.
+    #[cfg(test)]
+    pub(crate) fn fixture(product: &str) -> Self {
+        let upper = product.to_ascii_uppercase();
+        let (_, rules) = PRODUCTS
+            .iter()
+            .find(|(prefix, _)| upper.starts_with(prefix))
+            .expect("a product with a test vocabulary");
+        let mut profile = Profile::new(product);
+        profile.rules(rules).rules(COMMON);
+        profile
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds a rule, which applies after the rules added before it.
+    pub fn rule(&mut self, selector: &str, role: Role) -> &mut Self {
+        self.rules.push((selector.to_owned(), role));
+        self
+    }
+
+    fn rules(&mut self, rules: &[(&str, Role)]) -> &mut Self {
+        for (selector, role) in rules {
+            self.rule(selector, *role);
+        }
+        self
+    }
+
+    /// The role of `element`, or `None` if it's unknown markup.
+    pub fn role(&self, element: &Element) -> Option<Role> {
+        self.rules
+            .iter()
+            .find(|(selector, _)| matches(selector, element))
+            .map(|(_, role)| *role)
+    }
+}
+
+fn matches(selector: &str, element: &Element) -> bool {
+    let (name, class) = match selector.split_once('.') {
+        Some((name, class)) => (name, Some(class)),
+        None => (selector, None),
+    };
+    (name.is_empty() || element.local_name() == name)
+        && class.is_none_or(|class| element.has_class(class))
+}
@@ -1,15 +1,23 @@
-use std::{fmt::Error as FmtError, io::Error as IoError, str::Utf8Error};
+use std::{
+    fmt::{self, Error as FmtError},
+    io::Error as IoError,
+    path::{Path, PathBuf},
+    str::Utf8Error,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Error {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
     Transmute,
     KeyIndexHeaderValidate,
     KeyFileHeaderValidate,
+    HeadlineHeaderValidate,
+    FullTextHeaderValidate,
     FopenError,
     FstatError,
     MmapError,
     ZlibError,
     Utf8Error,
+    Utf16Error,
     RecordTooLarge,
     IncorrectStreamLength,
     BufferTooSmall,
@@ -27,28 +35,339 @@ pub enum Error {
     XmlError,
     MissingAudio,
     InvalidSubcommand,
+    RegexError,
+}
+
+impl ErrorKind {
+    fn description(self) -> &'static str {
+        use ErrorKind::*;
+        match self {
+            Transmute => "misaligned or truncated record",
+            KeyIndexHeaderValidate => "invalid key index header",
+            KeyFileHeaderValidate => "invalid file header",
+            HeadlineHeaderValidate => "invalid headline file header",
+            FullTextHeaderValidate => "invalid full-text index header",
+            FopenError => "failed to open file",
+            FstatError => "failed to stat file",
+            MmapError => "failed to map file",
+            ZlibError => "zlib decompression failed",
+            Utf8Error => "invalid UTF-8",
+            Utf16Error => "invalid UTF-16",
+            RecordTooLarge => "record too large",
+            IncorrectStreamLength => "unexpected stream length",
+            BufferTooSmall => "buffer too small",
+            IndexMismach => "index mismatch",
+            NotFound => "not found",
+            NoDictJsonFound => "dictionary JSON not found",
+            InvalidDictJson => "invalid dictionary JSON",
+            IOError => "I/O error",
+            MissingResourceFile => "missing resource file",
+            InvalidIndex => "invalid index",
+            InvalidAudioFormat => "invalid audio format",
+            InvalidArg => "invalid argument",
+            FmtError => "formatting error",
+            IndexDoesntExist => "index doesn't exist",
+            XmlError => "invalid XML",
+            MissingAudio => "no audio in dictionary",
+            InvalidSubcommand => "invalid subcommand",
+            RegexError => "invalid regular expression",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// The file format that was being parsed when an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileFormat {
+    DictJson,
+    Keystore,
+    Headlines,
+    RscIdx,
+    RscMap,
+    Rsc,
+    NrscIndex,
+    Nrsc,
+    PageXml,
+    /// The full-text index sidecar written by this crate.
+    FullText,
+    /// A TSV table of gaiji mappings.
+    GaijiTable,
+}
+
+/// Where in the file an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    Offset(u64),
+    Record(usize),
+    /// A line of a text file, counted from 1.
+    Line(usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Offset(offset) => write!(f, "offset {offset:#x}"),
+            Location::Record(idx) => write!(f, "record {idx}"),
+            Location::Line(line) => write!(f, "line {line}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Source {
+    Io(IoError),
+    Utf8(Utf8Error),
+    Xml(xmlparser::Error),
+    Fmt(FmtError),
+    Regex(regex::Error),
+}
+
+#[derive(Debug, Default)]
+struct Context {
+    path: Option<PathBuf>,
+    location: Option<Location>,
+    format: Option<FileFormat>,
+    source: Option<Source>,
+}
+
+/// An error with optional context: the file, the location in the file, the format
+/// being parsed and the underlying error.
+///
+/// Context is added from the inside out, so the `with_*`/`at_*`/`in_*` methods
+/// don't overwrite context that is already present.
+pub struct Error {
+    kind: ErrorKind,
+    ctx: Option<Box<Context>>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Error { kind, ctx: None }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.ctx.as_ref()?.path.as_deref()
+    }
+
+    pub fn location(&self) -> Option<Location> {
+        self.ctx.as_ref()?.location
+    }
+
+    pub fn format(&self) -> Option<FileFormat> {
+        self.ctx.as_ref()?.format
+    }
+
+    fn ctx(&mut self) -> &mut Context {
+        self.ctx.get_or_insert_with(Default::default)
+    }
+
+    fn with_source(mut self, source: Source) -> Self {
+        self.ctx().source.get_or_insert(source);
+        self
+    }
+
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        let ctx = self.ctx();
+        if ctx.path.is_none() {
+            ctx.path = Some(path.as_ref().to_owned());
+        }
+        self
+    }
+
+    pub fn at_offset(mut self, offset: u64) -> Self {
+        self.ctx().location.get_or_insert(Location::Offset(offset));
+        self
+    }
+
+    pub fn at_record(mut self, idx: usize) -> Self {
+        self.ctx().location.get_or_insert(Location::Record(idx));
+        self
+    }
+
+    /// Locates the error on `line`, counted from 1.
+    pub fn at_line(mut self, line: usize) -> Self {
+        self.ctx().location.get_or_insert(Location::Line(line));
+        self
+    }
+
+    pub fn in_format(mut self, format: FileFormat) -> Self {
+        self.ctx().format.get_or_insert(format);
+        self
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("Error");
+        s.field("kind", &self.kind);
+        if let Some(ctx) = &self.ctx {
+            if let Some(path) = &ctx.path {
+                s.field("path", path);
+            }
+            if let Some(location) = &ctx.location {
+                s.field("location", location);
+            }
+            if let Some(format) = &ctx.format {
+                s.field("format", format);
+            }
+            if let Some(source) = &ctx.source {
+                s.field("source", source);
+            }
+        }
+        s.finish()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        let Some(ctx) = &self.ctx else {
+            return Ok(());
+        };
+        if let Some(format) = ctx.format {
+            write!(f, " while parsing {format:?}")?;
+        }
+        if let Some(path) = &ctx.path {
+            write!(f, " in {}", path.display())?;
+        }
+        if let Some(location) = ctx.location {
+            write!(f, " at {location}")?;
+        }
+        match &ctx.source {
+            Some(Source::Io(err)) => write!(f, ": {err}"),
+            Some(Source::Utf8(err)) => write!(f, ": {err}"),
+            Some(Source::Xml(err)) => write!(f, ": {err}"),
+            Some(Source::Fmt(err)) => write!(f, ": {err}"),
+            Some(Source::Regex(err)) => write!(f, ": {err}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.ctx.as_ref()?.source {
+            Some(Source::Io(err)) => Some(err),
+            Some(Source::Utf8(err)) => Some(err),
+            Some(Source::Xml(err)) => Some(err),
+            Some(Source::Fmt(err)) => Some(err),
+            Some(Source::Regex(err)) => Some(err),
+            None => None,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::new(kind)
+    }
 }
 
 impl From<IoError> for Error {
-    fn from(_: IoError) -> Self {
-        Error::IOError
+    fn from(err: IoError) -> Self {
+        Error::new(ErrorKind::IOError).with_source(Source::Io(err))
     }
 }
 
 impl From<Utf8Error> for Error {
-    fn from(_: Utf8Error) -> Self {
-        Error::Utf8Error
+    fn from(err: Utf8Error) -> Self {
+        Error::new(ErrorKind::Utf8Error).with_source(Source::Utf8(err))
     }
 }
 
 impl From<FmtError> for Error {
-    fn from(_: FmtError) -> Self {
-        Error::FmtError
+    fn from(err: FmtError) -> Self {
+        Error::new(ErrorKind::FmtError).with_source(Source::Fmt(err))
     }
 }
 
 impl From<xmlparser::Error> for Error {
-    fn from(_: xmlparser::Error) -> Self {
-        Error::XmlError
+    fn from(err: xmlparser::Error) -> Self {
+        Error::new(ErrorKind::XmlError).with_source(Source::Xml(err))
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(err: regex::Error) -> Self {
+        Error::new(ErrorKind::RegexError).with_source(Source::Regex(err))
     }
 }
+
+impl Error {
+    /// Keeps `kind` but records `err` as the underlying cause.
+    pub(crate) fn io(kind: ErrorKind, err: IoError) -> Self {
+        Error::new(kind).with_source(Source::Io(err))
+    }
+}
+
+/// Adds context to the error of a `Result`.
+pub(crate) trait ResultExt<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, Error>;
+    fn at_offset(self, offset: u64) -> Result<T, Error>;
+    fn at_record(self, idx: usize) -> Result<T, Error>;
+    fn in_format(self, format: FileFormat) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, Error> {
+        self.map_err(|e| e.into().with_path(path))
+    }
+
+    fn at_offset(self, offset: u64) -> Result<T, Error> {
+        self.map_err(|e| e.into().at_offset(offset))
+    }
+
+    fn at_record(self, idx: usize) -> Result<T, Error> {
+        self.map_err(|e| e.into().at_record(idx))
+    }
+
+    fn in_format(self, format: FileFormat) -> Result<T, Error> {
+        self.map_err(|e| e.into().in_format(format))
+    }
+}
+
+#[test]
+fn test_error_context() {
+    use std::error::Error as _;
+    use std::io::ErrorKind as IoErrorKind;
+
+    let err = Error::from(ErrorKind::NotFound);
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert_eq!(err.to_string(), "not found");
+    assert!(err.source().is_none());
+
+    let res: Result<(), _> = Err(IoError::new(IoErrorKind::UnexpectedEof, "eof"));
+    let err = res
+        .at_offset(0x20)
+        .in_format(FileFormat::Keystore)
+        .with_path("key/headword.keystore")
+        .at_record(3)
+        .with_path("elsewhere")
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::IOError);
+    assert_eq!(err.location(), Some(Location::Offset(0x20)));
+    assert_eq!(err.format(), Some(FileFormat::Keystore));
+    assert_eq!(err.path(), Some(Path::new("key/headword.keystore")));
+    assert_eq!(
+        err.to_string(),
+        "I/O error while parsing Keystore in key/headword.keystore at offset 0x20: eof"
+    );
+    let source = err.source().unwrap().downcast_ref::<IoError>().unwrap();
+    assert_eq!(source.kind(), IoErrorKind::UnexpectedEof);
+
+    let err = Error::io(
+        ErrorKind::NoDictJsonFound,
+        IoError::from(IoErrorKind::NotFound),
+    );
+    assert_eq!(err.kind(), ErrorKind::NoDictJsonFound);
+    assert!(err.source().is_some());
+}
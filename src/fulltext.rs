@@ -0,0 +1,538 @@
+//! Full-text search over the text of the pages.
+//!
+//! The text of every page item is tokenized into terms: Latin words, and for runs of
+//! Japanese characters, every pair of adjacent characters plus the last character of
+//! the run. The inverted index maps each term to the items it occurs in and is saved
+//! as a sidecar file next to the product, as building it reads the whole dictionary.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use crate::{
+    entry::unescape,
+    error::ResultExt,
+    pages::{parse_item_id, Pages},
+    Error, ErrorKind, FileFormat, Normalizer, PageItemId,
+};
+
+const MAGIC: &[u8; 4] = b"MKFT";
+const VERSION: u32 = 1;
+
+// BM25 parameters
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+const SNIPPET_BEFORE: usize = 20;
+const SNIPPET_LEN: usize = 80;
+
+/// A search result of [`MonokakidoDict::search_text`](crate::MonokakidoDict::search_text).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextHit {
+    pub id: PageItemId,
+    pub score: f32,
+    /// A part of the item's text around the first match.
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Doc {
+    id: PageItemId,
+    /// Number of terms
+    len: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Term {
+    text: String,
+    /// Number of docs the term occurs in
+    df: u32,
+    /// Range of the encoded postings in `FullTextIndex::postings`
+    offset: usize,
+    len: usize,
+}
+
+/// An inverted index from terms to the page items they occur in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullTextIndex {
+    docs: Vec<Doc>,
+    /// Sorted by `text`
+    terms: Vec<Term>,
+    /// For each term, pairs of (doc index delta, term frequency) as LEB128 varints.
+    postings: Vec<u8>,
+}
+
+impl FullTextIndex {
+    /// Builds an index of the text of every page.
+    pub fn build(pages: &Pages) -> Result<Self, Error> {
+        let mut builder = Builder::default();
+        for idx in pages.idx_iter()? {
+            let (page, xml) = pages.page_by_idx(idx)?;
+            for (item, text) in item_texts(page, &xml).at_record(idx)? {
+                builder.add(PageItemId { page, item }, &text);
+            }
+        }
+        Ok(builder.finish())
+    }
+
+    /// Number of indexed page items.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Returns up to `limit` page items that contain terms of `query`, best first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(PageItemId, f32)> {
+        let avg_len = self.docs.iter().map(|d| d.len as f32).sum::<f32>() / self.len() as f32;
+        let mut terms = tokenize(query, true);
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+        for term in &terms {
+            for t in self.matching_terms(term) {
+                let df = t.df as f32;
+                let idf = (1.0 + (self.len() as f32 - df + 0.5) / (df + 0.5)).ln();
+                for (doc, tf) in self.postings(t) {
+                    let len = self.docs[doc as usize].len as f32;
+                    let tf = tf as f32;
+                    let score = idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * len / avg_len));
+                    *scores.entry(doc).or_default() += score;
+                }
+            }
+        }
+
+        let mut hits: Vec<_> = scores.into_iter().collect();
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        hits.truncate(limit);
+        hits.into_iter()
+            .map(|(doc, score)| (self.docs[doc as usize].id, score))
+            .collect()
+    }
+
+    /// A single Japanese character is indexed only at the end of a run, so it also
+    /// matches the pairs that start with it.
+    fn matching_terms(&self, term: &str) -> &[Term] {
+        let start = self.terms.partition_point(|t| t.text.as_str() < term);
+        let single_cjk = term.chars().count() == 1 && term.chars().all(is_cjk);
+        let end = if single_cjk {
+            start + self.terms[start..].partition_point(|t| t.text.starts_with(term))
+        } else {
+            start + usize::from(self.terms.get(start).is_some_and(|t| t.text == term))
+        };
+        &self.terms[start..end]
+    }
+
+    fn postings(&self, term: &Term) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let mut bytes = &self.postings[term.offset..term.offset + term.len];
+        let mut doc = 0;
+        std::iter::from_fn(move || {
+            if bytes.is_empty() {
+                return None;
+            }
+            // The postings were validated on load.
+            doc += read_varint(&mut bytes).unwrap();
+            let tf = read_varint(&mut bytes).unwrap();
+            Some((doc, tf))
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        write_varint(&mut out, self.docs.len() as u32);
+        for doc in &self.docs {
+            write_varint(&mut out, doc.id.page);
+            write_varint(&mut out, doc.id.item as u32);
+            write_varint(&mut out, doc.len);
+        }
+        write_varint(&mut out, self.terms.len() as u32);
+        for term in &self.terms {
+            write_varint(&mut out, term.text.len() as u32);
+            out.extend_from_slice(term.text.as_bytes());
+            write_varint(&mut out, term.df);
+            write_varint(&mut out, term.len as u32);
+            out.extend_from_slice(&self.postings[term.offset..term.offset + term.len]);
+        }
+        fs::write(path, out).with_path(path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let bytes = fs::read(path).with_path(path)?;
+        Self::from_bytes(&bytes)
+            .in_format(FileFormat::FullText)
+            .with_path(path)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut r = bytes;
+        let header = take(&mut r, 8)?;
+        if &header[..4] != MAGIC || header[4..] != VERSION.to_le_bytes() {
+            return Err(Error::new(ErrorKind::FullTextHeaderValidate).at_offset(0));
+        }
+        let offset = |r: &[u8]| (bytes.len() - r.len()) as u64;
+
+        let n_docs = read_varint(&mut r)?;
+        let mut docs = Vec::with_capacity(n_docs.min(1 << 20) as usize);
+        for _ in 0..n_docs {
+            let page = read_varint(&mut r)?;
+            let item = read_varint(&mut r)?;
+            let item = u8::try_from(item)
+                .map_err(|_| Error::new(ErrorKind::InvalidIndex).at_offset(offset(r)))?;
+            let len = read_varint(&mut r)?;
+            docs.push(Doc {
+                id: PageItemId { page, item },
+                len,
+            });
+        }
+
+        let n_terms = read_varint(&mut r)?;
+        let mut terms: Vec<Term> = Vec::with_capacity(n_terms.min(1 << 20) as usize);
+        let mut postings = Vec::new();
+        for _ in 0..n_terms {
+            let term_offset = offset(r);
+            let len = read_varint(&mut r)? as usize;
+            let text = std::str::from_utf8(take(&mut r, len)?)
+                .at_offset(term_offset)?
+                .to_owned();
+            if terms.last().is_some_and(|t| t.text >= text) {
+                return Err(Error::new(ErrorKind::IndexMismach).at_offset(term_offset));
+            }
+            let df = read_varint(&mut r)?;
+            let len = read_varint(&mut r)? as usize;
+            let encoded = take(&mut r, len)?;
+            let mut check = encoded;
+            let mut doc = 0_u32;
+            for _ in 0..df {
+                doc = doc.saturating_add(read_varint(&mut check)?);
+                read_varint(&mut check)?;
+            }
+            if !check.is_empty() || doc as usize >= docs.len() {
+                return Err(Error::new(ErrorKind::InvalidIndex).at_offset(term_offset));
+            }
+            terms.push(Term {
+                text,
+                df,
+                offset: postings.len(),
+                len,
+            });
+            postings.extend_from_slice(encoded);
+        }
+        Ok(FullTextIndex {
+            docs,
+            terms,
+            postings,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    docs: Vec<Doc>,
+    terms: BTreeMap<String, Vec<(u32, u32)>>,
+}
+
+impl Builder {
+    fn add(&mut self, id: PageItemId, text: &str) {
+        let tokens = tokenize(text, false);
+        if tokens.is_empty() {
+            return;
+        }
+        let doc = self.docs.len() as u32;
+        self.docs.push(Doc {
+            id,
+            len: tokens.len() as u32,
+        });
+        let mut tfs: HashMap<String, u32> = HashMap::new();
+        for token in tokens {
+            *tfs.entry(token).or_default() += 1;
+        }
+        for (term, tf) in tfs {
+            self.terms.entry(term).or_default().push((doc, tf));
+        }
+    }
+
+    fn finish(self) -> FullTextIndex {
+        let mut terms = Vec::with_capacity(self.terms.len());
+        let mut postings = Vec::new();
+        for (text, docs) in self.terms {
+            let offset = postings.len();
+            let mut prev = 0;
+            for &(doc, tf) in &docs {
+                write_varint(&mut postings, doc - prev);
+                write_varint(&mut postings, tf);
+                prev = doc;
+            }
+            terms.push(Term {
+                text,
+                df: docs.len() as u32,
+                offset,
+                len: postings.len() - offset,
+            });
+        }
+        FullTextIndex {
+            docs: self.docs,
+            terms,
+            postings,
+        }
+    }
+}
+
+fn take<'a>(r: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if r.len() < len {
+        return Err(ErrorKind::IncorrectStreamLength.into());
+    }
+    let (head, tail) = r.split_at(len);
+    *r = tail;
+    Ok(head)
+}
+
+fn read_varint(r: &mut &[u8]) -> Result<u32, Error> {
+    let mut value = 0_u32;
+    for shift in (0..35).step_by(7) {
+        let byte = take(r, 1)?[0];
+        value |= ((byte & 0x7f) as u32)
+            .checked_shl(shift)
+            .ok_or(ErrorKind::InvalidIndex)?;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(ErrorKind::InvalidIndex.into())
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3005}' // 々
+        | '\u{3041}'..='\u{30FF}' // kana
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2FFFF}')
+}
+
+// Unlike headword searches, which match the stored forms, full-text searches ignore case.
+fn normalizer() -> Normalizer {
+    Normalizer::new().case(true).clone()
+}
+
+/// Splits normalized text into Latin words, and pairs of characters plus the last
+/// character of every run of Japanese characters. In a `query`, the last character
+/// is left out of runs that have pairs, as it would also match the pairs starting with it.
+fn tokenize(text: &str, query: bool) -> Vec<String> {
+    let text = normalizer().normalize(text);
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut run: Vec<char> = Vec::new();
+    let flush_run = |run: &mut Vec<char>, tokens: &mut Vec<String>| {
+        for pair in run.windows(2) {
+            tokens.push(pair.iter().collect());
+        }
+        if let Some(last) = run.last().filter(|_| !query || run.len() == 1) {
+            tokens.push(last.to_string());
+        }
+        run.clear();
+    };
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            run.push(c);
+        } else {
+            flush_run(&mut run, &mut tokens);
+            if c.is_alphanumeric() {
+                word.push(c);
+            } else if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+        }
+    }
+    flush_run(&mut run, &mut tokens);
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+/// Returns the text of each item on a page. Text outside of items is item 0.
+pub(crate) fn item_texts(page: u32, xml: &str) -> Result<Vec<(u8, String)>, Error> {
+    use xmlparser::{ElementEnd, Token};
+
+    let mut texts: BTreeMap<u8, String> = BTreeMap::new();
+    // The item that each open element belongs to
+    let mut stack: Vec<u8> = Vec::new();
+    for token in xmlparser::Tokenizer::from(xml) {
+        match token.in_format(FileFormat::PageXml)? {
+            Token::ElementStart { .. } => stack.push(stack.last().copied().unwrap_or(0)),
+            Token::Attribute { local, value, .. } if local.as_str() == "id" => {
+                if let Some(id) = parse_item_id(&value).filter(|id| id.page == page) {
+                    if let Some(item) = stack.last_mut() {
+                        *item = id.item;
+                    }
+                }
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(..) | ElementEnd::Empty,
+                ..
+            } => {
+                stack.pop();
+            }
+            Token::Text { text } | Token::Cdata { text, .. } => {
+                let item = stack.last().copied().unwrap_or(0);
+                texts.entry(item).or_default().push_str(&unescape(&text));
+            }
+            _ => {}
+        }
+    }
+    Ok(texts
+        .into_iter()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .collect())
+}
+
+/// Returns about `SNIPPET_LEN` characters of `text` around the first occurrence
+/// of one of the terms of `query`.
+pub(crate) fn snippet(text: &str, query: &str) -> String {
+    let normalizer = normalizer();
+    let chars: Vec<char> = text.chars().collect();
+    // Normalize char by char, to map the matches back to `text`.
+    let mut normalized = String::new();
+    let mut origin = Vec::new();
+    for (i, c) in chars.iter().enumerate() {
+        for n in normalizer.normalize(c.encode_utf8(&mut [0; 4])).chars() {
+            normalized.push(n);
+            origin.push(i);
+        }
+    }
+    let first_match = tokenize(query, true)
+        .iter()
+        .filter_map(|term| normalized.find(term.as_str()))
+        .min()
+        .map(|byte_pos| origin[normalized[..byte_pos].chars().count()])
+        .unwrap_or(0);
+
+    let start = first_match.saturating_sub(SNIPPET_BEFORE);
+    let end = (start + SNIPPET_LEN).min(chars.len());
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    for c in &chars[start..end] {
+        if c.is_whitespace() {
+            if !snippet.ends_with(' ') {
+                snippet.push(' ');
+            }
+        } else {
+            snippet.push(*c);
+        }
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[test]
+fn test_tokenize() {
+    assert_eq!(
+        tokenize("アイスクリーム is COLD, isn't it?", false),
+        [
+            "アイ", "イス", "スク", "クリ", "リー", "ーム", "ム", "is", "cold", "isn", "t", "it"
+        ]
+    );
+    assert_eq!(tokenize("冷たい", false), ["冷タ", "タイ", "イ"]);
+    assert_eq!(tokenize("字", false), ["字"]);
+    assert_eq!(tokenize("ＡＢＣ１２３ｱｲ", false), ["abc123", "アイ", "イ"]);
+    assert!(tokenize(" 、。", false).is_empty());
+    assert_eq!(tokenize("冷たい 字", true), ["冷タ", "タイ", "字"]);
+}
+
+#[test]
+fn test_item_texts() {
+    let xml = r#"<d:entry><head>見出し &amp; 語</head><div id="5-1">アイス<![CDATA[ & ]]>cream</div><div id="5-2"><b>冷</b>たい<br/>物</div><a id="6-3">他</a></d:entry>"#;
+    assert_eq!(
+        item_texts(5, xml).unwrap(),
+        [
+            (0, "見出し & 語他".to_owned()),
+            (1, "アイス & cream".to_owned()),
+            (2, "冷たい物".to_owned()),
+        ]
+    );
+    assert_eq!(
+        item_texts(5, "<a>&#x41;&#66;&bogus; &</a>").unwrap(),
+        [(0, "AB&bogus; &".to_owned())]
+    );
+    assert!(item_texts(5, "<a x=y>").is_err());
+}
+
+#[test]
+fn test_snippet() {
+    let text = "ＡＩＳＵ  is\n an ice cream. ".repeat(10) + "つめたい アイス";
+    let s = snippet(&text, "あいす");
+    assert!(s.starts_with('…'));
+    assert!(s.ends_with("つめたい アイス"));
+    assert_eq!(snippet("short text", "text"), "short text");
+    assert_eq!(snippet("no match here", "xyz"), "no match here");
+    assert_eq!(snippet("ｱｲｽ", "あいす"), "ｱｲｽ");
+}
+
+#[test]
+fn test_index() {
+    let id = |page, item| PageItemId { page, item };
+    let mut builder = Builder::default();
+    builder.add(id(1, 0), "アイスクリームは冷たいお菓子。");
+    builder.add(id(1, 1), "アイスクリームを食べる。アイスクリームが好き。");
+    builder.add(id(2, 0), "ice cream is cold");
+    builder.add(id(3, 0), "冷");
+    builder.add(id(4, 0), "、。");
+    let index = builder.finish();
+    assert_eq!(index.len(), 4);
+
+    let hits = |query, limit| {
+        index
+            .search(query, limit)
+            .into_iter()
+            .map(|(id, _)| (id.page, id.item))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(hits("あいすくりーむ", 10), [(1, 1), (1, 0)]);
+    assert_eq!(hits("あいすくりーむ", 1), [(1, 1)]);
+    assert_eq!(hits("Cream", 10), [(2, 0)]);
+    assert_eq!(hits("冷", 10), [(3, 0), (1, 0)]);
+    assert_eq!(hits("冷たい", 10), [(1, 0)]);
+    assert!(hits("ラーメン", 10).is_empty());
+    assert!(hits("", 10).is_empty());
+
+    let tmp = tempfile::NamedTempFile::new().unwrap();
+    index.save(tmp.path()).unwrap();
+    assert_eq!(FullTextIndex::load(tmp.path()).unwrap(), index);
+
+    let bytes = fs::read(tmp.path()).unwrap();
+    for len in [0, 4, 9, bytes.len() / 2, bytes.len() - 1] {
+        let err = FullTextIndex::from_bytes(&bytes[..len]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::IncorrectStreamLength, "{len}");
+    }
+    let mut bad = bytes.clone();
+    bad[0] = b'X';
+    let err = FullTextIndex::from_bytes(&bad).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::FullTextHeaderValidate);
+    fs::write(tmp.path(), &bad).unwrap();
+    let err = FullTextIndex::load(tmp.path()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::FullTextHeaderValidate);
+    assert_eq!(err.format(), Some(FileFormat::FullText));
+}
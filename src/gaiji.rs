@@ -0,0 +1,263 @@
+//! Gaiji: glyphs outside of Unicode, which the page XML of older products references as
+//! small images or as private-use characters, and their mapping to Unicode.
+
+use std::{borrow::Cow, collections::HashMap, fs, path::Path};
+
+use crate::{
+    entry::{Element, MediaRef},
+    Error, ErrorKind, FileFormat, MediaKind,
+};
+
+/// A reference to a gaiji in the page XML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GaijiRef {
+    /// A private-use character
+    Codepoint(char),
+    /// An image of the glyph
+    Image(MediaRef),
+    /// A glyph known only by name, such as `<gaiji name="g1"/>`
+    Name(String),
+}
+
+impl GaijiRef {
+    /// Recognizes `<gaiji>` elements, elements of class `gaiji`, and images in a `gaiji`
+    /// directory. Only references to image files are taken as images; the others are
+    /// names.
+    pub fn from_element(element: &Element) -> Option<Self> {
+        let src = ["src", "href", "name"]
+            .into_iter()
+            .find_map(|name| element.attr(name))?;
+        let marked = element.local_name() == "gaiji" || element.has_class("gaiji");
+        let in_dir = src
+            .rsplit('/')
+            .nth(1)
+            .is_some_and(|dir| dir.eq_ignore_ascii_case("gaiji"));
+        if !marked && !in_dir {
+            return None;
+        }
+        Some(match MediaRef::from_href(src) {
+            Some(media) if media.kind == MediaKind::Graphics => GaijiRef::Image(media),
+            _ => GaijiRef::Name(src.to_owned()),
+        })
+    }
+
+    /// The key of the glyph in a [`GaijiTable`]: `U+E000` for codepoints, the file stem
+    /// for images, and the last path segment for names.
+    pub fn key(&self) -> String {
+        match self {
+            GaijiRef::Codepoint(c) => format!("U+{:04X}", *c as u32),
+            GaijiRef::Image(media) => {
+                let file = media.href.rsplit('/').next().unwrap_or(&media.href);
+                file.rsplit_once('.')
+                    .map_or(file, |(stem, _)| stem)
+                    .to_owned()
+            }
+            GaijiRef::Name(name) => name.rsplit('/').next().unwrap_or(name).to_owned(),
+        }
+    }
+}
+
+/// Whether `c` is in one of the private use areas, where products put their gaiji.
+pub fn is_private_use(c: char) -> bool {
+    matches!(c as u32, 0xE000..=0xF8FF | 0xF0000..=0xFFFFD | 0x100000..=0x10FFFD)
+}
+
+/// How a gaiji is displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glyph<'a> {
+    /// The Unicode text the gaiji maps to
+    Text(&'a str),
+    /// No mapping: the image of the glyph, in the graphics resource
+    Image(&'a MediaRef),
+    /// No mapping nor image: the private-use character as written
+    Unknown(char),
+    /// No mapping, image nor character: a glyph known only by name
+    Missing,
+}
+
+/// Mappings of gaiji to Unicode text, by [`GaijiRef::key`].
+///
+/// Tables are read from TSV: each line is a key and its replacement separated by a tab.
+/// Empty lines and lines starting with `#` are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GaijiTable {
+    map: HashMap<String, String>,
+}
+
+impl GaijiTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps `key` to `text`, replacing any previous mapping.
+    pub fn insert(&mut self, key: &str, text: &str) -> &mut Self {
+        self.map.insert(normalize_key(key), text.to_owned());
+        self
+    }
+
+    /// Adds the mappings of another table, which take precedence.
+    pub fn merge(&mut self, other: &GaijiTable) -> &mut Self {
+        self.map
+            .extend(other.map.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self
+    }
+
+    /// Adds the mappings of a TSV table, which take precedence.
+    pub fn load_tsv(&mut self, tsv: &str) -> Result<&mut Self, Error> {
+        for (line_no, line) in tsv.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, text) = line
+                .split_once('\t')
+                .filter(|(key, text)| !key.is_empty() && !text.is_empty())
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidArg)
+                        .at_line(line_no + 1)
+                        .in_format(FileFormat::GaijiTable)
+                })?;
+            self.insert(key, text);
+        }
+        Ok(self)
+    }
+
+    /// Adds the mappings of a TSV file, which take precedence.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, Error> {
+        let path = path.as_ref();
+        let tsv = fs::read_to_string(path)
+            .map_err(|e| Error::io(ErrorKind::IOError, e).with_path(path))?;
+        self.load_tsv(&tsv).map_err(|e| e.with_path(path))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.map.get(&normalize_key(key)).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// How to display `gaiji`: its mapping, or else its image or the character itself, if
+    /// it has one.
+    pub fn glyph<'a>(&'a self, gaiji: &'a GaijiRef) -> Glyph<'a> {
+        if let Some(text) = self.map.get(&gaiji.key()) {
+            return Glyph::Text(text);
+        }
+        match gaiji {
+            GaijiRef::Codepoint(c) => Glyph::Unknown(*c),
+            GaijiRef::Image(media) => Glyph::Image(media),
+            GaijiRef::Name(_) => Glyph::Missing,
+        }
+    }
+
+    /// Replaces the mapped private-use characters of `text`, leaving the others as they are.
+    pub fn replace_text<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        if self.map.is_empty() || !text.chars().any(is_private_use) {
+            return Cow::Borrowed(text);
+        }
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            match self.glyph(&GaijiRef::Codepoint(c)) {
+                Glyph::Text(mapped) if is_private_use(c) => out.push_str(mapped),
+                _ => out.push(c),
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+// Codepoint keys are compared regardless of case, image names as written.
+fn normalize_key(key: &str) -> String {
+    match key.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("U+") => key.to_ascii_uppercase(),
+        _ => key.to_owned(),
+    }
+}
+
+#[test]
+fn test_gaiji_ref() {
+    use crate::entry::{parse_fragment, Node};
+
+    let xml = r#"<gaiji name="g0123"/><img class="gaiji" src="a/b/k1.svg"/><img src="gaiji/k2.png"/><img src="photo.png"/><span class="gaiji">x</span><span class="gaiji" href="gaiji/k3"/>"#;
+    let refs: Vec<Option<GaijiRef>> = parse_fragment(xml)
+        .unwrap()
+        .iter()
+        .map(|node| match node {
+            Node::Element(e) => GaijiRef::from_element(e),
+            Node::Text(_) => None,
+        })
+        .collect();
+    let image = |kind, href: &str| {
+        Some(GaijiRef::Image(MediaRef {
+            kind,
+            href: href.to_owned(),
+        }))
+    };
+    assert_eq!(
+        refs,
+        [
+            Some(GaijiRef::Name("g0123".to_owned())),
+            image(MediaKind::Graphics, "a/b/k1.svg"),
+            image(MediaKind::Graphics, "gaiji/k2.png"),
+            None,
+            None,
+            Some(GaijiRef::Name("gaiji/k3".to_owned())),
+        ]
+    );
+    let keys: Vec<String> = refs.iter().flatten().map(GaijiRef::key).collect();
+    assert_eq!(keys, ["g0123", "k1", "k2", "k3"]);
+    assert_eq!(GaijiRef::Codepoint('\u{e00a}').key(), "U+E00A");
+    assert!(is_private_use('\u{e00a}'));
+    assert!(!is_private_use('愛'));
+}
+
+#[test]
+fn test_gaiji_table() {
+    let mut table = GaijiTable::new();
+    table.insert("U+E000", "𠀋");
+    assert_eq!(table.get("U+E000"), Some("𠀋"));
+    assert_eq!(table.get("u+e000"), Some("𠀋"));
+    assert!(GaijiTable::new().is_empty());
+
+    let mut user = GaijiTable::new();
+    user.load_tsv("# user\r\n\r\nu+e000\t吉\r\nk1\tkanji\r\n")
+        .unwrap();
+    table.merge(&user);
+    assert_eq!(table.get("U+E000"), Some("吉"));
+
+    let k1 = GaijiRef::Image(MediaRef::from_href("gaiji/k1.png").unwrap());
+    let k2 = GaijiRef::Image(MediaRef::from_href("gaiji/k2.png").unwrap());
+    assert_eq!(table.glyph(&k1), Glyph::Text("kanji"));
+    assert!(matches!(table.glyph(&k2), Glyph::Image(media) if media.href == "gaiji/k2.png"));
+    assert_eq!(
+        table.glyph(&GaijiRef::Codepoint('\u{f000}')),
+        Glyph::Unknown('\u{f000}')
+    );
+    assert_eq!(
+        table.glyph(&GaijiRef::Name("g2".to_owned())),
+        Glyph::Missing
+    );
+    assert_eq!(table.replace_text("a\u{e000}b\u{f000}"), "a吉b\u{f000}");
+    assert!(matches!(table.replace_text("愛"), Cow::Borrowed(_)));
+
+    let err = GaijiTable::new().load_tsv("k1\tx\nk2\n").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidArg);
+    assert_eq!(err.location(), Some(crate::Location::Line(2)));
+    assert!(err.to_string().contains("line 2"), "{err}");
+    assert_eq!(err.format(), Some(FileFormat::GaijiTable));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gaiji.tsv");
+    fs::write(&path, "k2\tkana\n").unwrap();
+    table.load_file(&path).unwrap();
+    assert_eq!(table.glyph(&k2), Glyph::Text("kana"));
+    let err = GaijiTable::new()
+        .load_file(dir.path().join("missing.tsv"))
+        .unwrap_err();
+    assert_eq!(err.path(), Some(dir.path().join("missing.tsv").as_path()));
+}
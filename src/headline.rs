@@ -1,12 +1,11 @@
-use std::{
-    fs::File,
-    io::{Read, Seek},
-};
+use std::{mem::size_of, path::Path};
 
 use crate::{
-    abi_utils::{TransmuteSafe, LE32, read_vec},
-    dict::Paths,
-    Error, PageItemId,
+    abi_utils::{TransmuteSafe, LE32},
+    diagnostics::{self, Event, IndexKind},
+    error::ResultExt,
+    storage::{Buf, IndexFile},
+    Error, ErrorKind, FileFormat, OpenOptions, PageItemId,
 };
 
 mod abi {
@@ -26,6 +25,18 @@ mod abi {
     }
 
     impl FileHeader {
+        #[cfg(test)]
+        pub(super) fn new(len: u32, rec_offset: u32, words_offset: u32) -> Self {
+            FileHeader {
+                magic2: 0x2.into(),
+                len: len.into(),
+                rec_offset: rec_offset.into(),
+                words_offset: words_offset.into(),
+                rec_bytes: 0x18.into(),
+                ..Default::default()
+            }
+        }
+
         pub(super) fn validate(&self) -> Result<(), Error> {
             if self.magic1.read() == 0
                 && self.magic2.read() == 0x2
@@ -35,7 +46,7 @@ mod abi {
             {
                 Ok(())
             } else {
-                Err(Error::KeyFileHeaderValidate)
+                Err(ErrorKind::HeadlineHeaderValidate.into())
             }
         }
     }
@@ -59,35 +70,203 @@ mod abi {
 use abi::{FileHeader, Offset};
 
 pub struct Headlines {
-    recs: Vec<Offset>,
-    words: Vec<u8>,
+    recs: Buf<Offset>,
+    words: Buf<u8>,
 }
 
 impl Headlines {
-    pub fn new(paths: &Paths) -> Result<Headlines, Error> {
-        let mut file = File::open(paths.headline_long_path())?;
-        let file_size = file.metadata()?.len() as usize;
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Headlines, Error> {
+        Self::new_with_options(path, &OpenOptions::new())
+    }
+
+    pub fn new_with_options<P: AsRef<Path>>(
+        path: P,
+        options: &OpenOptions,
+    ) -> Result<Headlines, Error> {
+        let file = IndexFile::open(path.as_ref(), options.storage)?;
+        let headlines = Self::from_file(file)
+            .in_format(FileFormat::Headlines)
+            .with_path(path.as_ref())?;
+        diagnostics::emit(Event::IndexLoaded {
+            kind: IndexKind::Headlines,
+            path: path.as_ref(),
+            entries: headlines.len(),
+            storage: options.storage,
+        });
+        Ok(headlines)
+    }
+
+    fn from_file(mut file: IndexFile) -> Result<Headlines, Error> {
         let mut hdr = FileHeader::default();
-        file.read_exact(hdr.as_bytes_mut())?;
+        file.read_exact_at(0, hdr.as_bytes_mut())?;
         hdr.validate()?;
+        let rec_end = hdr.rec_offset.us() + hdr.len.us() * size_of::<Offset>();
+        // The record area may be padded; the header tells how many records are actually in use.
+        if rec_end > hdr.words_offset.us() || hdr.words_offset.us() > file.len() {
+            return Err(ErrorKind::HeadlineHeaderValidate.into());
+        }
 
-        file.seek(std::io::SeekFrom::Start(hdr.words_offset.read() as u64))?;
-        let offsets: Option<Vec<Offset>> = read_vec(&mut file, hdr.rec_offset.us(), hdr.words_offset.us())?;
-        let Some(recs) = offsets else { return Err(Error::InvalidIndex); };
+        let recs = file.buf(hdr.rec_offset.us()..rec_end)?;
+        let words = file.buf(hdr.words_offset.us()..file.len())?;
 
-        let words: Option<Vec<u8>> = read_vec(&mut file, hdr.words_offset.us(), file_size)?;
-        let Some(words) = words else { return Err(Error::InvalidIndex); };
+        Ok(Headlines { recs, words })
+    }
+
+    pub fn len(&self) -> usize {
+        self.recs.len()
+    }
 
-        Ok(Headlines {
-            recs,
-            words,
-        })
+    pub fn is_empty(&self) -> bool {
+        self.recs.is_empty()
     }
 
+    /// Returns the headline of a page (`id.item == 0`) or of an item within a page.
     pub fn get(&self, id: PageItemId) -> Result<String, Error> {
-        let rec = self.recs.binary_search_by(|rec|
-            rec.page_id.read().cmp(&id.page).then(rec.item_id.cmp(&id.item))
-        ).map_err(|_| Error::InvalidIndex)?;
-        todo!();
+        let idx = self
+            .recs
+            .binary_search_by(|rec| {
+                rec.page_id
+                    .read()
+                    .cmp(&id.page)
+                    .then(rec.item_id.cmp(&id.item))
+            })
+            .map_err(|_| ErrorKind::NotFound)?;
+        self.get_word(self.recs[idx].offset.us())
+    }
+
+    // The words blob consists of null-terminated UTF-16LE strings.
+    fn get_word(&self, offset: usize) -> Result<String, Error> {
+        let bytes = self
+            .words
+            .get(offset..)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidIndex).at_offset(offset as u64))?;
+        let units = bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&u| u != 0);
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map_err(|_| Error::new(ErrorKind::Utf16Error).at_offset(offset as u64))
+    }
+}
+
+#[cfg(test)]
+fn headlinestore(entries: &[(u32, u8, &str)]) -> Vec<u8> {
+    let rec_offset = size_of::<FileHeader>();
+    let words_offset = rec_offset + entries.len() * size_of::<Offset>();
+    let mut recs = Vec::new();
+    let mut words = Vec::new();
+    for &(page, item, headline) in entries {
+        let mut rec = Offset::default();
+        rec.page_id = page.into();
+        rec.item_id = item;
+        rec.offset = (words.len() as u32).into();
+        recs.push(rec);
+        for unit in headline.encode_utf16().chain([0]) {
+            words.extend_from_slice(&unit.to_le_bytes());
+        }
+    }
+    let hdr = FileHeader::new(entries.len() as u32, rec_offset as u32, words_offset as u32);
+    let mut buf = hdr.as_bytes().to_vec();
+    buf.extend_from_slice(Offset::slice_as_bytes(&recs));
+    buf.extend_from_slice(&words);
+    buf
+}
+
+#[cfg(test)]
+fn headlines(entries: &[(u32, u8, &str)]) -> Headlines {
+    Headlines::from_file(IndexFile::from_bytes(headlinestore(entries))).unwrap()
+}
+
+#[cfg(test)]
+fn id(page: u32, item: u8) -> PageItemId {
+    PageItemId { page, item }
+}
+
+#[test]
+fn test_get_page_and_item() {
+    let hl = headlines(&[
+        (1, 0, "あい【愛】"),
+        (1, 1, "愛する"),
+        (1, 2, "愛らしい"),
+        (7, 0, "apple"),
+        (300, 0, "𠮷野家"),
+    ]);
+    assert_eq!(hl.len(), 5);
+    assert_eq!(hl.get(id(1, 0)).unwrap(), "あい【愛】");
+    assert_eq!(hl.get(id(1, 1)).unwrap(), "愛する");
+    assert_eq!(hl.get(id(1, 2)).unwrap(), "愛らしい");
+    assert_eq!(hl.get(id(7, 0)).unwrap(), "apple");
+    assert_eq!(hl.get(id(300, 0)).unwrap(), "𠮷野家");
+}
+
+#[test]
+fn test_get_missing() {
+    let hl = headlines(&[(1, 0, "a"), (2, 1, "b")]);
+    assert_eq!(
+        hl.get(id(0, 0)).map_err(|e| e.kind()),
+        Err(ErrorKind::NotFound)
+    );
+    assert_eq!(
+        hl.get(id(1, 1)).map_err(|e| e.kind()),
+        Err(ErrorKind::NotFound)
+    );
+    assert_eq!(
+        hl.get(id(2, 0)).map_err(|e| e.kind()),
+        Err(ErrorKind::NotFound)
+    );
+    assert_eq!(
+        hl.get(id(3, 0)).map_err(|e| e.kind()),
+        Err(ErrorKind::NotFound)
+    );
+
+    let empty = headlines(&[]);
+    assert!(empty.is_empty());
+    assert_eq!(
+        empty.get(id(1, 0)).map_err(|e| e.kind()),
+        Err(ErrorKind::NotFound)
+    );
+}
+
+#[test]
+fn test_invalid_header() {
+    let mut buf = headlinestore(&[(1, 0, "a")]);
+    buf[4] = 0x3;
+    assert_eq!(
+        Headlines::from_file(IndexFile::from_bytes(buf.clone()))
+            .err()
+            .map(|e| e.kind()),
+        Some(ErrorKind::HeadlineHeaderValidate)
+    );
+
+    let mut tmp = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut tmp, &buf).unwrap();
+    let err = Headlines::new(tmp.path()).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::HeadlineHeaderValidate);
+    assert_eq!(err.format(), Some(FileFormat::Headlines));
+    assert_eq!(err.path(), Some(tmp.path()));
+}
+
+#[test]
+fn test_mmap_storage() {
+    use crate::IndexStorage;
+    use std::io::Write;
+
+    let mut tmp = tempfile::NamedTempFile::new().unwrap();
+    tmp.write_all(&headlinestore(&[
+        (1, 0, "一"),
+        (2, 0, "二"),
+        (2, 3, "二の三"),
+    ]))
+    .unwrap();
+    for storage in [IndexStorage::Owned, IndexStorage::Mmap] {
+        let hl =
+            Headlines::new_with_options(tmp.path(), OpenOptions::new().storage(storage)).unwrap();
+        assert_eq!(hl.get(id(1, 0)).unwrap(), "一");
+        assert_eq!(hl.get(id(2, 3)).unwrap(), "二の三");
+        assert_eq!(
+            hl.get(id(2, 1)).map_err(|e| e.kind()),
+            Err(ErrorKind::NotFound)
+        );
     }
 }
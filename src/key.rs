@@ -1,18 +1,23 @@
-use std::{
-    borrow::Cow,
-    cmp::Ordering,
-    fs::File,
-    io::{Read, Seek},
-    mem::size_of,
-    path::Path,
-    str::from_utf8,
-};
+use std::{cmp::Ordering, mem::size_of, path::Path, str::from_utf8, sync::OnceLock};
 
 use crate::{
-    abi_utils::{read_vec, TransmuteSafe, LE32},
-    Error,
+    abi_utils::{TransmuteSafe, LE32},
+    deinflect::{deinflect, lemmatize, Deinflection},
+    diagnostics::{self, Event, IndexKind},
+    error::ResultExt,
+    storage::{Buf, IndexFile},
+    Error, ErrorKind, FileFormat, OpenOptions,
 };
 
+mod fuzzy;
+mod normalize;
+mod pattern;
+mod romaji;
+use fuzzy::BkTree;
+pub use normalize::Normalizer;
+use pattern::Pattern;
+use regex::Regex;
+
 mod abi {
     use super::*;
 
@@ -32,31 +37,29 @@ mod abi {
     }
 
     impl FileHeader {
-        pub(super) fn from(r: &mut impl Read) -> Result<Self, Error> {
+        pub(super) fn from(file: &mut IndexFile) -> Result<Self, Error> {
             let mut h = FileHeader::default();
-            r.read_exact(&mut h.as_bytes_mut()[..0x10])?;
+            file.read_exact_at(0, &mut h.as_bytes_mut()[..0x10])?;
             if h.ver.read() == 0x10000 && h.words_offset.read() == 0x10 {
             } else if h.ver.read() == 0x20000 && h.words_offset.read() == 0x20 {
-                r.read_exact(&mut h.as_bytes_mut()[0x10..])?;
+                file.read_exact_at(0x10, &mut h.as_bytes_mut()[0x10..])?;
             } else {
-                return Err(Error::KeyFileHeaderValidate);
+                return Err(ErrorKind::KeyFileHeaderValidate.into());
             }
-            if h.ver.read() == 0x10000
+            let v1_ok = h.ver.read() == 0x10000
                 && h.magic1.read() == 0
-                && h.words_offset.us() < h.idx_offset.us()
-            {
-                Ok(h)
-            } else if h.ver.read() == 0x20000
+                && h.words_offset.us() < h.idx_offset.us();
+            let v2_ok = h.ver.read() == 0x20000
                 && h.magic1.read() == 0
                 && h.magic5.read() == 0
                 && h.magic6.read() == 0
                 && h.magic7.read() == 0
                 && h.words_offset.us() < h.idx_offset.us()
-                && (h.next_offset.read() == 0 || h.idx_offset.us() < h.next_offset.us())
-            {
+                && (h.next_offset.read() == 0 || h.idx_offset.us() < h.next_offset.us());
+            if v1_ok || v2_ok {
                 Ok(h)
             } else {
-                Err(Error::KeyFileHeaderValidate)
+                Err(ErrorKind::KeyFileHeaderValidate.into())
             }
         }
     }
@@ -88,7 +91,7 @@ mod abi {
             {
                 Ok(())
             } else {
-                Err(Error::KeyIndexHeaderValidate)
+                Err(ErrorKind::KeyIndexHeaderValidate.into())
             }
         }
     }
@@ -97,166 +100,169 @@ mod abi {
 }
 use abi::{FileHeader, IndexHeader};
 
+/// A list of the headwords of a keystore, in some order.
+///
+/// Only the order of `index_prefix` is relied on, as exact lookups always have. The
+/// orders of the other indexes haven't been verified against real products, so a search
+/// checks the whole index against the order it expects the first time, and scans the
+/// index linearly if that order doesn't hold.
 #[derive(Debug)]
 pub struct KeyIndex {
-    index: Option<Vec<LE32>>,
+    index: Option<Buf<LE32>>,
+    /// Whether the index is in the order the searches expect, once checked.
+    sorted: OnceLock<bool>,
 }
 
 pub struct Keys {
-    words: Vec<LE32>,
+    words: Buf<LE32>,
+    /// Expected to be sorted by the length of the words in characters, then like
+    /// `index_prefix`.
     pub index_len: KeyIndex,
+    /// Sorted by the UTF-8 bytes of the words.
     pub index_prefix: KeyIndex,
+    /// Expected to be sorted by the UTF-8 bytes of the words with their characters
+    /// reversed.
     pub index_suffix: KeyIndex,
+    /// Expected to be sorted by the first page of each word, i.e. in the order of the
+    /// entries.
     pub index_d: KeyIndex,
+    normalizer: Normalizer,
+    /// Built from the words of `index_prefix` on the first fuzzy search.
+    fuzzy: OnceLock<BkTree>,
 }
 
 impl KeyIndex {
+    fn new(index: Option<Buf<LE32>>) -> Self {
+        KeyIndex {
+            index,
+            sorted: OnceLock::new(),
+        }
+    }
+
     fn get(&self, i: usize) -> Result<usize, Error> {
         let Some(index) = &self.index else {
-            return Err(Error::IndexDoesntExist);
+            return Err(ErrorKind::IndexDoesntExist.into());
         };
         let i = i + 1; // Because the the index is prefixed by its legth
         if i >= index.len() {
-            return Err(Error::InvalidIndex);
+            return Err(Error::new(ErrorKind::InvalidIndex).at_record(i));
         }
         Ok(index[i].us())
     }
 
     pub fn len(&self) -> usize {
-        self.index.as_ref().map(|v| v.len()).unwrap_or(0) - 1
+        self.index.as_ref().map(|v| v.len() - 1).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
 impl Keys {
-    fn check_vec_len(buf: &Option<Vec<LE32>>) -> Result<(), Error> {
+    fn check_vec_len(buf: &Option<Buf<LE32>>) -> Result<(), Error> {
         let Some(buf) = buf else { return Ok(()) };
-        if buf.get(0).ok_or(Error::InvalidIndex)?.us() + 1 != buf.len() {
-            return Err(Error::InvalidIndex);
+        if buf.first().ok_or(ErrorKind::InvalidIndex)?.us() + 1 != buf.len() {
+            return Err(ErrorKind::InvalidIndex.into());
         }
         Ok(())
     }
 
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Keys, Error> {
-        let mut file = File::open(path)?;
-        let file_size = file.metadata()?.len() as usize;
+        Self::new_with_options(path, &OpenOptions::new())
+    }
+
+    pub fn new_with_options<P: AsRef<Path>>(path: P, options: &OpenOptions) -> Result<Keys, Error> {
+        let file = IndexFile::open(path.as_ref(), options.storage)?;
+        let mut keys = Self::from_file(file)
+            .in_format(FileFormat::Keystore)
+            .with_path(path.as_ref())?;
+        keys.normalizer = options.normalizer.clone();
+        diagnostics::emit(Event::IndexLoaded {
+            kind: IndexKind::Keystore,
+            path: path.as_ref(),
+            entries: keys.index_prefix.len(),
+            storage: options.storage,
+        });
+        Ok(keys)
+    }
+
+    fn from_file(mut file: IndexFile) -> Result<Keys, Error> {
+        let file_size = file.len();
         let hdr = FileHeader::from(&mut file)?;
 
-        file.seek(std::io::SeekFrom::Start(hdr.words_offset.read() as u64))?;
-        let words = read_vec(&mut file, hdr.words_offset.us(), hdr.idx_offset.us())?;
-        let Some(words) = words else {
-            return Err(Error::InvalidIndex);
-        };
+        let words = file.buf(hdr.words_offset.us()..hdr.idx_offset.us())?;
 
+        let idx_offset = hdr.idx_offset.us();
         let idx_end = (if hdr.next_offset.us() == 0 {
             file_size
         } else {
             hdr.next_offset.us()
-        }) - hdr.idx_offset.us();
+        }) - idx_offset;
         let mut ihdr = IndexHeader::default();
-        file.seek(std::io::SeekFrom::Start(hdr.idx_offset.read() as u64))?;
-        file.read_exact(ihdr.as_bytes_mut())?;
+        file.read_exact_at(idx_offset, ihdr.as_bytes_mut())?;
         ihdr.validate(idx_end)?;
 
-        let index_a = read_vec(
-            &mut file,
-            ihdr.index_a_offset.us(),
-            ihdr.index_b_offset.us(),
-        )?;
-        Self::check_vec_len(&index_a)?;
-
-        let index_b = read_vec(
-            &mut file,
-            ihdr.index_b_offset.us(),
-            ihdr.index_c_offset.us(),
-        )?;
-        Self::check_vec_len(&index_b)?;
-
-        let index_c = read_vec(
-            &mut file,
-            ihdr.index_c_offset.us(),
-            ihdr.index_d_offset.us(),
-        )?;
-        Self::check_vec_len(&index_c)?;
-
-        let index_d = read_vec(&mut file, ihdr.index_d_offset.us(), idx_end)?;
-        Self::check_vec_len(&index_d)?;
+        // The index offsets are relative to the start of the index header; zero means absent.
+        let mut read_index = |start: usize, end: usize| -> Result<_, Error> {
+            if start == 0 || end == 0 {
+                return Ok(None);
+            }
+            let index = Some(file.buf(idx_offset + start..idx_offset + end)?);
+            Self::check_vec_len(&index)?;
+            Ok(index)
+        };
+        let index_a = read_index(ihdr.index_a_offset.us(), ihdr.index_b_offset.us())?;
+        let index_b = read_index(ihdr.index_b_offset.us(), ihdr.index_c_offset.us())?;
+        let index_c = read_index(ihdr.index_c_offset.us(), ihdr.index_d_offset.us())?;
+        let index_d = read_index(ihdr.index_d_offset.us(), idx_end)?;
 
         let keys = Keys {
             words,
-            index_len: KeyIndex { index: index_a },
-            index_prefix: KeyIndex { index: index_b },
-            index_suffix: KeyIndex { index: index_c },
-            index_d: KeyIndex { index: index_d },
+            index_len: KeyIndex::new(index_a),
+            index_prefix: KeyIndex::new(index_b),
+            index_suffix: KeyIndex::new(index_c),
+            index_d: KeyIndex::new(index_d),
+            normalizer: Normalizer::default(),
+            fuzzy: OnceLock::new(),
         };
 
-        // DEBUG: Print sample words from different parts of the dictionary
-        println!("DEBUG: Sample words from dictionary:");
-        let total_words = keys.index_prefix.len();
-
-        // Show first 5 words
-        println!("  First 5 words:");
-        for i in 0..std::cmp::min(5, total_words) {
-            if let Ok((word, _)) = keys.get_idx(&keys.index_prefix, i) {
-                println!("    {}: '{}'", i, word);
-            }
-        }
-
-        // Show words around position 15000 (might catch 'h' words)
-        println!("  Words around position 15000:");
-        for i in 14995..std::cmp::min(15005, total_words) {
-            if let Ok((word, _)) = keys.get_idx(&keys.index_prefix, i) {
-                println!("    {}: '{}'", i, word);
-            }
-        }
-
-        // Show words around position 25000
-        println!("  Words around position 25000:");
-        for i in 24995..std::cmp::min(25005, total_words) {
-            if let Ok((word, _)) = keys.get_idx(&keys.index_prefix, i) {
-                println!("    {}: '{}'", i, word);
-            }
-        }
+        Ok(keys)
+    }
 
-        println!("DEBUG: Total words in dictionary: {}", total_words);
+    /// The normalizer applied to the queries of the `search_*` methods.
+    pub fn normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
 
-        Ok(keys)
+    pub fn set_normalizer(&mut self, normalizer: Normalizer) {
+        self.normalizer = normalizer;
     }
 
-    fn get_page_iter(&self, pages_offset: usize) -> Result<PageIter, Error> {
+    fn get_page_iter(&self, pages_offset: usize) -> Result<PageIter<'_>, Error> {
         let pages = &LE32::slice_as_bytes(&self.words)[pages_offset..];
         PageIter::new(pages)
+            .inspect_err(|_| diagnostics::emit(Event::MalformedPageList { pages_offset }))
+            .at_offset(pages_offset as u64)
     }
 
     pub(crate) fn get_word_span(&self, offset: usize) -> Result<(&str, usize), Error> {
         let words_bytes = LE32::slice_as_bytes(&self.words);
         // TODO: add comment. What is this guarding against?
         if words_bytes.len() < offset + 2 * size_of::<LE32>() {
-            return Err(Error::InvalidIndex);
+            return Err(Error::new(ErrorKind::InvalidIndex).at_offset(offset as u64));
         }
         let (pages_offset, word_bytes) = LE32::from(&words_bytes[offset..])?;
         if let Some(word) = word_bytes[1..].split(|b| *b == b'\0').next() {
-            Ok((from_utf8(word)?, pages_offset.us()))
+            Ok((from_utf8(word).at_offset(offset as u64)?, pages_offset.us()))
         } else {
-            Err(Error::InvalidIndex)
+            Err(Error::new(ErrorKind::InvalidIndex).at_offset(offset as u64))
         }
     }
 
-    pub(crate) fn cmp_key(&self, target: &str, idx: usize) -> Result<Ordering, Error> {
-        // Get the actual word string
-        let (word, _) = self.get_idx(&self.index_prefix, idx)?;
-
-        // DEBUG: Show what we're actually comparing
-        println!("DEBUG: Comparing target '{}' with word '{}'", target, word);
-
-        // Use byte comparison instead of lexicographic comparison
-        let result = target.as_bytes().cmp(word.as_bytes());
-        println!("DEBUG: Comparison result: {:?}", result);
-        Ok(result)
-    }
-
     pub fn get_idx(&self, index: &KeyIndex, idx: usize) -> Result<(&str, PageIter<'_>), Error> {
         if idx >= index.len() {
-            return Err(Error::NotFound);
+            return Err(ErrorKind::NotFound.into());
         }
         // TODO: Why is this indexing ok?
         let word_offset = index.get(idx)?;
@@ -265,86 +271,389 @@ impl Keys {
         Ok((word, pages))
     }
 
-    pub fn search_exact(&self, target_key: &str) -> Result<(usize, PageIter<'_>), Error> {
-        let target_key = &to_katakana(target_key);
-        println!("DEBUG: Searching for: '{}'", target_key);
-
-        let mut high = self.index_prefix.len().saturating_sub(1); // Prevent underflow
+    /// Returns the position of the first entry in `index` for which `cmp` doesn't return
+    /// `Less`. `cmp` compares an entry to the target and must be monotonic over the index.
+    fn lower_bound(
+        &self,
+        index: &KeyIndex,
+        target: &str,
+        cmp: impl Fn(&str, PageIter<'_>) -> Ordering,
+    ) -> Result<usize, Error> {
+        if index.index.is_none() {
+            return Err(ErrorKind::IndexDoesntExist.into());
+        }
         let mut low = 0;
-
-        // TODO: Revise corner cases and add tests for this binary search
-        while low <= high {
+        let mut high = index.len();
+        while low < high {
             let mid = low + (high - low) / 2;
-            println!(
-                "DEBUG: Binary search - low: {}, high: {}, mid: {}",
-                low, high, mid
-            );
-
-            let cmp = self.cmp_key(target_key, mid)?;
-            println!("DEBUG: Comparison result: {:?}", cmp);
-
-            // Let's also see what word we're comparing against
-            if let Ok((word, _)) = self.get_idx(&self.index_prefix, mid) {
-                println!("DEBUG: Word at position {}: '{}'", mid, word);
+            let (word, pages) = self.get_idx(index, mid)?;
+            let ordering = cmp(word, pages);
+            diagnostics::emit(Event::SearchStep {
+                target,
+                low,
+                high,
+                mid,
+                word,
+                ordering,
+            });
+            match ordering {
+                Ordering::Less => low = mid + 1,
+                Ordering::Equal | Ordering::Greater => high = mid,
             }
+        }
+        Ok(low)
+    }
 
-            match cmp {
-                Ordering::Less => {
-                    if mid == 0 {
-                        break; // Can't go lower
-                    }
-                    high = mid - 1;
-                }
-                Ordering::Greater => low = mid + 1,
-                Ordering::Equal => {
-                    println!("DEBUG: Found exact match at position {}", mid);
-                    return Ok((mid, self.get_idx(&self.index_prefix, mid)?.1));
+    /// Whether the entries of `index` are in the order of `order`, which compares two
+    /// entries. Checked on the first call, as the orders other than that of `index_prefix`
+    /// aren't known for sure.
+    fn is_sorted_by(
+        &self,
+        index: &KeyIndex,
+        order: impl Fn((&str, PageIter<'_>), (&str, PageIter<'_>)) -> Ordering,
+    ) -> Result<bool, Error> {
+        if index.index.is_none() {
+            return Err(ErrorKind::IndexDoesntExist.into());
+        }
+        if let Some(sorted) = index.sorted.get() {
+            return Ok(*sorted);
+        }
+        let mut sorted = true;
+        let mut prev: Option<(&str, PageIter<'_>)> = None;
+        for i in 0..index.len() {
+            let entry = self.get_idx(index, i)?;
+            if let Some(prev) = prev.take() {
+                if order(prev, entry.clone()) == Ordering::Greater {
+                    sorted = false;
+                    break;
                 }
             }
+            prev = Some(entry);
+        }
+        // Another thread may have checked meanwhile, with the same result.
+        let _ = index.sorted.set(sorted);
+        Ok(sorted)
+    }
+
+    /// Returns the entries of `index` for which `matches` returns true: from the result of
+    /// `lower_bound` if `index` is in the order of `order`, or else by scanning it all.
+    fn ordered_matches<'a>(
+        &'a self,
+        index: &'a KeyIndex,
+        target: &str,
+        order: impl Fn((&str, PageIter<'_>), (&str, PageIter<'_>)) -> Ordering,
+        cmp: impl Fn(&str, PageIter<'_>) -> Ordering,
+        limit: Option<usize>,
+        matches: impl Fn(&str, PageIter<'_>) -> bool + Send + Sync + 'static,
+    ) -> Result<Matches<'a>, Error> {
+        if self.is_sorted_by(index, order)? {
+            let start = self.lower_bound(index, target, cmp)?;
+            Ok(Matches::new(self, index, start, limit, matches))
+        } else {
+            Ok(Matches::new(self, index, 0, limit, |_, _| true).filter(matches))
+        }
+    }
 
-            // Additional safety check to prevent infinite loops
-            if low > high {
-                break;
+    pub fn search_exact(&self, target_key: &str) -> Result<(usize, PageIter<'_>), Error> {
+        let target_key = &self.normalizer.normalize(target_key);
+
+        let idx = self.lower_bound(&self.index_prefix, target_key, |word, _| {
+            word.as_bytes().cmp(target_key.as_bytes())
+        })?;
+        let found = if idx < self.index_prefix.len() {
+            let (word, pages) = self.get_idx(&self.index_prefix, idx)?;
+            (word == target_key).then_some(pages)
+        } else {
+            None
+        };
+
+        diagnostics::emit(Event::SearchFinished {
+            target: target_key,
+            found: found.as_ref().map(|_| idx),
+        });
+        found
+            .map(|pages| (idx, pages))
+            .ok_or(ErrorKind::NotFound.into())
+    }
+
+    /// Returns the headwords that start with `prefix`, in the order of the prefix index.
+    /// At most `limit` headwords are returned, if given.
+    pub fn search_prefix(&self, prefix: &str, limit: Option<usize>) -> Result<Matches<'_>, Error> {
+        self.prefix_matches(self.normalizer.normalize(prefix), limit)
+    }
+
+    fn prefix_matches(&self, prefix: String, limit: Option<usize>) -> Result<Matches<'_>, Error> {
+        let start = self.lower_bound(&self.index_prefix, &prefix, |word, _| {
+            word.as_bytes().cmp(prefix.as_bytes())
+        })?;
+        Ok(Matches::new(
+            self,
+            &self.index_prefix,
+            start,
+            limit,
+            move |word, _| word.starts_with(&prefix),
+        ))
+    }
+
+    /// Returns the headwords that end with `suffix`, in the order of the suffix index.
+    /// At most `limit` headwords are returned, if given.
+    pub fn search_suffix(&self, suffix: &str, limit: Option<usize>) -> Result<Matches<'_>, Error> {
+        self.suffix_matches(self.normalizer.normalize(suffix), limit)
+    }
+
+    fn suffix_matches(&self, suffix: String, limit: Option<usize>) -> Result<Matches<'_>, Error> {
+        // Comparing chars is equivalent to comparing their UTF-8 bytes.
+        let target = suffix.clone();
+        self.ordered_matches(
+            &self.index_suffix,
+            &target,
+            |(a, _), (b, _)| a.chars().rev().cmp(b.chars().rev()),
+            |word, _| word.chars().rev().cmp(target.chars().rev()),
+            limit,
+            move |word, _| word.ends_with(&suffix),
+        )
+    }
+
+    /// Returns the headwords that are `len` characters long and start with `prefix`,
+    /// in the order of the length index.
+    /// At most `limit` headwords are returned, if given.
+    pub fn search_len(
+        &self,
+        len: usize,
+        prefix: &str,
+        limit: Option<usize>,
+    ) -> Result<Matches<'_>, Error> {
+        self.len_matches(len, self.normalizer.normalize(prefix), limit)
+    }
+
+    fn len_matches(
+        &self,
+        len: usize,
+        prefix: String,
+        limit: Option<usize>,
+    ) -> Result<Matches<'_>, Error> {
+        let target = prefix.clone();
+        self.ordered_matches(
+            &self.index_len,
+            &target,
+            |(a, _), (b, _)| {
+                (a.chars().count(), a.as_bytes()).cmp(&(b.chars().count(), b.as_bytes()))
+            },
+            |word, _| (word.chars().count(), word.as_bytes()).cmp(&(len, target.as_bytes())),
+            limit,
+            move |word, _| word.chars().count() == len && word.starts_with(&prefix),
+        )
+    }
+
+    /// Returns the headwords whose first page item is on `page`, in the order of `index_d`.
+    /// Headwords that refer to `page` only in later items aren't returned.
+    pub fn search_page(&self, page: u32) -> Result<Matches<'_>, Error> {
+        let first_page = |mut pages: PageIter<'_>| pages.next().map(|id| id.page);
+        self.ordered_matches(
+            &self.index_d,
+            &page.to_string(),
+            move |(_, a), (_, b)| first_page(a).cmp(&first_page(b)),
+            move |_, pages| first_page(pages).cmp(&Some(page)),
+            None,
+            move |_, pages| first_page(pages) == Some(page),
+        )
+    }
+
+    /// Returns the headwords that match the glob-style `pattern`, where `?` matches
+    /// one character and `*` any number of them. At most `limit` headwords are
+    /// returned, if given.
+    ///
+    /// Only the candidates that share the pattern's literal prefix or suffix, or its
+    /// length if it contains no `*`, are matched against the full pattern.
+    pub fn search_pattern(
+        &self,
+        pattern: &str,
+        limit: Option<usize>,
+    ) -> Result<Matches<'_>, Error> {
+        let pattern = Pattern::new(&self.normalizer.normalize(pattern));
+        let prefix = pattern.prefix();
+        let suffix = pattern.suffix();
+        let candidates = if let Some(len) = pattern.fixed_len() {
+            self.len_matches(len, prefix, limit)?
+        } else if prefix.is_empty() && suffix.is_empty() {
+            Matches::new(self, &self.index_prefix, 0, limit, |_, _| true)
+        } else if prefix.len() >= suffix.len() {
+            self.prefix_matches(prefix, limit)?
+        } else {
+            self.suffix_matches(suffix, limit)?
+        };
+        if !pattern.has_wildcards() {
+            return Ok(candidates);
+        }
+        Ok(candidates.filter(move |word, _| pattern.is_match(word)))
+    }
+
+    /// Returns the headwords that the regular expression `pattern` matches, in the order
+    /// of the prefix index. At most `limit` headwords are returned, if given.
+    ///
+    /// The pattern is matched against the headwords as stored, without normalization,
+    /// and can match anywhere in them. If it is anchored with `^`, only the headwords
+    /// that start with its literal prefix are scanned.
+    /// [`Matches::progress`] reports how far a long scan has got.
+    pub fn search_regex(&self, pattern: &str, limit: Option<usize>) -> Result<Matches<'_>, Error> {
+        let regex = Regex::new(pattern)?;
+        let candidates = match pattern::regex_prefix(pattern) {
+            Some(prefix) => self.prefix_matches(prefix, limit)?,
+            None => Matches::new(self, &self.index_prefix, 0, limit, |_, _| true),
+        };
+        Ok(candidates.filter(move |word, _| regex.is_match(word)))
+    }
+
+    /// Looks up `query` and every lemma it may be an inflection of, such as 食べる for
+    /// 食べなかった. Returns the candidates that are headwords with the inflections that
+    /// were undone, shortest chain first.
+    pub fn search_deinflected(
+        &self,
+        query: &str,
+    ) -> Result<Vec<(Deinflection, usize, PageIter<'_>)>, Error> {
+        self.search_candidates(deinflect(query))
+    }
+
+    /// Like [`search_deinflected`](Self::search_deinflected), for English: looks up
+    /// `query` and every lemma it may be an inflection of, such as goose for geese.
+    pub fn search_lemmatized(
+        &self,
+        query: &str,
+    ) -> Result<Vec<(Deinflection, usize, PageIter<'_>)>, Error> {
+        self.search_candidates(lemmatize(query))
+    }
+
+    fn search_candidates(
+        &self,
+        candidates: Vec<Deinflection>,
+    ) -> Result<Vec<(Deinflection, usize, PageIter<'_>)>, Error> {
+        let mut found = Vec::new();
+        for candidate in candidates {
+            match self.search_exact(&candidate.word) {
+                Ok((idx, pages)) => found.push((candidate, idx, pages)),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
             }
         }
+        Ok(found)
+    }
 
-        println!("DEBUG: Search failed - word not found");
-        Err(Error::NotFound)
+    /// Returns the headwords within `max_distance` edits of `query`, closest first.
+    /// Edits are counted in characters.
+    ///
+    /// The first call builds a search tree over all headwords, which takes a while
+    /// on large dictionaries; later calls reuse it.
+    pub fn search_fuzzy(
+        &self,
+        query: &str,
+        max_distance: usize,
+    ) -> Result<Vec<(&str, PageIter<'_>, usize)>, Error> {
+        let query = self.normalizer.normalize(query);
+        let tree = self.fuzzy_tree()?;
+        let mut found = Vec::new();
+        for (idx, distance) in tree.find(&query, max_distance) {
+            let (word, pages) = self.get_idx(&self.index_prefix, idx as usize)?;
+            found.push((word, pages, distance));
+        }
+        found.sort_by(|a, b| (a.2, a.0).cmp(&(b.2, b.0)));
+        Ok(found)
+    }
+
+    fn fuzzy_tree(&self) -> Result<&BkTree, Error> {
+        if let Some(tree) = self.fuzzy.get() {
+            return Ok(tree);
+        }
+        let words = (0..self.index_prefix.len())
+            .map(|i| Ok(self.get_word_span(self.index_prefix.get(i)?)?.0))
+            .collect::<Result<Vec<_>, Error>>()?;
+        // Another thread may have built it meanwhile; either tree will do.
+        let _ = self.fuzzy.set(BkTree::new(words));
+        Ok(self.fuzzy.get().unwrap())
     }
 }
 
-fn to_katakana(input: &str) -> Cow<str> {
-    let diff = 'ア' as u32 - 'あ' as u32;
-    if let Some(pos) = input.find(|c| matches!(c, 'ぁ'..='ん')) {
-        let mut output = input[..pos].to_owned();
-        for c in input[pos..].chars() {
-            if matches!(c, 'ぁ'..='ん') {
-                output.push(char::from_u32(c as u32 + diff).unwrap());
-            } else {
-                output.push(c);
-            }
+type MatchFn = dyn Fn(&str, PageIter<'_>) -> bool + Send + Sync;
+
+/// Iterates over consecutive entries of a key index that match a query.
+/// Created by the `search_*` methods of [`Keys`].
+pub struct Matches<'a> {
+    keys: &'a Keys,
+    index: &'a KeyIndex,
+    pos: usize,
+    remaining: Option<usize>,
+    /// The iteration ends at the first entry this returns false for.
+    matches: Box<MatchFn>,
+    /// Entries this returns false for are skipped.
+    filter: Option<Box<MatchFn>>,
+}
+
+impl<'a> Matches<'a> {
+    fn new(
+        keys: &'a Keys,
+        index: &'a KeyIndex,
+        pos: usize,
+        remaining: Option<usize>,
+        matches: impl Fn(&str, PageIter<'_>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Matches {
+            keys,
+            index,
+            pos,
+            remaining,
+            matches: Box::new(matches),
+            filter: None,
         }
-        return Cow::Owned(output);
-    } else {
-        return Cow::Borrowed(input);
+    }
+
+    /// Returns the number of entries of the underlying key index that have been
+    /// looked at, and the total number of entries. Once the iteration has finished,
+    /// both are equal, even if not every entry needed to be looked at.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.pos, self.index.len())
+    }
+
+    fn filter(
+        mut self,
+        filter: impl Fn(&str, PageIter<'_>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
     }
 }
 
-#[test]
-fn test_to_katakana() {
-    assert_eq!(*to_katakana(""), *"");
-    assert_eq!(*to_katakana("あ"), *"ア");
-    assert_eq!(*to_katakana("ぁ"), *"ァ");
-    assert_eq!(*to_katakana("ん"), *"ン");
-    assert_eq!(*to_katakana("っ"), *"ッ");
-    assert_eq!(*to_katakana("ア"), *"ア");
-    assert_eq!(*to_katakana("ァ"), *"ァ");
-    assert_eq!(*to_katakana("ン"), *"ン");
-    assert_eq!(*to_katakana("ッ"), *"ッ");
-    assert_eq!(*to_katakana("aアa"), *"aアa");
-    assert_eq!(*to_katakana("aァa"), *"aァa");
-    assert_eq!(*to_katakana("aンa"), *"aンa");
-    assert_eq!(*to_katakana("aッa"), *"aッa");
+impl<'a> Iterator for Matches<'a> {
+    type Item = Result<(&'a str, PageIter<'a>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.remaining != Some(0) && self.pos < self.index.len() {
+            let res = self.keys.get_idx(self.index, self.pos);
+            self.pos += 1;
+            match res {
+                Ok((word, ref pages)) if !(self.matches)(word, pages.clone()) => break,
+                Ok((word, ref pages))
+                    if self
+                        .filter
+                        .as_ref()
+                        .is_some_and(|f| !f(word, pages.clone())) =>
+                {
+                    continue
+                }
+                Ok(ok) => {
+                    if let Some(remaining) = &mut self.remaining {
+                        *remaining -= 1;
+                    }
+                    return Some(Ok(ok));
+                }
+                Err(e) => {
+                    // Stop after an error instead of skipping the broken entry.
+                    self.pos = self.index.len();
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.pos = self.index.len();
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -361,16 +670,13 @@ impl<'a> PageIter<'a> {
         // CHECK INVARIANT B: loop through `count` times and check that the shape is of expected
         let mut tail = pages;
         for _ in 0..count {
-            match tail {
-                &[1, _, ref t @ ..] => tail = t,
-                &[2, _, _, ref t @ ..] => tail = t,
-                &[4, _, _, _, ref t @ ..] => tail = t,
-                &[17, _, _, ref t @ ..] => tail = t,
-                &[18, _, _, _, ref t @ ..] => tail = t,
-                e => {
-                    dbg!("hmm", &e[..100]); // TODO: clean this up
-                    return Err(Error::InvalidIndex);
-                }
+            match *tail {
+                [1, _, ref t @ ..] => tail = t,
+                [2, _, _, ref t @ ..] => tail = t,
+                [4, _, _, _, ref t @ ..] => tail = t,
+                [17, _, _, ref t @ ..] => tail = t,
+                [18, _, _, _, ref t @ ..] => tail = t,
+                _ => return Err(ErrorKind::InvalidIndex.into()),
             }
         }
         let span_len = pages.len() - tail.len();
@@ -403,7 +709,7 @@ impl<'a> Iterator for PageIter<'a> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PageItemId {
     pub page: u32,
     pub item: u8,
@@ -415,3 +721,419 @@ fn pid([hi, mid, lo]: [u8; 3], item: u8) -> PageItemId {
         item,
     }
 }
+
+/// Builds a version 1 keystore with the given headwords and their (page, item) lists.
+#[cfg(test)]
+pub(crate) fn build_keystore(entries: &[(&str, &[(u32, u8)])]) -> Vec<u8> {
+    build_keystore_with(entries, true)
+}
+
+/// Builds a keystore whose indexes other than `index_prefix` list the headwords in the
+/// order they are given, or else in the order the searches expect.
+#[cfg(test)]
+fn build_keystore_with(entries: &[(&str, &[(u32, u8)])], sort: bool) -> Vec<u8> {
+    let mut words = Vec::new();
+    let mut offsets = Vec::new();
+    for (word, pages) in entries {
+        let offset = words.len();
+        offsets.push((*word, pages, offset as u32));
+        let pages_offset = offset + 4 + 1 + word.len() + 1;
+        words.extend_from_slice(&(pages_offset as u32).to_le_bytes());
+        words.push(0);
+        words.extend_from_slice(word.as_bytes());
+        words.push(0);
+        words.extend_from_slice(&(pages.len() as u16).to_le_bytes());
+        for &(page, item) in pages.iter() {
+            let [_, hi, mid, lo] = page.to_be_bytes();
+            match (page, item) {
+                (0..=0xff, 0) => words.extend_from_slice(&[1, lo]),
+                (0..=0xffff, 0) => words.extend_from_slice(&[2, mid, lo]),
+                (_, 0) => words.extend_from_slice(&[4, hi, mid, lo]),
+                (0..=0xff, _) => words.extend_from_slice(&[17, lo, item]),
+                _ => words.extend_from_slice(&[18, mid, lo, item]),
+            }
+        }
+    }
+    words.resize(words.len().next_multiple_of(4), 0);
+
+    let reversed = |w: &str| w.chars().rev().collect::<String>();
+    let mut by_prefix = offsets.clone();
+    by_prefix.sort_by(|a, b| a.0.cmp(b.0));
+    let mut by_len = offsets.clone();
+    let mut by_suffix = offsets.clone();
+    let mut by_page = offsets;
+    if sort {
+        by_len.sort_by(|a, b| (a.0.chars().count(), a.0).cmp(&(b.0.chars().count(), b.0)));
+        by_suffix.sort_by_key(|a| reversed(a.0));
+        by_page.sort_by_key(|a| (a.1.first().copied(), a.0));
+    }
+
+    let mut idx = vec![0_u8; 20];
+    let mut index_offsets = Vec::new();
+    for index in [by_len, by_prefix, by_suffix, by_page] {
+        index_offsets.push(idx.len() as u32);
+        idx.extend_from_slice(&(index.len() as u32).to_le_bytes());
+        for (_, _, offset) in index {
+            idx.extend_from_slice(&offset.to_le_bytes());
+        }
+    }
+    idx[..4].copy_from_slice(&4_u32.to_le_bytes());
+    for (i, offset) in index_offsets.iter().enumerate() {
+        idx[4 + i * 4..8 + i * 4].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    let mut file = Vec::new();
+    for field in [0x10000, 0, 0x10, 0x10 + words.len() as u32] {
+        file.extend_from_slice(&field.to_le_bytes());
+    }
+    file.extend_from_slice(&words);
+    file.extend_from_slice(&idx);
+    file
+}
+
+#[cfg(test)]
+fn test_keys() -> Keys {
+    Keys::from_file(IndexFile::from_bytes(build_keystore(&[
+        ("アイ", &[(1, 0)]),
+        ("アイス", &[(2, 0)]),
+        ("アイスクリーム", &[(3, 1), (3, 2)]),
+        ("アオ", &[(4, 0)]),
+        ("イ", &[(300, 0)]),
+        ("カ", &[(70000, 0)]),
+    ])))
+    .unwrap()
+}
+
+#[cfg(test)]
+fn words<'a>(
+    matches: impl Iterator<Item = Result<(&'a str, PageIter<'a>), Error>>,
+) -> Vec<&'a str> {
+    matches.map(|m| m.unwrap().0).collect()
+}
+
+#[test]
+fn test_search_exact() {
+    let keys = test_keys();
+    let (idx, pages) = keys.search_exact("あいす").unwrap();
+    assert_eq!(idx, 1);
+    assert_eq!(pages.map(|p| p.page).collect::<Vec<_>>(), [2]);
+    let (idx, pages) = keys.search_exact("カ").unwrap();
+    assert_eq!(idx, 5);
+    assert_eq!(pages.map(|p| p.page).collect::<Vec<_>>(), [70000]);
+    for missing in ["", "アイスク", "ア", "ン"] {
+        let err = keys.search_exact(missing).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+}
+
+#[test]
+fn test_search_prefix() {
+    let keys = test_keys();
+    assert_eq!(
+        words(keys.search_prefix("あい", None).unwrap()),
+        ["アイ", "アイス", "アイスクリーム"]
+    );
+    assert_eq!(
+        words(keys.search_prefix("ア", Some(2)).unwrap()),
+        ["アイ", "アイス"]
+    );
+    assert_eq!(words(keys.search_prefix("ア", None).unwrap()).len(), 4);
+    assert_eq!(words(keys.search_prefix("", None).unwrap()).len(), 6);
+    assert!(words(keys.search_prefix("ウ", None).unwrap()).is_empty());
+    assert!(words(keys.search_prefix("キ", None).unwrap()).is_empty());
+    assert!(words(keys.search_prefix("ア", Some(0)).unwrap()).is_empty());
+
+    let (word, pages) = keys
+        .search_prefix("アイスク", None)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(word, "アイスクリーム");
+    assert_eq!(
+        pages.map(|p| (p.page, p.item)).collect::<Vec<_>>(),
+        [(3, 1), (3, 2)]
+    );
+}
+
+#[test]
+fn test_search_suffix() {
+    let keys = test_keys();
+    assert_eq!(
+        words(keys.search_suffix("い", None).unwrap()),
+        ["イ", "アイ"]
+    );
+    assert_eq!(words(keys.search_suffix("イス", None).unwrap()), ["アイス"]);
+    assert_eq!(
+        words(keys.search_suffix("リーム", None).unwrap()),
+        ["アイスクリーム"]
+    );
+    assert_eq!(words(keys.search_suffix("イ", Some(1)).unwrap()), ["イ"]);
+    assert_eq!(
+        words(keys.search_suffix("", None).unwrap()),
+        ["イ", "アイ", "アオ", "カ", "アイス", "アイスクリーム"]
+    );
+    assert!(words(keys.search_suffix("ア", None).unwrap()).is_empty());
+    assert!(words(keys.search_suffix("ン", None).unwrap()).is_empty());
+}
+
+#[test]
+fn test_search_len() {
+    let keys = test_keys();
+    let all = (0..keys.index_len.len())
+        .map(|i| keys.get_idx(&keys.index_len, i).unwrap().0)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        all,
+        ["イ", "カ", "アイ", "アオ", "アイス", "アイスクリーム"]
+    );
+
+    assert_eq!(
+        words(keys.search_len(2, "", None).unwrap()),
+        ["アイ", "アオ"]
+    );
+    assert_eq!(words(keys.search_len(2, "あお", None).unwrap()), ["アオ"]);
+    assert_eq!(words(keys.search_len(1, "", Some(1)).unwrap()), ["イ"]);
+    assert_eq!(words(keys.search_len(3, "ア", None).unwrap()), ["アイス"]);
+    assert_eq!(
+        words(keys.search_len(7, "アイ", None).unwrap()),
+        ["アイスクリーム"]
+    );
+    assert!(words(keys.search_len(4, "", None).unwrap()).is_empty());
+    assert!(words(keys.search_len(2, "イ", None).unwrap()).is_empty());
+    assert!(words(keys.search_len(8, "", None).unwrap()).is_empty());
+}
+
+#[test]
+fn test_search_page() {
+    let keys = Keys::from_file(IndexFile::from_bytes(build_keystore(&[
+        ("ウ", &[(10, 0)]),
+        ("エ", &[(10, 1), (11, 0)]),
+        ("オ", &[(5, 0), (10, 2)]),
+        ("キ", &[]),
+        ("ク", &[(300, 0)]),
+    ])))
+    .unwrap();
+    assert_eq!(words(keys.search_page(10).unwrap()), ["ウ", "エ"]);
+    assert_eq!(words(keys.search_page(5).unwrap()), ["オ"]);
+    assert_eq!(words(keys.search_page(300).unwrap()), ["ク"]);
+    assert!(words(keys.search_page(11).unwrap()).is_empty());
+    assert!(words(keys.search_page(0).unwrap()).is_empty());
+    assert!(words(keys.search_page(301).unwrap()).is_empty());
+
+    let (_, pages) = keys.search_page(10).unwrap().nth(1).unwrap().unwrap();
+    assert_eq!(
+        pages.collect::<Vec<_>>(),
+        [
+            PageItemId { page: 10, item: 1 },
+            PageItemId { page: 11, item: 0 }
+        ]
+    );
+}
+
+#[test]
+fn test_search_unsorted() {
+    let entries: &[(&str, &[(u32, u8)])] = &[
+        ("アイスクリーム", &[(3, 1)]),
+        ("カ", &[(70000, 0)]),
+        ("アイ", &[(1, 0), (5, 0)]),
+        ("イ", &[(300, 0)]),
+        ("アオ", &[(5, 0)]),
+        ("アイス", &[(2, 0)]),
+    ];
+    let sorted = Keys::from_file(IndexFile::from_bytes(build_keystore(entries))).unwrap();
+    let keys = Keys::from_file(IndexFile::from_bytes(build_keystore_with(entries, false))).unwrap();
+    assert_eq!(
+        words(keys.search_len(2, "", None).unwrap()),
+        ["アイ", "アオ"]
+    );
+    assert_eq!(words(keys.search_len(3, "ア", None).unwrap()), ["アイス"]);
+    assert_eq!(
+        words(keys.search_suffix("イ", None).unwrap()),
+        ["アイ", "イ"]
+    );
+    assert_eq!(
+        words(keys.search_suffix("", Some(2)).unwrap()),
+        ["アイスクリーム", "カ"]
+    );
+    assert_eq!(words(keys.search_page(5).unwrap()), ["アオ"]);
+    assert_eq!(words(keys.search_page(300).unwrap()), ["イ"]);
+    assert!(words(keys.search_page(4).unwrap()).is_empty());
+    for index in [&keys.index_len, &keys.index_suffix, &keys.index_d] {
+        assert_eq!(index.sorted.get(), Some(&false));
+    }
+
+    // The same headwords are found through binary searches in the expected orders.
+    assert_eq!(
+        words(sorted.search_suffix("イ", None).unwrap()),
+        ["イ", "アイ"]
+    );
+    assert_eq!(words(sorted.search_page(5).unwrap()), ["アオ"]);
+    for index in [&sorted.index_suffix, &sorted.index_d] {
+        assert_eq!(index.sorted.get(), Some(&true));
+    }
+}
+
+#[test]
+fn test_search_normalized() {
+    let mut keys = test_keys();
+    assert_eq!(keys.search_exact("ｱｲｽ").unwrap().0, 1);
+    assert_eq!(
+        words(keys.search_prefix("ｱｲｽｸﾘｰ", None).unwrap()),
+        ["アイスクリーム"]
+    );
+    assert_eq!(
+        words(keys.search_suffix("りーむ", None).unwrap()),
+        ["アイスクリーム"]
+    );
+    assert_eq!(
+        words(keys.search_len(2, "ｱ", None).unwrap()),
+        ["アイ", "アオ"]
+    );
+
+    keys.set_normalizer(Normalizer::none());
+    let err = keys.search_exact("あいす").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert_eq!(keys.search_exact("アイス").unwrap().0, 1);
+
+    keys.set_normalizer(Normalizer::new().romaji(true).clone());
+    assert_eq!(keys.search_exact("aisu").unwrap().0, 1);
+    assert_eq!(
+        words(keys.search_prefix("aisukuri", None).unwrap()),
+        ["アイスクリーム"]
+    );
+    let fuzzy = keys.search_fuzzy("aisukuriimu", 1).unwrap();
+    assert_eq!(fuzzy[0].0, "アイスクリーム");
+}
+
+#[test]
+fn test_search_fuzzy() {
+    let keys = test_keys();
+    let found = |query, max| {
+        keys.search_fuzzy(query, max)
+            .unwrap()
+            .into_iter()
+            .map(|(word, _, dist)| (word, dist))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(found("あいく", 1), [("アイ", 1), ("アイス", 1)]);
+    assert_eq!(found("アイス", 0), [("アイス", 0)]);
+    assert_eq!(found("アイス", 1), [("アイス", 0), ("アイ", 1)]);
+    assert_eq!(found("アイスクリム", 1), [("アイスクリーム", 1)]);
+    assert!(found("ンンンン", 2).is_empty());
+
+    let mut keys = Keys::from_file(IndexFile::from_bytes(build_keystore(&[
+        ("apple", &[(1, 0)]),
+        ("apply", &[(2, 0)]),
+        ("ample", &[(3, 0)]),
+        ("maple", &[(4, 0)]),
+    ])))
+    .unwrap();
+    assert!(keys.search_fuzzy("APPLE", 2).unwrap().is_empty());
+    keys.set_normalizer(Normalizer::new().case(true).clone());
+    let (word, pages, dist) = keys.search_fuzzy("Appel", 2).unwrap().remove(0);
+    assert_eq!((word, dist), ("apple", 2));
+    assert_eq!(pages.map(|p| p.page).collect::<Vec<_>>(), [1]);
+    assert_eq!(keys.search_fuzzy("appel", 2).unwrap().len(), 2);
+}
+
+#[test]
+fn test_search_pattern() {
+    let keys = test_keys();
+    let found = |pattern, limit| words(keys.search_pattern(pattern, limit).unwrap());
+    assert_eq!(found("ア?", None), ["アイ", "アオ"]);
+    assert_eq!(found("?", None), ["イ", "カ"]);
+    assert_eq!(found("?イ?", None), ["アイス"]);
+    assert_eq!(found("*イ", None), ["イ", "アイ"]);
+    assert_eq!(found("ア*ム", None), ["アイスクリーム"]);
+    assert_eq!(found("*ス*", None), ["アイス", "アイスクリーム"]);
+    assert_eq!(found("*", Some(3)), ["アイ", "アイス", "アイスクリーム"]);
+    assert_eq!(found("あい*", None), ["アイ", "アイス", "アイスクリーム"]);
+    assert_eq!(found("ア*", Some(1)), ["アイ"]);
+    assert_eq!(found("アイス", None), ["アイス"]);
+    assert!(found("アイスク", None).is_empty());
+    assert!(found("??????", None).is_empty());
+    assert!(found("*ン", None).is_empty());
+}
+
+#[test]
+fn test_search_regex() {
+    let keys = test_keys();
+    let found = |pattern, limit| words(keys.search_regex(pattern, limit).unwrap());
+    assert_eq!(found("^アイ", None), ["アイ", "アイス", "アイスクリーム"]);
+    assert_eq!(found("^アイ.$", None), ["アイス"]);
+    assert_eq!(
+        found("イ", None),
+        ["アイ", "アイス", "アイスクリーム", "イ"]
+    );
+    assert_eq!(found("^(ア|カ)$|オ$", None), ["アオ", "カ"]);
+    assert_eq!(found("ー", Some(1)), ["アイスクリーム"]);
+    assert_eq!(found(".", Some(2)), ["アイ", "アイス"]);
+    assert!(found("^ン", None).is_empty());
+
+    let mut matches = keys.search_regex("^アイス", None).unwrap();
+    assert_eq!(matches.progress(), (1, 6));
+    matches.next();
+    assert_eq!(matches.progress(), (2, 6));
+    assert_eq!(matches.by_ref().count(), 1);
+    assert_eq!(matches.progress(), (6, 6));
+
+    let err = keys.search_regex("(", None).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::RegexError);
+}
+
+#[test]
+fn test_search_deinflected() {
+    use crate::Reason::*;
+    let keys = Keys::from_file(IndexFile::from_bytes(build_keystore(&[
+        ("タカイ", &[(1, 0)]),
+        ("食ベル", &[(2, 0)]),
+        ("読ム", &[(3, 0)]),
+        ("読ミ", &[(4, 0)]),
+    ])))
+    .unwrap();
+    let found = |query| {
+        keys.search_deinflected(query)
+            .unwrap()
+            .into_iter()
+            .map(|(c, idx, _)| (c.word, c.reasons, idx))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        found("食べなかった"),
+        [("食べる".to_owned(), vec![Negative, Past], 3)]
+    );
+    assert_eq!(found("たかくて"), [("たかい".to_owned(), vec![TeForm], 0)]);
+    assert_eq!(found("読みます"), [("読む".to_owned(), vec![Polite], 2)]);
+    assert_eq!(found("読み")[0], ("読み".to_owned(), vec![], 1));
+    assert!(found("見た").is_empty());
+}
+
+#[test]
+fn test_search_lemmatized() {
+    use crate::Reason::*;
+    let keys = Keys::from_file(IndexFile::from_bytes(build_keystore(&[
+        ("good", &[(1, 0)]),
+        ("goose", &[(2, 0)]),
+        ("run", &[(3, 0)]),
+        ("running", &[(4, 0)]),
+    ])))
+    .unwrap();
+    let found = |query| {
+        keys.search_lemmatized(query)
+            .unwrap()
+            .into_iter()
+            .map(|(c, idx, _)| (c.word, c.reasons, idx))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(found("Geese"), [("goose".to_owned(), vec![Plural], 1)]);
+    assert_eq!(found("better"), [("good".to_owned(), vec![Comparative], 0)]);
+    assert_eq!(
+        found("running"),
+        [
+            ("running".to_owned(), vec![], 3),
+            ("run".to_owned(), vec![Progressive], 2)
+        ]
+    );
+    assert!(found("geeses").is_empty());
+}
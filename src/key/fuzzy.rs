@@ -0,0 +1,160 @@
+/// A BK-tree over the words of a key index, for finding words within an edit distance
+/// of a query. Distances count chars, so a kana and a Latin letter are both one unit.
+#[derive(Debug)]
+pub(super) struct BkTree {
+    /// The chars of every word, concatenated. Word `i` is `chars[spans[i].0..spans[i].1]`.
+    chars: Vec<char>,
+    spans: Vec<(u32, u32)>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    /// Distance to the parent node.
+    dist: u32,
+    first_child: u32,
+    next_sibling: u32,
+}
+
+const NONE: u32 = u32::MAX;
+
+impl BkTree {
+    /// Builds a tree of `words`. The nth word is reported as `n` by `find`.
+    pub(super) fn new<'a>(words: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut tree = BkTree {
+            chars: Vec::new(),
+            spans: Vec::new(),
+            nodes: Vec::new(),
+        };
+        for word in words {
+            let start = tree.chars.len() as u32;
+            tree.chars.extend(word.chars());
+            tree.spans.push((start, tree.chars.len() as u32));
+        }
+        let mut dist_buf = Vec::new();
+        for i in 0..tree.spans.len() {
+            tree.insert(i as u32, &mut dist_buf);
+        }
+        tree
+    }
+
+    fn word(&self, i: u32) -> &[char] {
+        let (start, end) = self.spans[i as usize];
+        &self.chars[start as usize..end as usize]
+    }
+
+    fn insert(&mut self, new: u32, dist_buf: &mut Vec<usize>) {
+        self.nodes.push(Node {
+            dist: 0,
+            first_child: NONE,
+            next_sibling: NONE,
+        });
+        if new == 0 {
+            return;
+        }
+        let mut node = 0;
+        loop {
+            let dist = levenshtein(self.word(node), self.word(new), dist_buf) as u32;
+            let mut child = self.nodes[node as usize].first_child;
+            while child != NONE && self.nodes[child as usize].dist != dist {
+                child = self.nodes[child as usize].next_sibling;
+            }
+            if child == NONE {
+                let parent = &mut self.nodes[node as usize];
+                let next_sibling = parent.first_child;
+                parent.first_child = new;
+                self.nodes[new as usize] = Node {
+                    dist,
+                    first_child: NONE,
+                    next_sibling,
+                };
+                return;
+            }
+            node = child;
+        }
+    }
+
+    /// Returns the words within `max_dist` of `query` with their distances, unordered.
+    pub(super) fn find(&self, query: &str, max_dist: usize) -> Vec<(u32, usize)> {
+        let query: Vec<char> = query.chars().collect();
+        let mut found = Vec::new();
+        if self.nodes.is_empty() {
+            return found;
+        }
+        let mut dist_buf = Vec::new();
+        let mut stack = vec![0_u32];
+        while let Some(node) = stack.pop() {
+            let dist = levenshtein(self.word(node), &query, &mut dist_buf);
+            if dist <= max_dist {
+                found.push((node, dist));
+            }
+            let mut child = self.nodes[node as usize].first_child;
+            while child != NONE {
+                let child_dist = self.nodes[child as usize].dist as usize;
+                // Triangle inequality: the words under `child` are at least this far from the query.
+                if child_dist.abs_diff(dist) <= max_dist {
+                    stack.push(child);
+                }
+                child = self.nodes[child as usize].next_sibling;
+            }
+        }
+        found
+    }
+}
+
+fn levenshtein(a: &[char], b: &[char], row: &mut Vec<usize>) -> usize {
+    row.clear();
+    row.extend(0..=b.len());
+    for (i, ca) in a.iter().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let subst = diag + usize::from(ca != cb);
+            diag = row[j + 1];
+            row[j + 1] = subst.min(row[j] + 1).min(diag + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[test]
+fn test_levenshtein() {
+    let dist = |a: &str, b: &str| {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        levenshtein(&a, &b, &mut Vec::new())
+    };
+    assert_eq!(dist("", ""), 0);
+    assert_eq!(dist("abc", ""), 3);
+    assert_eq!(dist("", "abc"), 3);
+    assert_eq!(dist("kitten", "sitting"), 3);
+    assert_eq!(dist("アイス", "アイス"), 0);
+    assert_eq!(dist("アイス", "アイ"), 1);
+    assert_eq!(dist("アイス", "アオス"), 1);
+    assert_eq!(dist("コトバ", "トコバ"), 2);
+}
+
+#[test]
+fn test_bk_tree() {
+    let words = [
+        "book", "books", "cake", "boo", "cape", "cart", "boon", "cook", "book",
+    ];
+    let tree = BkTree::new(words);
+    let brute_force = |query: &str, max: usize| {
+        let q: Vec<char> = query.chars().collect();
+        let mut found: Vec<_> = (0..words.len() as u32)
+            .map(|i| (i, levenshtein(tree.word(i), &q, &mut Vec::new())))
+            .filter(|&(_, d)| d <= max)
+            .collect();
+        found.sort();
+        found
+    };
+    for query in ["book", "bo", "caqe", "xyz", ""] {
+        for max in 0..4 {
+            let mut found = tree.find(query, max);
+            found.sort();
+            assert_eq!(found, brute_force(query, max), "{query} {max}");
+        }
+    }
+    assert!(BkTree::new([]).find("a", 3).is_empty());
+}
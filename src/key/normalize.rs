@@ -0,0 +1,301 @@
+use super::romaji;
+
+/// Normalizes queries to the form the keystore stores headwords in.
+///
+/// Every step but case folding and romaji conversion is enabled by default, and each can be
+/// toggled separately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalizer {
+    width: bool,
+    dakuten: bool,
+    kana: bool,
+    iteration_marks: bool,
+    long_vowel: bool,
+    case: bool,
+    romaji: bool,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer {
+            width: true,
+            dakuten: true,
+            kana: true,
+            iteration_marks: true,
+            long_vowel: true,
+            case: false,
+            romaji: false,
+        }
+    }
+}
+
+impl Normalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A normalizer that leaves queries as they are.
+    pub fn none() -> Self {
+        Normalizer {
+            width: false,
+            dakuten: false,
+            kana: false,
+            iteration_marks: false,
+            long_vowel: false,
+            case: false,
+            romaji: false,
+        }
+    }
+
+    /// Folds full-width ASCII to ASCII and half-width katakana to full-width.
+    pub fn width(&mut self, enabled: bool) -> &mut Self {
+        self.width = enabled;
+        self
+    }
+
+    /// Composes kana followed by a (combining or spacing) dakuten or handakuten.
+    pub fn dakuten(&mut self, enabled: bool) -> &mut Self {
+        self.dakuten = enabled;
+        self
+    }
+
+    /// Folds hiragana to katakana.
+    pub fn kana(&mut self, enabled: bool) -> &mut Self {
+        self.kana = enabled;
+        self
+    }
+
+    /// Expands the kana iteration marks ゝ, ゞ, ヽ and ヾ.
+    pub fn iteration_marks(&mut self, enabled: bool) -> &mut Self {
+        self.iteration_marks = enabled;
+        self
+    }
+
+    /// Replaces dashes and tildes that follow kana with the long vowel mark ー.
+    pub fn long_vowel(&mut self, enabled: bool) -> &mut Self {
+        self.long_vowel = enabled;
+        self
+    }
+
+    /// Folds letters to lowercase. Off by default, as keystores aren't known to store
+    /// headwords in lowercase.
+    pub fn case(&mut self, enabled: bool) -> &mut Self {
+        self.case = enabled;
+        self
+    }
+
+    /// Converts romaji, in Hepburn or Kunrei spelling, to kana: `kyou` is searched as
+    /// キョウ. Off by default, as it also converts English words that happen to be romaji.
+    pub fn romaji(&mut self, enabled: bool) -> &mut Self {
+        self.romaji = enabled;
+        self
+    }
+
+    pub fn normalize(&self, input: &str) -> String {
+        let kana;
+        let input = if self.romaji {
+            // Fold full-width letters first, so that they are recognized as romaji.
+            let folded: String = if self.width {
+                input.chars().map(fold_width).collect()
+            } else {
+                input.to_owned()
+            };
+            kana = romaji::to_kana(&folded);
+            &kana
+        } else {
+            input
+        };
+        let mut out = String::with_capacity(input.len());
+        for c in input.chars() {
+            let c = if self.width { fold_width(c) } else { c };
+            let prev = out.chars().next_back();
+
+            if self.dakuten {
+                if let Some(composed) = prev.and_then(|p| compose(p, c)) {
+                    out.pop();
+                    out.push(composed);
+                    continue;
+                }
+            }
+            let c = if self.kana { to_katakana(c) } else { c };
+            match (c, prev) {
+                ('ゝ' | 'ヽ', Some(p)) if self.iteration_marks && is_kana(p) => {
+                    out.push(unvoiced(p));
+                }
+                ('ゞ' | 'ヾ', Some(p)) if self.iteration_marks && is_kana(p) => {
+                    let p = unvoiced(p);
+                    out.push(voiced(p).unwrap_or(p));
+                }
+                (c, Some(p)) if self.long_vowel && is_dash(c) && is_kana(p) => out.push('ー'),
+                (c, _) if self.case => out.extend(c.to_lowercase()),
+                (c, _) => out.push(c),
+            }
+        }
+        out
+    }
+}
+
+const HALF_WIDTH_KATAKANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+
+fn fold_width(c: char) -> char {
+    match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap(),
+        '\u{3000}' => ' ',
+        '\u{FF61}'..='\u{FF9D}' => HALF_WIDTH_KATAKANA
+            .chars()
+            .nth((c as u32 - 0xFF61) as usize)
+            .unwrap(),
+        '\u{FF9E}' => '\u{3099}',
+        '\u{FF9F}' => '\u{309A}',
+        _ => c,
+    }
+}
+
+const VOICEABLE: &str =
+    "かきくけこさしすせそたちつてとはひふへほカキクケコサシスセソタチツテトハヒフヘホ";
+const SEMI_VOICEABLE: &str = "はひふへほハヒフヘホ";
+
+fn voiced(c: char) -> Option<char> {
+    match c {
+        'う' => Some('ゔ'),
+        'ウ' => Some('ヴ'),
+        'ワ' => Some('ヷ'),
+        'ヰ' => Some('ヸ'),
+        'ヱ' => Some('ヹ'),
+        'ヲ' => Some('ヺ'),
+        'ゝ' => Some('ゞ'),
+        'ヽ' => Some('ヾ'),
+        c if VOICEABLE.contains(c) => char::from_u32(c as u32 + 1),
+        _ => None,
+    }
+}
+
+fn semi_voiced(c: char) -> Option<char> {
+    if SEMI_VOICEABLE.contains(c) {
+        char::from_u32(c as u32 + 2)
+    } else {
+        None
+    }
+}
+
+fn unvoiced(c: char) -> char {
+    let base = |offset| char::from_u32(c as u32 - offset).filter(|b| VOICEABLE.contains(*b));
+    match c {
+        'ゔ' => 'う',
+        'ヴ' => 'ウ',
+        'ヷ'..='ヺ' => char::from_u32(c as u32 - 8).unwrap(),
+        _ => base(1)
+            .filter(|b| voiced(*b) == Some(c))
+            .or(base(2).filter(|b| semi_voiced(*b) == Some(c)))
+            .unwrap_or(c),
+    }
+}
+
+fn compose(base: char, mark: char) -> Option<char> {
+    match mark {
+        '\u{3099}' | '゛' => voiced(base),
+        '\u{309A}' | '゜' => semi_voiced(base),
+        _ => None,
+    }
+}
+
+fn to_katakana(c: char) -> char {
+    match c {
+        'ぁ'..='ゖ' => char::from_u32(c as u32 + ('ア' as u32 - 'あ' as u32)).unwrap(),
+        _ => c,
+    }
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, 'ぁ'..='ゖ' | 'ァ'..='ヺ' | 'ー')
+}
+
+fn is_dash(c: char) -> bool {
+    matches!(
+        c,
+        '-' | '~' | '\u{2010}'..='\u{2015}' | '\u{2212}' | '〜' | '～' | 'ｰ'
+    )
+}
+
+#[test]
+fn test_to_katakana() {
+    let to_katakana = |s: &str| s.chars().map(to_katakana).collect::<String>();
+    assert_eq!(to_katakana(""), "");
+    assert_eq!(to_katakana("あ"), "ア");
+    assert_eq!(to_katakana("ぁ"), "ァ");
+    assert_eq!(to_katakana("ん"), "ン");
+    assert_eq!(to_katakana("っ"), "ッ");
+    assert_eq!(to_katakana("ア"), "ア");
+    assert_eq!(to_katakana("ァ"), "ァ");
+    assert_eq!(to_katakana("ン"), "ン");
+    assert_eq!(to_katakana("ッ"), "ッ");
+    assert_eq!(to_katakana("aアa"), "aアa");
+    assert_eq!(to_katakana("aァa"), "aァa");
+    assert_eq!(to_katakana("aンa"), "aンa");
+    assert_eq!(to_katakana("aッa"), "aッa");
+    assert_eq!(to_katakana("ゔゕゖ"), "ヴヵヶ");
+}
+
+#[test]
+fn test_normalize() {
+    let n = Normalizer::new();
+    // (query, form stored in the keystore)
+    let table = [
+        ("", ""),
+        ("ことば", "コトバ"),
+        ("コトバ", "コトバ"),
+        ("ｺﾄﾊﾞ", "コトバ"),
+        ("ﾊﾟﾝ", "パン"),
+        ("ｶﾞｯｺｳ", "ガッコウ"),
+        ("ゔぁいおりん", "ヴァイオリン"),
+        ("ｳﾞｧｲｵﾘﾝ", "ヴァイオリン"),
+        ("か\u{3099}っこう", "ガッコウ"),
+        ("ハ\u{309A}ン", "パン"),
+        ("か゛っこう", "ガッコウ"),
+        ("いすゞ", "イスズ"),
+        ("こゝろ", "ココロ"),
+        ("ぶゞ", "ブブ"),
+        ("バヽ", "バハ"),
+        ("ハヾ", "ハバ"),
+        ("らーめん", "ラーメン"),
+        ("ら-めん", "ラーメン"),
+        ("ラ―メン", "ラーメン"),
+        ("ラ〜メン", "ラーメン"),
+        ("ﾗｰﾒﾝ", "ラーメン"),
+        ("ＡＢＣ", "ABC"),
+        ("Ｔシャツ", "Tシャツ"),
+        ("English", "English"),
+        ("e-mail", "e-mail"),
+        ("ゝ", "ゝ"),
+        ("\u{3099}", "\u{3099}"),
+        ("全角　空白", "全角 空白"),
+        ("々", "々"),
+    ];
+    for (query, stored) in table {
+        assert_eq!(n.normalize(query), stored, "{query}");
+    }
+
+    assert_eq!(
+        Normalizer::none().normalize("ｺﾄﾊﾞ ことば ＡＢＣ"),
+        "ｺﾄﾊﾞ ことば ＡＢＣ"
+    );
+    assert_eq!(Normalizer::new().kana(false).normalize("ことば"), "ことば");
+    assert_eq!(Normalizer::new().case(true).normalize("ＡＢＣ"), "abc");
+    assert_eq!(Normalizer::new().width(false).normalize("ＡＢＣ"), "ＡＢＣ");
+    assert_eq!(Normalizer::new().dakuten(false).normalize("か゛"), "カ゛");
+    assert_eq!(
+        Normalizer::new().iteration_marks(false).normalize("いすゞ"),
+        "イスゞ"
+    );
+    assert_eq!(Normalizer::new().long_vowel(false).normalize("ら-"), "ラ-");
+    assert_eq!(Normalizer::new().normalize("kyou"), "kyou");
+    assert_eq!(Normalizer::new().romaji(true).normalize("Kyō"), "キョウ");
+    assert_eq!(
+        Normalizer::new().romaji(true).normalize("ra-men"),
+        "ラーメン"
+    );
+    assert_eq!(
+        Normalizer::new().romaji(true).normalize("ＴＡＢＥＲＵ"),
+        "タベル"
+    );
+}
@@ -0,0 +1,171 @@
+use regex_syntax::hir::{
+    literal::{ExtractKind, Extractor},
+    Look,
+};
+
+/// A glob-style pattern over headwords: `?` matches one character and `*` matches
+/// any number of characters. Every other character matches itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Pattern {
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    One,
+    Many,
+}
+
+impl Pattern {
+    pub(super) fn new(pattern: &str) -> Self {
+        let tokens = pattern
+            .chars()
+            .map(|c| match c {
+                '?' => Token::One,
+                '*' => Token::Many,
+                c => Token::Char(c),
+            })
+            .collect();
+        Pattern { tokens }
+    }
+
+    fn literal(tokens: impl Iterator<Item = Token>) -> String {
+        tokens
+            .map_while(|t| match t {
+                Token::Char(c) => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The characters before the first wildcard.
+    pub(super) fn prefix(&self) -> String {
+        Self::literal(self.tokens.iter().copied())
+    }
+
+    /// The characters after the last wildcard.
+    pub(super) fn suffix(&self) -> String {
+        let reversed = Self::literal(self.tokens.iter().rev().copied());
+        reversed.chars().rev().collect()
+    }
+
+    pub(super) fn has_wildcards(&self) -> bool {
+        self.tokens.iter().any(|t| !matches!(t, Token::Char(_)))
+    }
+
+    /// The length in characters of every matching word, unless the pattern contains `*`.
+    pub(super) fn fixed_len(&self) -> Option<usize> {
+        if self.tokens.contains(&Token::Many) {
+            None
+        } else {
+            Some(self.tokens.len())
+        }
+    }
+
+    pub(super) fn is_match(&self, word: &str) -> bool {
+        let word: Vec<char> = word.chars().collect();
+        let (mut t, mut w) = (0, 0);
+        // Position of the last `*` and of the word char it was tried against
+        let mut backtrack = None;
+        while w < word.len() {
+            match self.tokens.get(t) {
+                Some(Token::Many) => {
+                    backtrack = Some((t, w));
+                    t += 1;
+                    continue;
+                }
+                Some(Token::One) => {
+                    t += 1;
+                    w += 1;
+                    continue;
+                }
+                Some(Token::Char(c)) if *c == word[w] => {
+                    t += 1;
+                    w += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            // Mismatch: let the last `*` consume one more char
+            let Some((star, star_w)) = backtrack else {
+                return false;
+            };
+            backtrack = Some((star, star_w + 1));
+            t = star + 1;
+            w = star_w + 1;
+        }
+        self.tokens[t..].iter().all(|t| *t == Token::Many)
+    }
+}
+
+/// Returns the literal that every match of the regex `pattern` starts with, if the
+/// regex is anchored at the start of the word.
+pub(super) fn regex_prefix(pattern: &str) -> Option<String> {
+    let hir = regex_syntax::parse(pattern).ok()?;
+    if !hir.properties().look_set_prefix().contains(Look::Start) {
+        return None;
+    }
+    let seq = Extractor::new().kind(ExtractKind::Prefix).extract(&hir);
+    let prefix = seq.longest_common_prefix()?;
+    // The common prefix may end in the middle of a char.
+    let prefix = match std::str::from_utf8(prefix) {
+        Ok(prefix) => prefix,
+        Err(e) => std::str::from_utf8(&prefix[..e.valid_up_to()]).unwrap(),
+    };
+    Some(prefix.to_owned()).filter(|p| !p.is_empty())
+}
+
+#[test]
+fn test_pattern() {
+    let p = Pattern::new("?ん?");
+    assert_eq!(p.prefix(), "");
+    assert_eq!(p.suffix(), "");
+    assert_eq!(p.fixed_len(), Some(3));
+    assert!(p.is_match("かんじ"));
+    assert!(!p.is_match("かんじ?"));
+    assert!(!p.is_match("かん"));
+    assert!(!p.is_match("かいじ"));
+
+    let p = Pattern::new("*ability");
+    assert_eq!(p.prefix(), "");
+    assert_eq!(p.suffix(), "ability");
+    assert_eq!(p.fixed_len(), None);
+    assert!(p.is_match("ability"));
+    assert!(p.is_match("capability"));
+    assert!(!p.is_match("abilities"));
+
+    let p = Pattern::new("a*b?c*");
+    assert_eq!(p.prefix(), "a");
+    assert_eq!(p.suffix(), "");
+    assert!(p.is_match("abxc"));
+    assert!(p.is_match("axxbbbcbxcyy"));
+    assert!(!p.is_match("abc"));
+    assert!(!p.is_match("xabxc"));
+
+    let p = Pattern::new("アイス");
+    assert!(!p.has_wildcards());
+    assert_eq!(p.prefix(), "アイス");
+    assert_eq!(p.suffix(), "アイス");
+    assert!(p.is_match("アイス"));
+    assert!(!p.is_match("アイスー"));
+
+    assert!(Pattern::new("").is_match(""));
+    assert!(!Pattern::new("").is_match("a"));
+    assert!(Pattern::new("*").is_match(""));
+    assert!(Pattern::new("**").is_match("abc"));
+}
+
+#[test]
+fn test_regex_prefix() {
+    assert_eq!(regex_prefix("^アイス"), Some("アイス".to_owned()));
+    assert_eq!(regex_prefix("^アイ(ス|ロン)"), Some("アイ".to_owned()));
+    assert_eq!(regex_prefix("^abc.*def$"), Some("abc".to_owned()));
+    assert_eq!(regex_prefix("^(アイ|アオ)"), Some("ア".to_owned()));
+    // The common prefix of カ and ガ is a partial char
+    assert_eq!(regex_prefix("^[カガ]"), None);
+    assert_eq!(regex_prefix("アイス"), None);
+    assert_eq!(regex_prefix("^.*ス"), None);
+    assert_eq!(regex_prefix("^(?i)abc"), None);
+    assert_eq!(regex_prefix("("), None);
+}
@@ -0,0 +1,171 @@
+/// Romaji syllables, in Hepburn and Kunrei spellings, and the kana they stand for.
+#[rustfmt::skip]
+const SYLLABLES: &[(&str, &str)] = &[
+    ("a", "あ"), ("i", "い"), ("u", "う"), ("e", "え"), ("o", "お"),
+    ("ka", "か"), ("ki", "き"), ("ku", "く"), ("ke", "け"), ("ko", "こ"),
+    ("kya", "きゃ"), ("kyu", "きゅ"), ("kyo", "きょ"),
+    ("ga", "が"), ("gi", "ぎ"), ("gu", "ぐ"), ("ge", "げ"), ("go", "ご"),
+    ("gya", "ぎゃ"), ("gyu", "ぎゅ"), ("gyo", "ぎょ"),
+    ("sa", "さ"), ("shi", "し"), ("si", "し"), ("su", "す"), ("se", "せ"), ("so", "そ"),
+    ("sha", "しゃ"), ("shu", "しゅ"), ("sho", "しょ"), ("she", "しぇ"),
+    ("sya", "しゃ"), ("syu", "しゅ"), ("syo", "しょ"),
+    ("za", "ざ"), ("ji", "じ"), ("zi", "じ"), ("zu", "ず"), ("ze", "ぜ"), ("zo", "ぞ"),
+    ("ja", "じゃ"), ("ju", "じゅ"), ("jo", "じょ"), ("je", "じぇ"),
+    ("zya", "じゃ"), ("zyu", "じゅ"), ("zyo", "じょ"),
+    ("jya", "じゃ"), ("jyu", "じゅ"), ("jyo", "じょ"),
+    ("ta", "た"), ("chi", "ち"), ("ti", "ち"), ("tsu", "つ"), ("tu", "つ"), ("te", "て"), ("to", "と"),
+    ("cha", "ちゃ"), ("chu", "ちゅ"), ("cho", "ちょ"), ("che", "ちぇ"),
+    ("tya", "ちゃ"), ("tyu", "ちゅ"), ("tyo", "ちょ"),
+    ("da", "だ"), ("di", "ぢ"), ("du", "づ"), ("de", "で"), ("do", "ど"),
+    ("dya", "ぢゃ"), ("dyu", "ぢゅ"), ("dyo", "ぢょ"),
+    ("na", "な"), ("ni", "に"), ("nu", "ぬ"), ("ne", "ね"), ("no", "の"),
+    ("nya", "にゃ"), ("nyu", "にゅ"), ("nyo", "にょ"),
+    ("ha", "は"), ("hi", "ひ"), ("fu", "ふ"), ("hu", "ふ"), ("he", "へ"), ("ho", "ほ"),
+    ("hya", "ひゃ"), ("hyu", "ひゅ"), ("hyo", "ひょ"),
+    ("fa", "ふぁ"), ("fi", "ふぃ"), ("fe", "ふぇ"), ("fo", "ふぉ"),
+    ("ba", "ば"), ("bi", "び"), ("bu", "ぶ"), ("be", "べ"), ("bo", "ぼ"),
+    ("bya", "びゃ"), ("byu", "びゅ"), ("byo", "びょ"),
+    ("pa", "ぱ"), ("pi", "ぴ"), ("pu", "ぷ"), ("pe", "ぺ"), ("po", "ぽ"),
+    ("pya", "ぴゃ"), ("pyu", "ぴゅ"), ("pyo", "ぴょ"),
+    ("ma", "ま"), ("mi", "み"), ("mu", "む"), ("me", "め"), ("mo", "も"),
+    ("mya", "みゃ"), ("myu", "みゅ"), ("myo", "みょ"),
+    ("ya", "や"), ("yu", "ゆ"), ("yo", "よ"),
+    ("ra", "ら"), ("ri", "り"), ("ru", "る"), ("re", "れ"), ("ro", "ろ"),
+    ("rya", "りゃ"), ("ryu", "りゅ"), ("ryo", "りょ"),
+    ("wa", "わ"), ("wo", "を"),
+    ("va", "ゔぁ"), ("vi", "ゔぃ"), ("vu", "ゔ"), ("ve", "ゔぇ"), ("vo", "ゔぉ"),
+    ("xa", "ぁ"), ("xi", "ぃ"), ("xu", "ぅ"), ("xe", "ぇ"), ("xo", "ぉ"),
+    ("xya", "ゃ"), ("xyu", "ゅ"), ("xyo", "ょ"), ("xtsu", "っ"), ("xtu", "っ"),
+];
+
+fn is_vowel(c: u8) -> bool {
+    b"aeiou".contains(&c)
+}
+
+/// Spells out vowels marked long with a macron (Hepburn) or circumflex (Kunrei).
+fn expand_long_vowel(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ā' | 'â' => "aa",
+        'ī' | 'î' => "ii",
+        'ū' | 'û' => "uu",
+        'ē' | 'ê' => "ee",
+        'ō' | 'ô' => "ou",
+        _ => return None,
+    })
+}
+
+fn is_romaji(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '\'' || c.to_lowercase().all(|c| expand_long_vowel(c).is_some())
+}
+
+/// Converts a run of romaji to hiragana, or returns `None` if it isn't romaji.
+fn convert_run(run: &str) -> Option<String> {
+    let mut romaji = String::with_capacity(run.len());
+    for c in run.chars().flat_map(char::to_lowercase) {
+        match expand_long_vowel(c) {
+            Some(vowels) => romaji.push_str(vowels),
+            None => romaji.push(c),
+        }
+    }
+    let b = romaji.as_bytes();
+    let mut kana = String::new();
+    let mut i = 0;
+    while i < b.len() {
+        let next = b.get(i + 1).copied();
+        match b[i] {
+            // n' separates ん from a following vowel or y: kin'en, kon'ya
+            b'n' if next == Some(b'\'') => {
+                kana.push('ん');
+                i += 2;
+            }
+            b'n' if next == Some(b'n')
+                && !b.get(i + 2).is_some_and(|&c| is_vowel(c) || c == b'y') =>
+            {
+                kana.push('ん');
+                i += 2;
+            }
+            b'n' if !next.is_some_and(|c| is_vowel(c) || c == b'y') => {
+                kana.push('ん');
+                i += 1;
+            }
+            // Hepburn writes ん as m before labials: shimbun
+            b'm' if matches!(next, Some(b'b' | b'm' | b'p')) => {
+                kana.push('ん');
+                i += 1;
+            }
+            // Doubled consonants and Hepburn's tch stand for the sokuon.
+            c if !is_vowel(c)
+                && c.is_ascii_alphabetic()
+                && (next == Some(c) || (c == b't' && next == Some(b'c'))) =>
+            {
+                kana.push('っ');
+                i += 1;
+            }
+            _ => {
+                let (len, syllable) = (1..=4).rev().find_map(|len| {
+                    let romaji = romaji.get(i..i + len)?;
+                    let (_, kana) = SYLLABLES.iter().find(|(r, _)| *r == romaji)?;
+                    Some((len, kana))
+                })?;
+                kana.push_str(syllable);
+                i += len;
+            }
+        }
+    }
+    Some(kana)
+}
+
+/// Converts the runs of romaji in `input` to hiragana. Runs that aren't romaji, such as
+/// the t of tシャツ, are left as they are.
+pub(super) fn to_kana(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while !rest.is_empty() {
+        let end = rest.find(|c| !is_romaji(c)).unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(end);
+        match convert_run(run) {
+            Some(kana) => out.push_str(&kana),
+            None => out.push_str(run),
+        }
+        let other = tail.find(is_romaji).unwrap_or(tail.len());
+        out.push_str(&tail[..other]);
+        rest = &tail[other..];
+    }
+    out
+}
+
+#[test]
+fn test_to_kana() {
+    let table = [
+        ("", ""),
+        ("taberu", "たべる"),
+        ("kyou", "きょう"),
+        ("kyō", "きょう"),
+        ("TŌKYŌ", "とうきょう"),
+        ("tôkyô", "とうきょう"),
+        ("gakkou", "がっこう"),
+        ("matcha", "まっちゃ"),
+        ("shinbun", "しんぶん"),
+        ("shimbun", "しんぶん"),
+        ("sinbun", "しんぶん"),
+        ("konnichiwa", "こんにちわ"),
+        ("kon'ya", "こんや"),
+        ("konya", "こにゃ"),
+        ("kin'en", "きんえん"),
+        ("hon", "ほん"),
+        ("honn", "ほん"),
+        ("tyotto", "ちょっと"),
+        ("chotto", "ちょっと"),
+        ("tsukue", "つくえ"),
+        ("tukue", "つくえ"),
+        ("fuji", "ふじ"),
+        ("huzi", "ふじ"),
+        ("ra-men", "ら-めん"),
+        ("tシャツ", "tシャツ"),
+        ("xyz", "xyz"),
+        ("aisu kuriimu", "あいす くりいむ"),
+    ];
+    for (romaji, kana) in table {
+        assert_eq!(to_kana(romaji), kana, "{romaji}");
+    }
+}
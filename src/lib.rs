@@ -1,15 +1,36 @@
 mod abi_utils;
 mod media;
 mod dict;
+mod dict_set;
+mod entry;
+mod deinflect;
 mod error;
+mod fulltext;
+mod gaiji;
 mod key;
+mod links;
 mod pages;
+mod render;
 pub mod resource;
+pub mod diagnostics;
 mod headline;
+mod options;
+mod storage;
 
-pub use media::Media;
-pub use dict::MonokakidoDict;
-pub use error::Error;
-pub use key::{KeyIndex, Keys, PageItemId};
-pub use pages::{Pages, XmlParser};
-pub use headline::{Headlines};
+pub use media::{Media, MediaId, MediaKind};
+pub use dict::{Language, MonokakidoDict};
+pub use dict_set::{DictionarySet, SetResults};
+pub use deinflect::{deinflect, lemmatize, Deinflection, Reason};
+pub use entry::{
+    parse_fragment, CrossRef, Element, Entry, Example, Idiom, MediaRef, Node, Profile, Role, Sense,
+};
+pub use error::{Error, ErrorKind, FileFormat, Location};
+pub use fulltext::{FullTextIndex, TextHit};
+pub use gaiji::{is_private_use, GaijiRef, GaijiTable, Glyph};
+pub use key::{KeyIndex, Keys, Matches, Normalizer, PageItemId, PageIter};
+pub use links::{Link, LinkIndex, LinkIter, LinkTarget};
+pub use pages::{ItemIter, Pages, Xml, XmlParser};
+pub use render::{HtmlRenderer, StyleFamily, TextRenderer};
+pub use headline::Headlines;
+pub use options::{IndexStorage, OpenOptions};
+pub use resource::CacheStats;
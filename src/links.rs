@@ -0,0 +1,362 @@
+//! Links between entries: extraction from the page XML, resolution, and a reverse index.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    entry::{unescape, MediaRef},
+    error::ResultExt,
+    pages::parse_item_id,
+    Error, ErrorKind, FileFormat, Keys, MonokakidoDict, PageItemId,
+};
+
+/// Where a link points to.
+///
+/// These `href` forms are recognized:
+/// - `12-3`, `#12-3`: item 3 of page 12; `12` is the page itself, as item 0
+/// - `key:word`, `?key=word`: the headword `word`, percent-encoded
+/// - `PRODUCT:target`, with an upper-case product name such as `DAIJISEN2`: one of the
+///   above in another product
+/// - `http(s)://…`, `mailto:…`: a web page or address
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LinkTarget {
+    Item(PageItemId),
+    Keyword(String),
+    Product {
+        product: String,
+        target: Box<LinkTarget>,
+    },
+    Url(String),
+}
+
+impl LinkTarget {
+    /// Parses an `href`. Returns `None` for media and anything else that isn't a link.
+    pub fn parse(href: &str) -> Option<Self> {
+        let href = href.trim();
+        if MediaRef::from_href(href).is_some() {
+            return None;
+        }
+        if ["http://", "https://", "mailto:"]
+            .iter()
+            .any(|scheme| href.starts_with(scheme))
+        {
+            return Some(LinkTarget::Url(href.to_owned()));
+        }
+        if let Some(word) = href.strip_prefix("key:").or(href.strip_prefix("?key=")) {
+            let word = percent_decode(word)?;
+            return (!word.is_empty()).then_some(LinkTarget::Keyword(word));
+        }
+        if let Some((product, target)) = href.split_once(':') {
+            let is_product = product.starts_with(|c: char| c.is_ascii_uppercase())
+                && product
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+            let target = LinkTarget::parse(target)
+                .filter(|t| matches!(t, LinkTarget::Item(_) | LinkTarget::Keyword(_)));
+            return Some(LinkTarget::Product {
+                product: product.to_owned(),
+                target: Box::new(target.filter(|_| is_product)?),
+            });
+        }
+        let id = href.strip_prefix('#').unwrap_or(href);
+        match id.parse() {
+            Ok(page) => Some(LinkTarget::Item(PageItemId { page, item: 0 })),
+            Err(_) => parse_item_id(id).map(LinkTarget::Item),
+        }
+    }
+
+    /// Whether the target is in the same product.
+    pub fn is_internal(&self) -> bool {
+        matches!(self, LinkTarget::Item(_) | LinkTarget::Keyword(_))
+    }
+
+    /// Resolves a link within the product of `keys` to the item it points to: keywords to
+    /// the first item of the headword. Links to other products and to the web resolve
+    /// to `None`, as do keywords that aren't headwords.
+    pub fn resolve(&self, keys: &Keys) -> Result<Option<PageItemId>, Error> {
+        match self {
+            LinkTarget::Item(id) => Ok(Some(*id)),
+            LinkTarget::Keyword(word) => match keys.search_exact(word) {
+                Ok((_, mut pages)) => Ok(pages.next()),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            },
+            LinkTarget::Product { .. } | LinkTarget::Url(_) => Ok(None),
+        }
+    }
+}
+
+/// Percent-encodes `text` for a URL, keeping the unreserved characters and `/`.
+pub(crate) fn percent_encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        if b == b'%' {
+            let hex = std::str::from_utf8(rest.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &rest[2..];
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// A link found in the page XML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link<'a> {
+    /// The whole link element.
+    pub fragment: &'a str,
+    pub href: String,
+    /// The text of the link element.
+    pub text: String,
+    /// The innermost item the link is in.
+    pub source: Option<PageItemId>,
+    pub target: LinkTarget,
+}
+
+/// An iterator over the links of a page or an item, in the order their elements end.
+pub struct LinkIter<'a> {
+    xml: &'a str,
+    tokens: xmlparser::Tokenizer<'a>,
+    stack: Vec<Open>,
+}
+
+/// An open element: its start, its item ID and, for links, the href and text.
+struct Open {
+    start: usize,
+    id: Option<PageItemId>,
+    link: Option<(String, String)>,
+}
+
+impl<'a> LinkIter<'a> {
+    pub fn from(xml: &'a str) -> Self {
+        LinkIter {
+            xml,
+            tokens: xmlparser::Tokenizer::from_fragment(xml, 0..xml.len()),
+            stack: Vec::new(),
+        }
+    }
+
+    fn source(&self) -> Option<PageItemId> {
+        self.stack.iter().rev().find_map(|open| open.id)
+    }
+
+    fn next_link(&mut self) -> Result<Option<Link<'a>>, Error> {
+        use xmlparser::{ElementEnd, Token};
+
+        while let Some(token) = self.tokens.next() {
+            match token.in_format(FileFormat::PageXml)? {
+                Token::ElementStart { span, .. } => self.stack.push(Open {
+                    start: span.start(),
+                    id: None,
+                    link: None,
+                }),
+                Token::Attribute { local, value, .. } => {
+                    let Some(open) = self.stack.last_mut() else {
+                        continue;
+                    };
+                    match local.as_str() {
+                        "id" => open.id = parse_item_id(&value),
+                        "href" => open.link = Some((unescape(&value), String::new())),
+                        _ => {}
+                    }
+                }
+                Token::ElementEnd {
+                    end: ElementEnd::Open,
+                    ..
+                } => {}
+                Token::ElementEnd { span, .. } => {
+                    let Open { start, link, .. } = self.stack.pop().ok_or_else(|| {
+                        Error::new(ErrorKind::XmlError)
+                            .at_offset(span.start() as u64)
+                            .in_format(FileFormat::PageXml)
+                    })?;
+                    let Some((href, text)) = link else {
+                        continue;
+                    };
+                    let Some(target) = LinkTarget::parse(&href) else {
+                        continue;
+                    };
+                    // With the link element popped, the source is the item it's in.
+                    return Ok(Some(Link {
+                        fragment: &self.xml[start..span.end()],
+                        href,
+                        text,
+                        source: self.source(),
+                        target,
+                    }));
+                }
+                Token::Text { text } | Token::Cdata { text, .. } => {
+                    let text = unescape(&text);
+                    for open in &mut self.stack {
+                        if let Some((_, link_text)) = &mut open.link {
+                            link_text.push_str(&text);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for LinkIter<'a> {
+    type Item = Result<Link<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_link().transpose()
+    }
+}
+
+/// The links between the items of a dictionary, to find what links to an item.
+#[derive(Debug, Clone, Default)]
+pub struct LinkIndex {
+    /// Sorted, deduplicated sources by target
+    backlinks: BTreeMap<PageItemId, Vec<PageItemId>>,
+}
+
+impl LinkIndex {
+    /// Reads every page of `dict` and resolves its internal links.
+    /// Links outside of items count as coming from item 0 of their page.
+    pub fn build(dict: &MonokakidoDict) -> Result<Self, Error> {
+        let mut backlinks: BTreeMap<PageItemId, Vec<PageItemId>> = BTreeMap::new();
+        for idx in dict.pages.idx_iter()? {
+            let (page, xml) = dict.pages.page_by_idx(idx)?;
+            for link in LinkIter::from(&xml) {
+                let link = link.at_record(idx)?;
+                if !link.target.is_internal() {
+                    continue;
+                }
+                if let Some(target) = link.target.resolve(&dict.keys)? {
+                    let source = link.source.unwrap_or(PageItemId { page, item: 0 });
+                    backlinks.entry(target).or_default().push(source);
+                }
+            }
+        }
+        for sources in backlinks.values_mut() {
+            sources.sort();
+            sources.dedup();
+        }
+        Ok(LinkIndex { backlinks })
+    }
+
+    /// The items that link to `target`.
+    pub fn links_to(&self, target: PageItemId) -> &[PageItemId] {
+        self.backlinks.get(&target).map_or(&[], Vec::as_slice)
+    }
+
+    /// The items that link to any item of `page`, with the items they link to.
+    pub fn links_to_page(&self, page: u32) -> impl Iterator<Item = (PageItemId, PageItemId)> + '_ {
+        let range = PageItemId { page, item: 0 }..=PageItemId {
+            page,
+            item: u8::MAX,
+        };
+        self.backlinks
+            .range(range)
+            .flat_map(|(target, sources)| sources.iter().map(|source| (*source, *target)))
+    }
+
+    /// Number of items linked to.
+    pub fn len(&self) -> usize {
+        self.backlinks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.backlinks.is_empty()
+    }
+}
+
+#[test]
+fn test_link_target() {
+    let item = |page, item| Some(LinkTarget::Item(PageItemId { page, item }));
+    let keyword = |word: &str| Some(LinkTarget::Keyword(word.to_owned()));
+    assert_eq!(LinkTarget::parse("12-3"), item(12, 3));
+    assert_eq!(LinkTarget::parse("#12-3"), item(12, 3));
+    assert_eq!(LinkTarget::parse("12"), item(12, 0));
+    assert_eq!(LinkTarget::parse("key:%E6%84%9B"), keyword("愛"));
+    assert_eq!(LinkTarget::parse("?key=ice cream"), keyword("ice cream"));
+    assert_eq!(
+        LinkTarget::parse("DAIJISEN2:5-1"),
+        Some(LinkTarget::Product {
+            product: "DAIJISEN2".to_owned(),
+            target: Box::new(item(5, 1).unwrap()),
+        })
+    );
+    assert_eq!(
+        LinkTarget::parse("https://example.com/a"),
+        Some(LinkTarget::Url("https://example.com/a".to_owned()))
+    );
+    assert_eq!(percent_encode("a/b c-愛.png"), "a/b%20c-%E6%84%9B.png");
+    assert_eq!(
+        LinkTarget::parse(&format!("key:{}", percent_encode("愛 100%"))),
+        keyword("愛 100%")
+    );
+    for not_a_link in [
+        "12-0.aac", "pic.png", "", "key:", "key:%E6", "x:1-2", "OALD:?", "a-b",
+    ] {
+        assert_eq!(LinkTarget::parse(not_a_link), None, "{not_a_link}");
+    }
+}
+
+#[test]
+fn test_link_iter() {
+    let xml = r#"<d><body><div id="7-1">see <a href="12-3">this &amp; <b>that</b></a> and <a href="12-0.aac">♪</a></div><a href="key:愛">愛</a><a href="WISDOM3:key:love">love</a></body></d>"#;
+    let links: Vec<_> = LinkIter::from(xml).collect::<Result<_, _>>().unwrap();
+    assert_eq!(links.len(), 3);
+    assert_eq!(
+        links[0].fragment,
+        r#"<a href="12-3">this &amp; <b>that</b></a>"#
+    );
+    assert_eq!(links[0].text, "this & that");
+    assert_eq!(links[0].source, Some(PageItemId { page: 7, item: 1 }));
+    assert_eq!(links[1].source, None);
+    assert_eq!(links[1].target, LinkTarget::Keyword("愛".to_owned()));
+    assert!(!links[2].target.is_internal());
+    assert!(LinkIter::from("<a href='1'>").all(|l| l.is_ok()));
+    assert!(LinkIter::from("<a x=1>").any(|l| l.is_err()));
+}
+
+#[test]
+fn test_link_index() {
+    use crate::dict::{write_test_pages, write_test_product};
+    let tmp = tempfile::tempdir().unwrap();
+    write_test_product(tmp.path(), "Test", &[("アイ", &[(2, 1)])]);
+    let pages = [
+        (
+            1,
+            r#"<d><body><div id="1-1"><a href="2-1">a</a><a href="key:あい">b</a></div><a href="3">c</a></body></d>"#,
+        ),
+        (
+            2,
+            r#"<d><body><div id="2-1"><a href="key:ない">d</a><a href="http://x.org">e</a></div></body></d>"#,
+        ),
+        (3, r#"<d><body><a href="2-1">f</a></body></d>"#),
+    ];
+    write_test_pages(tmp.path(), "Test", &pages);
+    let dict = MonokakidoDict::open_with_dir("Test", tmp.path().to_str()).unwrap();
+
+    let index = LinkIndex::build(&dict).unwrap();
+    let id = |page, item| PageItemId { page, item };
+    assert_eq!(index.links_to(id(2, 1)), [id(1, 1), id(3, 0)]);
+    assert_eq!(index.links_to(id(3, 0)), [id(1, 0)]);
+    assert!(index.links_to(id(1, 1)).is_empty());
+    assert_eq!(index.len(), 2);
+    assert_eq!(
+        index.links_to_page(2).collect::<Vec<_>>(),
+        [(id(1, 1), id(2, 1)), (id(3, 0), id(2, 1))]
+    );
+}
@@ -1,16 +1,37 @@
-use std::{fmt::Display, ops::Range, path::PathBuf};
+use std::{fmt::Display, ops::Range, path::PathBuf, sync::OnceLock};
 
 use crate::{
     dict::Paths,
-    resource::{Nrsc, Rsc},
-    Error,
+    error::ResultExt,
+    resource::{Blob, CacheStats, Nrsc, Rsc},
+    Error, ErrorKind, OpenOptions,
 };
 
-const RSC_NAME: &str = "audio";
+/// The kind of media stored in a resource directory under the contents directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaKind {
+    Audio,
+    Graphics,
+    /// Any other resource directory, e.g. a product-specific one.
+    Named(String),
+}
+
+impl MediaKind {
+    /// Name of the resource directory, which is also the file stem of its rsc files.
+    pub fn dir_name(&self) -> &str {
+        match self {
+            MediaKind::Audio => "audio",
+            MediaKind::Graphics => "graphics",
+            MediaKind::Named(name) => name,
+        }
+    }
+}
 
 pub struct Media {
+    kind: MediaKind,
     path: PathBuf,
-    res: Option<MediaResource>,
+    options: OpenOptions,
+    res: OnceLock<MediaResource>,
 }
 
 enum MediaResource {
@@ -19,62 +40,92 @@ enum MediaResource {
 }
 
 impl Media {
-    pub fn new(paths: &Paths) -> Result<Option<Self>, Error> {
-        let mut path = paths.contents_path();
-        path.push(RSC_NAME);
-        Ok(if path.exists() {
-            Some(Media { path, res: None })
+    pub fn new(
+        paths: &Paths,
+        kind: MediaKind,
+        options: &OpenOptions,
+    ) -> Result<Option<Self>, Error> {
+        Ok(Self::new_with_dir(paths.contents_path(), kind, options))
+    }
+
+    fn new_with_dir(mut path: PathBuf, kind: MediaKind, options: &OpenOptions) -> Option<Self> {
+        path.push(kind.dir_name());
+        if path.is_dir() {
+            Some(Media {
+                kind,
+                path,
+                options: options.clone(),
+                res: OnceLock::new(),
+            })
         } else {
             None
-        })
+        }
+    }
+
+    pub fn kind(&self) -> &MediaKind {
+        &self.kind
+    }
+
+    pub fn init(&self) -> Result<(), Error> {
+        self.res().map(|_| ())
     }
 
-    pub fn init(&mut self) -> Result<(), Error> {
-        if self.res.is_none() {
-            self.path.push("index.nidx");
-            let nrsc_index_exists = self.path.exists();
-            self.path.pop();
-            self.res = Some(if nrsc_index_exists {
-                MediaResource::Nrsc(Nrsc::new(&self.path)?)
-            } else {
-                MediaResource::Rsc(Rsc::new(&self.path, RSC_NAME)?)
-            });
+    fn res(&self) -> Result<&MediaResource, Error> {
+        if let Some(res) = self.res.get() {
+            return Ok(res);
         }
-        Ok(())
+        let res = if self.path.join("index.nidx").exists() {
+            MediaResource::Nrsc(Nrsc::new_with_options(&self.path, &self.options)?)
+        } else {
+            MediaResource::Rsc(Rsc::new_with_options(
+                &self.path,
+                self.kind.dir_name(),
+                &self.options,
+            )?)
+        };
+        // Another thread may have won the race; its resource is as good as ours.
+        let _ = self.res.set(res);
+        Ok(self.res.get().unwrap())
     }
 
-    pub fn get(&mut self, id: &str) -> Result<&[u8], Error> {
-        self.init()?;
-        let Some(res) = self.res.as_mut() else { unreachable!() };
-        match res {
-            MediaResource::Rsc(rsc) => rsc.get(id.parse::<u32>().map_err(|_| Error::InvalidIndex)?),
+    pub fn get(&self, id: &str) -> Result<Blob, Error> {
+        match self.res()? {
+            MediaResource::Rsc(rsc) => {
+                rsc.get(id.parse::<u32>().map_err(|_| ErrorKind::InvalidIndex)?)
+            }
             MediaResource::Nrsc(nrsc) => nrsc.get(id),
         }
+        .with_path(&self.path)
     }
 
-    pub fn get_by_idx(&mut self, idx: usize) -> Result<(MediaId, &[u8]), Error> {
-        self.init()?;
-        let Some(res) = self.res.as_mut() else { unreachable!() };
-        Ok(match res {
+    pub fn get_by_idx(&self, idx: usize) -> Result<(MediaId<'_>, Blob), Error> {
+        Ok(match self.res()? {
             MediaResource::Rsc(rsc) => {
-                let (id, page) = rsc.get_by_idx(idx)?;
+                let (id, page) = rsc.get_by_idx(idx).with_path(&self.path)?;
                 (MediaId::Num(id), page)
             }
             MediaResource::Nrsc(nrsc) => {
-                let (id, page) = nrsc.get_by_idx(idx)?;
+                let (id, page) = nrsc.get_by_idx(idx).with_path(&self.path)?;
                 (MediaId::Str(id), page)
             }
         })
     }
 
-    pub fn idx_iter(&mut self) -> Result<Range<usize>, Error> {
-        self.init()?;
-        let Some(res) = self.res.as_ref() else { unreachable!() };
-        Ok(0..match res {
+    pub fn idx_iter(&self) -> Result<Range<usize>, Error> {
+        Ok(0..match self.res()? {
             MediaResource::Rsc(rsc) => rsc.len(),
             MediaResource::Nrsc(nrsc) => nrsc.len(),
         })
     }
+
+    /// Counters of the decompressed chunk cache. All zero until the media are first read,
+    /// and for nrsc resources, whose files are read without chunks.
+    pub fn cache_stats(&self) -> CacheStats {
+        match self.res.get() {
+            Some(MediaResource::Rsc(rsc)) => rsc.cache_stats(),
+            _ => CacheStats::default(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -91,3 +142,71 @@ impl Display for MediaId<'_> {
         }
     }
 }
+
+#[test]
+fn test_media_dirs() {
+    use std::fs::create_dir;
+
+    let contents = tempfile::tempdir().unwrap();
+    let contents = contents.path().to_path_buf();
+    create_dir(contents.join("audio")).unwrap();
+    create_dir(contents.join("graphics")).unwrap();
+    create_dir(contents.join("movie")).unwrap();
+    let opts = OpenOptions::new();
+
+    let audio = Media::new_with_dir(contents.clone(), MediaKind::Audio, &opts).unwrap();
+    assert_eq!(audio.path, contents.join("audio"));
+    assert_eq!(audio.kind(), &MediaKind::Audio);
+
+    let graphics = Media::new_with_dir(contents.clone(), MediaKind::Graphics, &opts).unwrap();
+    assert_eq!(graphics.path, contents.join("graphics"));
+
+    let movie = Media::new_with_dir(
+        contents.clone(),
+        MediaKind::Named("movie".to_owned()),
+        &opts,
+    );
+    assert_eq!(movie.unwrap().path, contents.join("movie"));
+
+    let missing = Media::new_with_dir(
+        contents.clone(),
+        MediaKind::Named("gaiji".to_owned()),
+        &opts,
+    );
+    assert!(missing.is_none());
+}
+
+#[test]
+fn test_dict_graphics() {
+    use crate::{dict::write_test_product, resource::write_test_rsc, MonokakidoDict};
+
+    let tmp = tempfile::tempdir().unwrap();
+    write_test_product(tmp.path(), "TEST", &[("ア", &[(1, 0)])]);
+    let png = b"\x89PNG\r\n\x1a\n";
+    let items: &[(u32, &[u8])] = &[(3, png), (5, b"GIF89a"), (9, b"<svg/>")];
+    write_test_rsc(
+        &tmp.path().join("TEST/Contents/test/graphics"),
+        "graphics",
+        items,
+        2,
+    );
+
+    let dict = MonokakidoDict::open_with_dir("TEST", tmp.path().to_str()).unwrap();
+    assert!(dict.audio.is_none());
+    let graphics = dict.graphics.as_ref().unwrap();
+    assert_eq!(graphics.kind(), &MediaKind::Graphics);
+    assert_eq!(&graphics.get("5").unwrap()[..], b"GIF89a");
+    assert_eq!(graphics.get("4").unwrap_err().kind(), ErrorKind::NotFound);
+
+    let all: Vec<(String, Vec<u8>)> = graphics
+        .idx_iter()
+        .unwrap()
+        .map(|idx| {
+            let (id, data) = graphics.get_by_idx(idx).unwrap();
+            (id.to_string(), data.to_vec())
+        })
+        .collect();
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0], ("0000000003".to_owned(), png.to_vec()));
+    assert_eq!(all[2], ("0000000009".to_owned(), b"<svg/>".to_vec()));
+}
@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+
+use crate::{
+    resource::DEFAULT_CHUNK_CACHE_BYTES, Error, GaijiTable, Language, MonokakidoDict, Normalizer,
+    Profile,
+};
+
+/// How the index files (keystore, headline store, rsc and nrsc indexes) are kept in memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndexStorage {
+    /// Read the index files into owned buffers on open.
+    #[default]
+    Owned,
+    /// Map the index files into memory, so pages are loaded lazily by the OS.
+    /// Falls back to owned buffers for regions that are not suitably aligned.
+    Mmap,
+}
+
+/// Options for opening a dictionary, in the spirit of `std::fs::OpenOptions`.
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    pub(crate) storage: IndexStorage,
+    pub(crate) normalizer: Normalizer,
+    pub(crate) language: Option<Language>,
+    pub(crate) chunk_cache: Option<usize>,
+    pub(crate) profile: Option<Profile>,
+    pub(crate) gaiji: Option<GaijiTable>,
+}
+
+impl OpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn storage(&mut self, storage: IndexStorage) -> &mut Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Sets how key search queries are normalized. See [`Normalizer`].
+    pub fn normalizer(&mut self, normalizer: Normalizer) -> &mut Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Sets the language of the headwords, instead of taking it from the product metadata.
+    pub fn language(&mut self, language: Language) -> &mut Self {
+        self.language = Some(language);
+        self
+    }
+
+    /// Sets the profile used to parse entries, instead of the default one of the product,
+    /// which only knows the wrappers all products share.
+    pub fn profile(&mut self, profile: Profile) -> &mut Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Sets the gaiji mappings of the product, which are empty by default.
+    pub fn gaiji(&mut self, table: GaijiTable) -> &mut Self {
+        self.gaiji = Some(table);
+        self
+    }
+
+    /// Sets the size limit, in decompressed bytes, of the chunk cache of each rsc resource
+    /// (pages and media). Zero disables the cache.
+    /// Defaults to [`DEFAULT_CHUNK_CACHE_BYTES`].
+    pub fn chunk_cache(&mut self, bytes: usize) -> &mut Self {
+        self.chunk_cache = Some(bytes);
+        self
+    }
+
+    pub(crate) fn chunk_cache_limit(&self) -> usize {
+        self.chunk_cache.unwrap_or(DEFAULT_CHUNK_CACHE_BYTES)
+    }
+
+    pub fn open(&self, name: &str) -> Result<MonokakidoDict, Error> {
+        MonokakidoDict::open_with_options(name, None, self)
+    }
+
+    pub fn open_with_dir(
+        &self,
+        name: &str,
+        custom_dir: Option<&str>,
+    ) -> Result<MonokakidoDict, Error> {
+        MonokakidoDict::open_with_options(name, custom_dir, self)
+    }
+
+    pub fn open_with_path(&self, path: impl Into<PathBuf>) -> Result<MonokakidoDict, Error> {
+        MonokakidoDict::open_with_path_options(path, self)
+    }
+}
@@ -1,12 +1,68 @@
-use std::{ops::Range, path::PathBuf};
+use std::{fmt, ops::Deref, ops::Range, path::PathBuf, sync::OnceLock};
 
-use crate::{dict::Paths, resource::Rsc, Error, PageItemId};
+use crate::{
+    dict::Paths,
+    error::ResultExt,
+    resource::{Blob, CacheStats, Rsc},
+    Error, ErrorKind, FileFormat, OpenOptions, PageItemId,
+};
 
 const RSC_NAME: &str = "contents";
 
 pub struct Pages {
     path: PathBuf,
-    res: Option<Rsc>,
+    options: OpenOptions,
+    res: OnceLock<Rsc>,
+}
+
+/// The XML of a page or of a fragment of it. It shares the decompressed page data,
+/// so it can be kept around after other pages have been read.
+#[derive(Clone)]
+pub struct Xml {
+    /// Always valid UTF-8
+    blob: Blob,
+}
+
+impl Xml {
+    fn new(blob: Blob) -> Result<Self, Error> {
+        std::str::from_utf8(&blob).in_format(FileFormat::PageXml)?;
+        Ok(Xml { blob })
+    }
+
+    /// The fragment `part`, which must be a subslice of this XML.
+    fn slice(&self, part: &str) -> Self {
+        let start = part.as_ptr() as usize - self.as_ptr() as usize;
+        Xml {
+            blob: self.blob.slice(start..start + part.len()),
+        }
+    }
+}
+
+impl Deref for Xml {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        // SAFETY: validated in `Xml::new`, and only sliced at `str` boundaries since.
+        unsafe { std::str::from_utf8_unchecked(&self.blob) }
+    }
+}
+
+impl AsRef<str> for Xml {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl fmt::Display for Xml {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl fmt::Debug for Xml {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
 }
 
 pub struct XmlParser<'a> {
@@ -38,7 +94,7 @@ impl<'a> XmlParser<'a> {
 
         for token in &mut self.tokens {
             let mut popped = None;
-            let token = token?;
+            let token = token.in_format(FileFormat::PageXml)?;
             match token {
                 ElementStart { local, span, .. } => {
                     self.tag_stack.push((local.as_str(), span.start()));
@@ -58,7 +114,9 @@ impl<'a> XmlParser<'a> {
                     if Some(&*tag) == self.tag_stack.last().map(|(t, _)| *t) {
                         popped = self.tag_stack.pop().map(|(_, start)| (start, span.end()));
                     } else {
-                        return Err(Error::XmlError);
+                        return Err(Error::new(ErrorKind::XmlError)
+                            .at_offset(span.start() as u64)
+                            .in_format(FileFormat::PageXml));
                     }
                 }
                 ElementEnd { end: Empty, span } => {
@@ -79,83 +137,249 @@ impl<'a> XmlParser<'a> {
 }
 
 impl Pages {
-    pub fn new(paths: &Paths) -> Result<Self, Error> {
+    pub fn new(paths: &Paths, options: &OpenOptions) -> Result<Self, Error> {
         Ok(Pages {
             path: paths.contents_path().join(RSC_NAME),
-            res: None,
+            options: options.clone(),
+            res: OnceLock::new(),
         })
     }
 
-    pub fn init(&mut self) -> Result<(), Error> {
-        if self.res.is_none() {
-            self.res = Some(Rsc::new(&self.path, RSC_NAME)?);
+    pub fn init(&self) -> Result<(), Error> {
+        self.res().map(|_| ())
+    }
+
+    fn res(&self) -> Result<&Rsc, Error> {
+        if let Some(res) = self.res.get() {
+            return Ok(res);
         }
-        Ok(())
+        let res = Rsc::new_with_options(&self.path, RSC_NAME, &self.options)?;
+        // Another thread may have opened it meanwhile; either will do.
+        let _ = self.res.set(res);
+        Ok(self.res.get().unwrap())
     }
 
-    pub fn get_page(&mut self, id: PageItemId) -> Result<&str, Error> {
-        self.init()?;
-        let Some(res) = self.res.as_mut() else { unreachable!() };
-        let xml = std::str::from_utf8(res.get(id.page)?).map_err(|_| Error::Utf8Error)?;
-        Ok(xml)
+    pub fn get_page(&self, id: PageItemId) -> Result<Xml, Error> {
+        let page = self.res()?.get(id.page).with_path(&self.path)?;
+        Xml::new(page)
     }
 
-    pub fn get_item(&mut self, id: PageItemId) -> Result<&str, Error> {
+    pub fn get_item(&self, id: PageItemId) -> Result<Xml, Error> {
         let xml = self.get_page(id)?;
-        let mut parser = XmlParser::from(xml);
-        if id.item == 0 {
+        let mut parser = XmlParser::from(&xml);
+        let item = if id.item == 0 {
             parser.next_fragment_by(|tag| tag == "body", |_, _| false)
         } else {
             parser.next_fragment_by(
                 |_| false,
-                |name, value| {
-                    if name == "id" {
-                        if let Some((page, item)) = value.split_once('-') {
-                            if page.parse() == Ok(id.page) && item.parse() == Ok(id.item) {
-                                return true;
-                            }
-                        }
-                    }
-                    false
-                },
+                |name, value| name == "id" && parse_item_id(value) == Some(id),
             )
         }?
-        .ok_or(Error::XmlError)
+        .ok_or(ErrorKind::XmlError)?;
+        Ok(xml.slice(item))
+    }
+
+    /// The items of the page `page`: the elements with an `id` of the page, in document
+    /// order, with their span in the XML returned by [`get_page`](Self::get_page).
+    pub fn items(&self, page: u32) -> Result<Vec<(PageItemId, Range<usize>)>, Error> {
+        let xml = self.get_page(PageItemId { page, item: 0 })?;
+        item_spans(page, &xml)
     }
 
-    pub fn get_item_audio(&mut self, id: PageItemId) -> Result<AudioIter, Error> {
+    /// An iterator over the items of all pages, in page order, with their XML.
+    pub fn all_items(&self) -> Result<ItemIter<'_>, Error> {
+        Ok(ItemIter {
+            pages: self,
+            idx: self.idx_iter()?,
+            page: None,
+            items: Vec::new().into_iter(),
+        })
+    }
+
+    pub fn get_item_audio(&self, id: PageItemId) -> Result<AudioIter, Error> {
         let xml = self.get_item(id)?;
-        let parser = XmlParser::from(xml);
-        Ok(AudioIter { parser })
+        let mut parser = XmlParser::from(&xml);
+        let mut audio = Vec::new();
+        loop {
+            let next = parser.next_fragment_by(
+                |_| false,
+                |name, value| name == "href" && value.ends_with(".aac"),
+            );
+            match next {
+                Ok(Some(fragment)) => audio.push(Ok(xml.slice(fragment))),
+                Ok(None) => break,
+                Err(e) => {
+                    audio.push(Err(e));
+                    break;
+                }
+            }
+        }
+        Ok(AudioIter {
+            audio: audio.into_iter(),
+        })
     }
 
-    pub fn page_by_idx(&mut self, idx: usize) -> Result<(u32, &str), Error> {
-        self.init()?;
-        let Some(res) = self.res.as_mut() else { unreachable!() };
-        let (id, page) = res.get_by_idx(idx)?;
-        Ok((id, std::str::from_utf8(page).map_err(|_| Error::Utf8Error)?))
+    pub fn page_by_idx(&self, idx: usize) -> Result<(u32, Xml), Error> {
+        let (id, page) = self.res()?.get_by_idx(idx).with_path(&self.path)?;
+        Ok((id, Xml::new(page).at_record(idx)?))
     }
 
-    pub fn idx_iter(&mut self) -> Result<Range<usize>, Error> {
-        self.init()?;
-        let Some(res) = self.res.as_ref() else { unreachable!() };
-        Ok(0..res.len())
+    pub fn idx_iter(&self) -> Result<Range<usize>, Error> {
+        Ok(0..self.res()?.len())
     }
+
+    /// Counters of the decompressed chunk cache. All zero until the pages are first read.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.res.get().map(Rsc::cache_stats).unwrap_or_default()
+    }
+}
+
+/// Parses the value of an item's `id` attribute, which has the form `<page>-<item>`.
+pub(crate) fn parse_item_id(value: &str) -> Option<PageItemId> {
+    let (page, item) = value.split_once('-')?;
+    Some(PageItemId {
+        page: page.parse().ok()?,
+        item: item.parse().ok()?,
+    })
+}
+
+/// Returns the span of each element of `xml` with an `id` of the page `page`, in the order
+/// the elements start.
+fn item_spans(page: u32, xml: &str) -> Result<Vec<(PageItemId, Range<usize>)>, Error> {
+    use xmlparser::{ElementEnd, Token};
+
+    let mut items = Vec::new();
+    // The start and the item ID of each open element
+    let mut stack: Vec<(usize, Option<PageItemId>)> = Vec::new();
+    for token in xmlparser::Tokenizer::from(xml) {
+        match token.in_format(FileFormat::PageXml)? {
+            Token::ElementStart { span, .. } => stack.push((span.start(), None)),
+            Token::Attribute { local, value, .. } if local.as_str() == "id" => {
+                if let Some((_, id)) = stack.last_mut() {
+                    *id = parse_item_id(&value).filter(|id| id.page == page);
+                }
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(..) | ElementEnd::Empty,
+                span,
+            } => {
+                let (start, id) = stack.pop().ok_or_else(|| {
+                    Error::new(ErrorKind::XmlError)
+                        .at_offset(span.start() as u64)
+                        .in_format(FileFormat::PageXml)
+                })?;
+                items.extend(id.map(|id| (id, start..span.end())));
+            }
+            _ => {}
+        }
+    }
+    // Elements end inner first.
+    items.sort_by_key(|(_, span)| span.start);
+    Ok(items)
 }
 
-pub struct AudioIter<'a> {
-    parser: XmlParser<'a>,
+/// An iterator over the items of all pages. See [`Pages::all_items`].
+pub struct ItemIter<'a> {
+    pages: &'a Pages,
+    idx: Range<usize>,
+    /// The page being iterated
+    page: Option<Xml>,
+    /// The items of the page left
+    items: std::vec::IntoIter<(PageItemId, Range<usize>)>,
 }
 
-impl<'a> Iterator for AudioIter<'a> {
-    type Item = Result<&'a str, Error>;
+impl Iterator for ItemIter<'_> {
+    type Item = Result<(PageItemId, Xml), Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.parser
-            .next_fragment_by(
-                |_| false,
-                |name, value| name == "href" && value.ends_with(".aac"),
-            )
-            .transpose()
+        loop {
+            if let Some(xml) = &self.page {
+                if let Some((id, span)) = self.items.next() {
+                    return Some(Ok((id, xml.slice(&xml[span]))));
+                }
+            }
+            let idx = self.idx.next()?;
+            let page = self.pages.page_by_idx(idx).and_then(|(page, xml)| {
+                let items = item_spans(page, &xml).at_record(idx)?;
+                Ok((xml, items))
+            });
+            match page {
+                Ok((xml, items)) => {
+                    self.page = Some(xml);
+                    self.items = items.into_iter();
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// The audio fragments of an item.
+pub struct AudioIter {
+    audio: std::vec::IntoIter<Result<Xml, Error>>,
+}
+
+impl Iterator for AudioIter {
+    type Item = Result<Xml, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.audio.next()
+    }
+}
+
+#[test]
+fn test_parse_item_id() {
+    assert_eq!(
+        parse_item_id("123-4"),
+        Some(PageItemId { page: 123, item: 4 })
+    );
+    assert_eq!(parse_item_id("123"), None);
+    assert_eq!(parse_item_id("123-"), None);
+    assert_eq!(parse_item_id("a-1"), None);
+    assert_eq!(parse_item_id("1-256"), None);
+}
+
+#[test]
+fn test_page_items() {
+    use crate::{
+        dict::{write_test_pages, write_test_product},
+        MonokakidoDict,
+    };
+
+    let page1 = r##"<d><body><div id="1-1">a<div id="1-2">b</div></div><a href="#2-1" id="2-1"/><p id="1-3"/></body></d>"##;
+    let page2 = "<d><body><span>c</span></body></d>";
+    let page3 = r#"<d><body><div id="3-1">d</div></body></d>"#;
+    let tmp = tempfile::tempdir().unwrap();
+    write_test_product(tmp.path(), "TEST", &[("ア", &[(1, 1)])]);
+    write_test_pages(tmp.path(), "TEST", &[(1, page1), (2, page2), (3, page3)]);
+    let dict = MonokakidoDict::open_with_dir("TEST", tmp.path().to_str()).unwrap();
+
+    let id = |page, item| PageItemId { page, item };
+    let items = dict.pages.items(1).unwrap();
+    let ids: Vec<PageItemId> = items.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, [id(1, 1), id(1, 2), id(1, 3)]);
+    assert_eq!(&page1[items[1].1.clone()], r#"<div id="1-2">b</div>"#);
+    assert_eq!(&page1[items[2].1.clone()], r#"<p id="1-3"/>"#);
+    assert!(dict.pages.items(2).unwrap().is_empty());
+    assert!(dict.pages.items(4).is_err());
+
+    let all: Vec<(PageItemId, Xml)> = dict
+        .pages
+        .all_items()
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let all: Vec<(PageItemId, &str)> = all.iter().map(|(id, xml)| (*id, &**xml)).collect();
+    assert_eq!(
+        all,
+        [
+            (id(1, 1), r#"<div id="1-1">a<div id="1-2">b</div></div>"#),
+            (id(1, 2), r#"<div id="1-2">b</div>"#),
+            (id(1, 3), r#"<p id="1-3"/>"#),
+            (id(3, 1), r#"<div id="3-1">d</div>"#),
+        ]
+    );
+    for (id, xml) in all {
+        assert_eq!(&*dict.pages.get_item(id).unwrap(), xml);
     }
 }
@@ -0,0 +1,7 @@
+//! Rendering of entries for display.
+
+mod html;
+mod text;
+
+pub use html::{HtmlRenderer, StyleFamily};
+pub use text::TextRenderer;
@@ -0,0 +1,463 @@
+use std::fmt;
+
+use crate::{
+    entry::{escape, parse_fragment, Element, MediaRef, Node, Profile, Role},
+    links::percent_encode,
+    pages::parse_item_id,
+    Error, GaijiRef, GaijiTable, Glyph, Keys, LinkTarget, PageItemId,
+};
+
+/// A family of products that share a default stylesheet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StyleFamily {
+    /// Japanese monolingual dictionaries
+    Kokugo,
+    /// English-Japanese dictionaries
+    Bilingual,
+    Default,
+}
+
+const FAMILIES: &[(&str, StyleFamily)] = &[
+    ("SMK", StyleFamily::Kokugo),
+    ("DAIJISEN", StyleFamily::Kokugo),
+    ("NHKACCENT", StyleFamily::Kokugo),
+    ("WISDOM", StyleFamily::Bilingual),
+    ("RHEJ", StyleFamily::Bilingual),
+];
+
+impl StyleFamily {
+    pub fn for_product(product: &str) -> Self {
+        let upper = product.to_ascii_uppercase();
+        FAMILIES
+            .iter()
+            .find(|(prefix, _)| upper.starts_with(prefix))
+            .map_or(StyleFamily::Default, |(_, family)| *family)
+    }
+
+    /// The default stylesheet of the family.
+    ///
+    /// It styles the markup of any product: the typeface of the family, links, audio,
+    /// images, gaiji and ruby. Its rules for the parts of entries, such as `.headword`,
+    /// only apply with a [`Profile`] that maps the product's elements to roles, as the
+    /// default profile of a product doesn't.
+    pub fn stylesheet(self) -> &'static str {
+        match self {
+            StyleFamily::Kokugo => include_str!("css/kokugo.css"),
+            StyleFamily::Bilingual => include_str!("css/bilingual.css"),
+            StyleFamily::Default => include_str!("css/default.css"),
+        }
+    }
+}
+
+// HTML elements that are passed through as they are.
+const HTML_TAGS: &[&str] = &[
+    "a", "b", "br", "div", "em", "hr", "i", "img", "p", "rb", "rp", "rt", "ruby", "small", "span",
+    "strong", "sub", "sup", "table", "tbody", "td", "th", "thead", "tr", "u", "wbr",
+];
+const VOID_TAGS: &[&str] = &["br", "hr", "img", "wbr"];
+// Attributes kept on the rendered elements, besides the rewritten ones.
+const KEPT_ATTRS: &[&str] = &[
+    "lang", "title", "alt", "width", "height", "colspan", "rowspan",
+];
+
+/// Renders the page XML of entries to HTML.
+///
+/// Elements with a [`Role`] in the product's [`Profile`] become semantic HTML with a class
+/// named after the role, such as `<dfn class="headword">`. Other custom elements become
+/// `span`s with a class named after the element. Gaiji are replaced by their Unicode
+/// mapping, or else shown as `<img class="gaiji">`, or as `〓` if they have no image.
+///
+/// Links are read as [`LinkTarget`]s: links to items of the product and to its headwords
+/// become `<a class="xref">` anchors, and the others are kept as they are.
+#[derive(Clone)]
+pub struct HtmlRenderer<'k> {
+    profile: Profile,
+    family: StyleFamily,
+    gaiji: GaijiTable,
+    media_url: String,
+    entry_url: String,
+    keyword_url: String,
+    keys: Option<&'k Keys>,
+}
+
+impl fmt::Debug for HtmlRenderer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HtmlRenderer")
+            .field("profile", &self.profile)
+            .field("family", &self.family)
+            .field("gaiji", &self.gaiji)
+            .field("media_url", &self.media_url)
+            .field("entry_url", &self.entry_url)
+            .field("keyword_url", &self.keyword_url)
+            .field("keys", &self.keys.is_some())
+            .finish()
+    }
+}
+
+impl<'k> HtmlRenderer<'k> {
+    /// A renderer for the product of `profile`. Media links point to `{kind}/{href}`,
+    /// the layout of `monokakido-explode`, and entry links to anchors in the same document.
+    pub fn new(profile: &Profile) -> Self {
+        HtmlRenderer {
+            profile: profile.clone(),
+            family: StyleFamily::for_product(profile.name()),
+            gaiji: GaijiTable::new(),
+            media_url: "{kind}/{href}".to_owned(),
+            entry_url: "#{page}-{item}".to_owned(),
+            keyword_url: "#key:{word}".to_owned(),
+            keys: None,
+        }
+    }
+
+    /// Sets the URL template of media links. `{kind}` is replaced by the media directory
+    /// name, such as `audio`, and `{href}` by the path in the page XML, percent-encoded.
+    pub fn media_url(&mut self, template: &str) -> &mut Self {
+        self.media_url = template.to_owned();
+        self
+    }
+
+    /// Sets the URL template of links to other entries, with `{page}` and `{item}`
+    /// replaced by the parts of the item ID.
+    pub fn entry_url(&mut self, template: &str) -> &mut Self {
+        self.entry_url = template.to_owned();
+        self
+    }
+
+    /// Sets the URL template of links to headwords that aren't resolved to an item, with
+    /// `{word}` replaced by the percent-encoded headword.
+    pub fn keyword_url(&mut self, template: &str) -> &mut Self {
+        self.keyword_url = template.to_owned();
+        self
+    }
+
+    /// Resolves links to headwords with the keystore of the product, so that they link
+    /// to the entry of the headword like links to items do.
+    pub fn keys(&mut self, keys: &'k Keys) -> &mut Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Sets the gaiji mappings, which are empty by default.
+    pub fn gaiji(&mut self, table: GaijiTable) -> &mut Self {
+        self.gaiji = table;
+        self
+    }
+
+    pub fn style_family(&mut self, family: StyleFamily) -> &mut Self {
+        self.family = family;
+        self
+    }
+
+    pub fn stylesheet(&self) -> &'static str {
+        self.family.stylesheet()
+    }
+
+    /// Renders the XML of a page or an item as an `<article class="entry">`.
+    pub fn render(&self, xml: &str) -> Result<String, Error> {
+        let mut out = String::from(r#"<article class="entry">"#);
+        for node in parse_fragment(xml)? {
+            self.node(&node, &mut out)?;
+        }
+        out.push_str("</article>");
+        Ok(out)
+    }
+
+    /// Renders a standalone HTML document, with the stylesheet inlined.
+    pub fn render_document<S: AsRef<str>>(
+        &self,
+        title: &str,
+        entries: impl IntoIterator<Item = S>,
+    ) -> Result<String, Error> {
+        let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str(&format!("<title>{}</title>\n", escape(title, false)));
+        out.push_str(&format!("<style>\n{}</style>\n", self.stylesheet()));
+        out.push_str("</head>\n<body>\n");
+        for xml in entries {
+            out.push_str(&self.render(xml.as_ref())?);
+            out.push('\n');
+        }
+        out.push_str("</body>\n</html>\n");
+        Ok(out)
+    }
+
+    fn node(&self, node: &Node, out: &mut String) -> Result<(), Error> {
+        match node {
+            Node::Element(element) => self.element(element, out)?,
+            Node::Text(text) => out.push_str(&escape(&self.gaiji.replace_text(text), false)),
+        }
+        Ok(())
+    }
+
+    fn element(&self, element: &Element, out: &mut String) -> Result<(), Error> {
+        if let Some(gaiji) = GaijiRef::from_element(element) {
+            self.glyph(&gaiji, out);
+            return Ok(());
+        }
+        let local = element.local_name();
+        let is_html = HTML_TAGS.contains(&local);
+        let role = self.profile.role(element);
+        if role == Some(Role::Ignore) && !is_html {
+            return Ok(());
+        }
+        let role = role.filter(|role| *role != Role::Ignore);
+
+        let media = ["href", "src"]
+            .into_iter()
+            .find_map(|name| MediaRef::from_href(element.attr(name)?));
+        // Links to other entries, and external links
+        let link = match element.attr("href").filter(|_| media.is_none()) {
+            Some(href) => Some(self.link(href)?),
+            None => None,
+        };
+
+        let (tag, role_class) = match role {
+            _ if media.is_some() && local != "img" => ("a", None),
+            Some(role) => role_html(role, local, link.is_some()),
+            None if is_html => (local, None),
+            // Links in markup the profile doesn't know still link.
+            None if link.is_some() => ("a", None),
+            None => ("span", None),
+        };
+
+        let mut classes: Vec<&str> = Vec::new();
+        classes.extend(role_class);
+        classes.extend(media.as_ref().map(|media| media.kind.dir_name()));
+        if link.as_ref().is_some_and(|(internal, _)| *internal) {
+            classes.push("xref");
+        }
+        if !is_html {
+            classes.push(local);
+        }
+        classes.extend(
+            element
+                .attr("class")
+                .into_iter()
+                .flat_map(str::split_whitespace),
+        );
+        classes.dedup();
+
+        out.push('<');
+        out.push_str(tag);
+        if let Some(id) = element.attr("id") {
+            let id =
+                parse_item_id(id).map_or(id.to_owned(), |id| format!("{}-{}", id.page, id.item));
+            push_attr(out, "id", &id);
+        }
+        if !classes.is_empty() {
+            push_attr(out, "class", &classes.join(" "));
+        }
+        if let Some(media) = &media {
+            let name = if tag == "img" { "src" } else { "href" };
+            push_attr(out, name, &self.media_link(media));
+        } else if let Some((_, href)) = link.as_ref().filter(|_| tag == "a") {
+            push_attr(out, "href", href);
+        }
+        for (name, value) in &element.attrs {
+            if KEPT_ATTRS.contains(&name.as_str()) {
+                push_attr(out, name, value);
+            }
+        }
+        out.push('>');
+        if VOID_TAGS.contains(&tag) {
+            return Ok(());
+        }
+        for child in &element.children {
+            self.node(child, out)?;
+        }
+        out.push_str("</");
+        out.push_str(tag);
+        out.push('>');
+        Ok(())
+    }
+
+    /// The URL of a link, and whether it points within the product.
+    fn link(&self, href: &str) -> Result<(bool, String), Error> {
+        let Some(target) = LinkTarget::parse(href) else {
+            return Ok((false, href.to_owned()));
+        };
+        let id = match (&target, self.keys) {
+            (LinkTarget::Item(id), _) => Some(*id),
+            (LinkTarget::Keyword(_), Some(keys)) => target.resolve(keys)?,
+            _ => None,
+        };
+        Ok(match (id, &target) {
+            (Some(id), _) => (true, self.entry_link(id)),
+            (None, LinkTarget::Keyword(word)) => (
+                true,
+                self.keyword_url.replace("{word}", &percent_encode(word)),
+            ),
+            (None, _) => (false, href.to_owned()),
+        })
+    }
+
+    fn glyph(&self, gaiji: &GaijiRef, out: &mut String) {
+        match self.gaiji.glyph(gaiji) {
+            Glyph::Text(text) => out.push_str(&escape(text, false)),
+            Glyph::Image(media) => {
+                out.push_str(r#"<img class="gaiji""#);
+                push_attr(out, "src", &self.media_link(media));
+                push_attr(out, "alt", &gaiji.key());
+                out.push('>');
+            }
+            Glyph::Unknown(c) => out.push(c),
+            Glyph::Missing => {
+                out.push_str(r#"<span class="gaiji""#);
+                push_attr(out, "title", &gaiji.key());
+                out.push_str(">〓</span>");
+            }
+        }
+    }
+
+    fn media_link(&self, media: &MediaRef) -> String {
+        self.media_url
+            .replace("{kind}", media.kind.dir_name())
+            .replace("{href}", &percent_encode(&media.href))
+    }
+
+    fn entry_link(&self, id: PageItemId) -> String {
+        self.entry_url
+            .replace("{page}", &id.page.to_string())
+            .replace("{item}", &id.item.to_string())
+    }
+}
+
+/// The HTML element and class of an element with `role`.
+fn role_html(role: Role, local: &str, has_link: bool) -> (&str, Option<&'static str>) {
+    match role {
+        Role::Container if HTML_TAGS.contains(&local) && local != "a" => (local, None),
+        Role::Container | Role::Ignore => ("div", None),
+        Role::Headword => ("dfn", Some("headword")),
+        Role::Reading => ("span", Some("reading")),
+        Role::PitchAccent => ("span", Some("accent")),
+        Role::PartOfSpeech => ("span", Some("pos")),
+        Role::Sense => ("div", Some("sense")),
+        Role::SenseNumber => ("span", Some("sense-number")),
+        Role::Definition => ("span", Some("definition")),
+        Role::Example => ("span", Some("example")),
+        Role::Translation => ("span", Some("translation")),
+        Role::Idiom => ("div", Some("idiom")),
+        Role::IdiomPhrase => ("b", Some("idiom-phrase")),
+        // Only links within the product are marked as cross-references.
+        Role::CrossRef if has_link => ("a", None),
+        Role::CrossRef => ("span", Some("xref")),
+    }
+}
+
+fn push_attr(out: &mut String, name: &str, value: &str) {
+    out.push(' ');
+    out.push_str(name);
+    out.push_str("=\"");
+    out.push_str(&escape(value, true));
+    out.push('"');
+}
+
+#[test]
+fn test_render_html() {
+    let xml = r#"<entry id="12-0"><head><midashi>あい</midashi><accent>1</accent><a href="12-0.aac">♪</a></head><meaning class="m"><number>1</number><imi>A &amp; B<br/></imi><ref href="99-1">恋</ref><img src="ai.png" alt="愛"/><custom x="1">?</custom><a href="https://example.com">web</a></meaning></entry>"#;
+    let profile = Profile::fixture("SMK8");
+    let mut renderer = HtmlRenderer::new(&profile);
+    assert_eq!(
+        renderer.render(xml).unwrap(),
+        concat!(
+            r#"<article class="entry"><div id="12-0" class="entry"><div class="head">"#,
+            r#"<dfn class="headword midashi">あい</dfn><span class="accent">1</span>"#,
+            r#"<a class="audio" href="audio/12-0.aac">♪</a></div>"#,
+            r#"<div class="sense meaning m"><span class="sense-number number">1</span>"#,
+            r#"<span class="definition imi">A &amp; B<br></span>"#,
+            r##"<a class="xref ref" href="#99-1">恋</a>"##,
+            r#"<img class="graphics" src="graphics/ai.png" alt="愛">"#,
+            r#"<span class="custom">?</span>"#,
+            r#"<a href="https://example.com">web</a></div></div></article>"#,
+        )
+    );
+    assert_eq!(renderer.stylesheet(), StyleFamily::Kokugo.stylesheet());
+    assert_eq!(StyleFamily::for_product("wisdom3"), StyleFamily::Bilingual);
+
+    // With the default profile, links and media are still marked up for the stylesheets.
+    let html = HtmlRenderer::new(&Profile::for_product("SMK8"))
+        .render(xml)
+        .unwrap();
+    assert!(
+        html.contains(r##"<a class="xref ref" href="#99-1">恋</a>"##),
+        "{html}"
+    );
+    assert!(html.contains(r#"<a class="audio" href="audio/12-0.aac">"#));
+    for family in [
+        StyleFamily::Kokugo,
+        StyleFamily::Bilingual,
+        StyleFamily::Default,
+    ] {
+        assert!(family.stylesheet().contains("a.xref"));
+        assert!(family.stylesheet().contains("a.audio"));
+    }
+    // A monolingual English dictionary
+    assert_eq!(StyleFamily::for_product("OALD10"), StyleFamily::Default);
+
+    renderer
+        .media_url("media://{kind}/{href}")
+        .entry_url("entry://{page}/{item}")
+        .style_family(StyleFamily::Default);
+    let html = renderer.render(xml).unwrap();
+    assert!(html.contains(r#"href="media://audio/12-0.aac""#));
+    assert!(html.contains(r#"href="entry://99/1""#));
+
+    let doc = renderer.render_document("<愛>", [xml, xml]).unwrap();
+    assert!(doc.starts_with("<!DOCTYPE html>"));
+    assert!(doc.contains("<title>&lt;愛&gt;</title>"));
+    assert!(doc.contains(StyleFamily::Default.stylesheet()));
+    assert_eq!(doc.matches("<article").count(), 2);
+    assert!(renderer.render("<a>").is_err());
+}
+
+#[test]
+fn test_render_gaiji() {
+    let xml = "<p>\u{e002}<gaiji name='g1'/><img class='gaiji' src='gaiji/g2.png'/>\u{e0ff}</p>";
+    let image = r#"<img class="gaiji" src="graphics/gaiji/g2.png" alt="g2">"#;
+    let mut renderer = HtmlRenderer::new(&Profile::fixture("SMK8"));
+    assert_eq!(
+        renderer.render(xml).unwrap(),
+        format!(
+            "<article class=\"entry\"><p>\u{e002}<span class=\"gaiji\" title=\"g1\">〓</span>\
+             {image}\u{e0ff}</p></article>"
+        )
+    );
+
+    let mut table = GaijiTable::new();
+    table.insert("g1", "𠀋").insert("U+E002", "⇔");
+    renderer.gaiji(table);
+    assert_eq!(
+        renderer.render(xml).unwrap(),
+        format!("<article class=\"entry\"><p>⇔𠀋{image}\u{e0ff}</p></article>")
+    );
+}
+
+#[test]
+fn test_render_links() {
+    let xml = r#"<p><ref href="key:愛">愛</ref><ref href="key:恋">恋</ref><ref href="12">x</ref><a href="OTHER:1-1">y</a></p>"#;
+    let profile = Profile::fixture("SMK8");
+    let mut renderer = HtmlRenderer::new(&profile);
+    assert_eq!(
+        renderer.render(xml).unwrap(),
+        concat!(
+            r##"<article class="entry"><p><a class="xref ref" href="#key:%E6%84%9B">愛</a>"##,
+            r##"<a class="xref ref" href="#key:%E6%81%8B">恋</a>"##,
+            r##"<a class="xref ref" href="#12-0">x</a>"##,
+            r#"<a href="OTHER:1-1">y</a></p></article>"#,
+        )
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("headword.keystore");
+    std::fs::write(&path, crate::key::build_keystore(&[("愛", &[(7, 2)])])).unwrap();
+    let keys = Keys::new(&path).unwrap();
+    renderer.keys(&keys).keyword_url("?key={word}");
+    let html = renderer.render(xml).unwrap();
+    assert!(html.contains(r##"href="#7-2">愛"##));
+    assert!(html.contains(r#"href="?key=%E6%81%8B">恋"#));
+
+    let html = renderer.render(r#"<img src="a b/愛.png"/>"#).unwrap();
+    assert!(
+        html.contains(r#"src="graphics/a%20b/%E6%84%9B.png""#),
+        "{html}"
+    );
+}
@@ -0,0 +1,542 @@
+use std::io::IsTerminal;
+
+use terminal_size::Width;
+
+use crate::{
+    entry::{parse_fragment, Element, MediaRef, Node, Profile, Role},
+    Error, GaijiRef, GaijiTable, Glyph, MediaKind,
+};
+
+const DEFAULT_WIDTH: usize = 80;
+/// Columns each level of senses is indented by.
+const INDENT: usize = 2;
+
+// Characters that a line can't start with, or end with.
+const NO_BREAK_BEFORE: &str =
+    "、。，．・：；？！ー）」』】〕〉》ゝゞ々ぁぃぅぇぉっゃゅょァィゥェォッャュョ,.:;?!)]}";
+const NO_BREAK_AFTER: &str = "（「『【〔〈《([{";
+
+/// Renders the page XML of entries as plain text, or as text with ANSI escapes for
+/// terminals.
+///
+/// The fields of the head are separated as in `あい【愛】 1`, senses are indented by their
+/// depth, ruby is written as `漢字(かんじ)`, and audio and images are replaced by
+/// placeholders. Gaiji are replaced by their Unicode mapping, or else by `〓`.
+#[derive(Debug, Clone)]
+pub struct TextRenderer {
+    profile: Profile,
+    gaiji: GaijiTable,
+    width: usize,
+    ansi: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    dim: bool,
+    color: Option<u8>,
+}
+
+impl Style {
+    fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    /// The SGR escape sequence that switches from plain text to this style.
+    fn sgr(&self) -> String {
+        let mut codes = Vec::new();
+        for (on, code) in [
+            (self.bold, 1),
+            (self.dim, 2),
+            (self.italic, 3),
+            (self.underline, 4),
+        ] {
+            if on {
+                codes.push(code.to_string());
+            }
+        }
+        codes.extend(self.color.map(|c| c.to_string()));
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+/// A paragraph, wrapped on its own.
+#[derive(Default)]
+struct Block {
+    indent: usize,
+    /// Extra indentation of the lines after the first one
+    hang: usize,
+    text: Vec<(char, Style)>,
+}
+
+struct Walker<'a> {
+    profile: &'a Profile,
+    gaiji: &'a GaijiTable,
+    blocks: Vec<Block>,
+    indent: usize,
+    sense_depth: usize,
+    /// The last head field rendered, and where its text ended.
+    head_end: Option<HeadEnd>,
+}
+
+#[derive(Clone, Copy)]
+struct HeadEnd {
+    role: Role,
+    block: usize,
+    len: usize,
+}
+
+impl Walker<'_> {
+    fn current(&mut self) -> &mut Block {
+        if self.blocks.is_empty() {
+            self.blocks.push(Block::default());
+        }
+        self.blocks.last_mut().unwrap()
+    }
+
+    /// Starts a new paragraph at the current indentation.
+    fn break_block(&mut self) {
+        let indent = self.indent;
+        match self.blocks.last_mut() {
+            Some(block) if block.text.iter().all(|(c, _)| c.is_whitespace()) => {
+                block.text.clear();
+                block.indent = indent;
+                block.hang = 0;
+            }
+            _ => self.blocks.push(Block {
+                indent,
+                ..Block::default()
+            }),
+        }
+    }
+
+    /// The role of the head field that the text so far ends with, if any.
+    fn previous_head(&self) -> Option<Role> {
+        let end = self.head_end?;
+        let block = self.blocks.last()?;
+        let after = block.text.get(end.len..)?;
+        (self.blocks.len() == end.block && after.iter().all(|(c, _)| c.is_whitespace()))
+            .then_some(end.role)
+    }
+
+    fn push_str(&mut self, text: &str, style: Style) {
+        let block = self.current();
+        block.text.extend(text.chars().map(|c| (c, style)));
+    }
+
+    /// Pushes text from the XML, with its gaiji replaced.
+    fn text(&mut self, text: &str, style: Style) {
+        let text = self.gaiji.replace_text(text);
+        self.push_str(&text, style);
+    }
+
+    fn nodes(&mut self, nodes: &[Node], style: Style) {
+        for node in nodes {
+            match node {
+                Node::Element(element) => self.element(element, style),
+                Node::Text(text) => self.text(text, style),
+            }
+        }
+    }
+
+    /// Renders `element` in its own paragraphs, indented by `extra` more columns.
+    fn block(&mut self, element: &Element, style: Style, extra: usize) {
+        self.indent += extra;
+        self.break_block();
+        self.nodes(&element.children, style);
+        self.indent -= extra;
+        self.break_block();
+    }
+
+    fn element(&mut self, element: &Element, style: Style) {
+        if let Some(gaiji) = GaijiRef::from_element(element) {
+            return match self.gaiji.glyph(&gaiji) {
+                Glyph::Text(text) => self.push_str(text, style),
+                Glyph::Image(_) | Glyph::Missing => {
+                    self.push_str("〓", Style { dim: true, ..style })
+                }
+                Glyph::Unknown(c) => self.push_str(c.encode_utf8(&mut [0; 4]), style),
+            };
+        }
+        let local = element.local_name();
+        if let Some(media) = ["href", "src"]
+            .into_iter()
+            .find_map(|name| MediaRef::from_href(element.attr(name)?))
+        {
+            let placeholder = match (media.kind, element.attr("alt")) {
+                (MediaKind::Audio, _) => "[♪]".to_owned(),
+                (_, Some(alt)) => format!("[image: {alt}]"),
+                (_, None) => format!("[image: {}]", media.href),
+            };
+            if self.previous_head().is_some() {
+                self.push_str(" ", style);
+            }
+            let style = Style { dim: true, ..style };
+            return self.push_str(&placeholder, style);
+        }
+
+        let role = self.profile.role(element);
+        // Head fields are separated like the kokugo stylesheet does: further headwords
+        // are bracketed, as in `あい【愛】`, and the other fields follow a space.
+        let head = match role {
+            Some(
+                role @ (Role::Headword | Role::Reading | Role::PitchAccent | Role::PartOfSpeech),
+            ) => Some(role),
+            _ => None,
+        };
+        let mut closing = None;
+        if let Some(role) = head {
+            match self.previous_head() {
+                Some(Role::Headword) if role == Role::Headword => {
+                    self.push_str("【", style);
+                    closing = Some("】");
+                }
+                Some(_) => self.push_str(" ", style),
+                None => {}
+            }
+        }
+        let mut inner = style;
+        match role {
+            Some(Role::Ignore) if local == "br" => return self.break_block(),
+            Some(Role::Ignore) => return,
+            Some(Role::Headword | Role::IdiomPhrase) => inner.bold = true,
+            Some(Role::Reading | Role::PitchAccent) => inner.color = Some(36),
+            Some(Role::PartOfSpeech) => inner.color = Some(33),
+            Some(Role::SenseNumber) => {
+                inner.bold = true;
+                inner.color = Some(31);
+                // Continuation lines of the sense align after its number.
+                let text = element.text();
+                let number = text.trim();
+                let block = self.current();
+                if block.text.iter().all(|(c, _)| c.is_whitespace()) {
+                    block.hang = number.chars().map(char_width).sum::<usize>() + 1;
+                }
+                self.push_str(number, inner);
+                return self.push_str(" ", style);
+            }
+            Some(Role::Translation) => inner.dim = true,
+            Some(Role::CrossRef) => {
+                inner.underline = true;
+                inner.color = Some(34);
+            }
+            Some(Role::Sense) => {
+                // Top-level senses start at the margin, with their number.
+                let extra = if self.sense_depth > 0 { INDENT } else { 0 };
+                self.sense_depth += 1;
+                self.block(element, style, extra);
+                self.sense_depth -= 1;
+                return;
+            }
+            Some(Role::Example) => return self.block(element, style, INDENT),
+            Some(Role::Idiom) => return self.block(element, style, 0),
+            Some(Role::Container | Role::Definition) | None => {}
+        }
+        match local {
+            "b" | "strong" => inner.bold = true,
+            "i" | "em" => inner.italic = true,
+            "u" => inner.underline = true,
+            "rp" => return,
+            "ruby" => return self.ruby(element, inner),
+            "br" => return self.break_block(),
+            "p" | "div" | "tr" | "hr" => return self.block(element, inner, 0),
+            _ => {}
+        }
+        self.nodes(&element.children, inner);
+        if let Some(closing) = closing {
+            self.push_str(closing, style);
+        }
+        if let Some(role) = head {
+            self.head_end = Some(HeadEnd {
+                role,
+                block: self.blocks.len(),
+                len: self.current().text.len(),
+            });
+        }
+    }
+
+    fn ruby(&mut self, element: &Element, style: Style) {
+        let mut readings = String::new();
+        for node in &element.children {
+            match node {
+                Node::Element(e) if e.local_name() == "rt" => {
+                    readings.push_str(&self.gaiji.replace_text(&e.text()))
+                }
+                Node::Element(e) if e.local_name() == "rp" => {}
+                Node::Element(e) => self.element(e, style),
+                Node::Text(text) => self.text(text, style),
+            }
+        }
+        if !readings.is_empty() {
+            self.push_str(&format!("({readings})"), style);
+        }
+    }
+}
+
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0x3099..=0x309A => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Splits text into the units that lines can be broken between: words, and single
+/// CJK characters, with punctuation kept on the right side of the break.
+/// Each unit records whether a space preceded it.
+fn units(text: &[(char, Style)]) -> Vec<(bool, Vec<(char, Style)>)> {
+    let mut units: Vec<(bool, Vec<(char, Style)>)> = Vec::new();
+    let mut space = false;
+    for &(c, style) in text {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        let breakable = match units.last().and_then(|(_, unit)| unit.last()) {
+            None => true,
+            Some(_) if space => true,
+            Some(&(last, _)) => {
+                (char_width(c) == 2 || char_width(last) == 2)
+                    && !NO_BREAK_BEFORE.contains(c)
+                    && !NO_BREAK_AFTER.contains(last)
+            }
+        };
+        if breakable {
+            units.push((space, Vec::new()));
+        }
+        units.last_mut().unwrap().1.push((c, style));
+        space = false;
+    }
+    units
+}
+
+impl TextRenderer {
+    /// A renderer of plain text, wrapped at 80 columns.
+    pub fn new(profile: &Profile) -> Self {
+        TextRenderer {
+            profile: profile.clone(),
+            gaiji: GaijiTable::new(),
+            width: DEFAULT_WIDTH,
+            ansi: false,
+        }
+    }
+
+    /// A renderer for standard output: with ANSI escapes if it's a terminal and `NO_COLOR`
+    /// isn't set, and wrapped at the width of the terminal. When that's unknown, e.g. when
+    /// the output is piped, the width in `COLUMNS` is used, if set.
+    pub fn for_terminal(profile: &Profile) -> Self {
+        let mut renderer = Self::new(profile);
+        renderer.ansi = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        let columns = terminal_size::terminal_size()
+            .map(|(Width(width), _)| usize::from(width))
+            .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok());
+        if let Some(columns) = columns {
+            renderer.width = columns;
+        }
+        renderer
+    }
+
+    /// Sets the width lines are wrapped at. Zero disables wrapping.
+    pub fn width(&mut self, width: usize) -> &mut Self {
+        self.width = width;
+        self
+    }
+
+    /// Sets the gaiji mappings, which are empty by default.
+    pub fn gaiji(&mut self, table: GaijiTable) -> &mut Self {
+        self.gaiji = table;
+        self
+    }
+
+    /// Sets whether bold, italic and colours are written as ANSI escapes.
+    pub fn ansi(&mut self, ansi: bool) -> &mut Self {
+        self.ansi = ansi;
+        self
+    }
+
+    /// Renders the XML of a page or an item.
+    pub fn render(&self, xml: &str) -> Result<String, Error> {
+        let nodes = parse_fragment(xml)?;
+        let mut walker = Walker {
+            profile: &self.profile,
+            gaiji: &self.gaiji,
+            blocks: Vec::new(),
+            indent: 0,
+            sense_depth: 0,
+            head_end: None,
+        };
+        walker.nodes(&nodes, Style::default());
+        let lines: Vec<String> = walker
+            .blocks
+            .iter()
+            .flat_map(|block| self.wrap(block))
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    fn wrap(&self, block: &Block) -> Vec<String> {
+        let width = if self.width == 0 {
+            usize::MAX
+        } else {
+            self.width
+        };
+        let mut lines = Vec::new();
+        let mut line: Vec<(char, Style)> = Vec::new();
+        let mut line_width = block.indent;
+        let mut indent = block.indent;
+        for (space, unit) in units(&block.text) {
+            let unit_width: usize = unit.iter().map(|(c, _)| char_width(*c)).sum();
+            let space = usize::from(space && !line.is_empty());
+            if !line.is_empty() && line_width + space + unit_width > width {
+                lines.push(self.line(indent, &line));
+                line.clear();
+                indent = block.indent + block.hang;
+                line_width = indent;
+            } else if space == 1 {
+                line.push((' ', line.last().map(|(_, s)| *s).unwrap_or_default()));
+                line_width += 1;
+            }
+            for (c, style) in unit {
+                // Units wider than a line are split anywhere.
+                if !line.is_empty() && line_width + char_width(c) > width {
+                    lines.push(self.line(indent, &line));
+                    line.clear();
+                    indent = block.indent + block.hang;
+                    line_width = indent;
+                }
+                line.push((c, style));
+                line_width += char_width(c);
+            }
+        }
+        if !line.is_empty() {
+            lines.push(self.line(indent, &line));
+        }
+        lines
+    }
+
+    fn line(&self, indent: usize, text: &[(char, Style)]) -> String {
+        let mut out = " ".repeat(indent);
+        let mut current = Style::default();
+        for &(c, style) in text {
+            if self.ansi && style != current {
+                if !current.is_plain() {
+                    out.push_str("\x1b[0m");
+                }
+                if !style.is_plain() {
+                    out.push_str(&style.sgr());
+                }
+                current = style;
+            }
+            out.push(c);
+        }
+        if self.ansi && !current.is_plain() {
+            out.push_str("\x1b[0m");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+const TEST_XML: &str = r#"<entry><head><midashi>あい</midashi><hyouki>愛</hyouki><accent>1</accent><a href="ai.aac">♪</a></head>
+<meaning><number>1</number><imi>親子・兄弟などが互いに<ruby>慈<rp>(</rp><rt>いつく</rt><rp>)</rp></ruby>しみ合う気持ち。</imi><yourei>「親の―」</yourei>
+<subMeaning><number>㋐</number><imi>かわいがる心。</imi></subMeaning></meaning>
+<meaning><number>2</number><imi>恋愛。<b>強い</b>気持ち。</imi><img src="ai.png"/></meaning></entry>"#;
+
+#[test]
+fn test_render_text() {
+    let renderer = TextRenderer::new(&Profile::fixture("SMK8"));
+    assert_eq!(
+        renderer.render(TEST_XML).unwrap(),
+        [
+            "あい【愛】 1 [♪]",
+            "1 親子・兄弟などが互いに慈(いつく)しみ合う気持ち。",
+            "  「親の―」",
+            "  ㋐ かわいがる心。",
+            "2 恋愛。強い気持ち。[image: ai.png]",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn test_render_text_wrap() {
+    let mut renderer = TextRenderer::new(&Profile::fixture("SMK8"));
+    renderer.width(20);
+    let text = renderer.render(TEST_XML).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines[1], "1 親子・兄弟などが互");
+    assert_eq!(lines[2], "  いに慈(いつく)しみ");
+    for line in lines {
+        assert!(line.chars().map(char_width).sum::<usize>() <= 20, "{line}");
+    }
+
+    // Continuation lines align after the number, whatever the whitespace around it.
+    renderer.width(10);
+    let text = renderer
+        .render("<meaning><number> 1 </number><imi>ああああああああ</imi></meaning>")
+        .unwrap();
+    assert_eq!(text, "1 ああああ\n  ああああ");
+
+    // Punctuation stays with the preceding character.
+    renderer.width(6);
+    let text = renderer.render("<p>あいう。え</p>").unwrap();
+    assert_eq!(text, "あい\nう。え");
+    let text = renderer.render("<p>lorem ipsum dolor</p>").unwrap();
+    assert_eq!(text, "lorem\nipsum\ndolor");
+    renderer.width(0);
+    assert_eq!(
+        renderer.render("<p>lorem ipsum dolor</p>").unwrap(),
+        "lorem ipsum dolor"
+    );
+}
+
+#[test]
+fn test_render_ansi() {
+    let mut renderer = TextRenderer::new(&Profile::fixture("SMK8"));
+    renderer.ansi(true);
+    let text = renderer
+        .render("<midashi>あい</midashi>と<b>愛</b>")
+        .unwrap();
+    assert_eq!(text, "\x1b[1mあい\x1b[0mと\x1b[1m愛\x1b[0m");
+    let text = renderer
+        .render("<midashi>あい</midashi><hyouki>愛</hyouki><hinshi>名</hinshi>")
+        .unwrap();
+    assert_eq!(
+        text,
+        "\x1b[1mあい\x1b[0m【\x1b[1m愛\x1b[0m】 \x1b[33m名\x1b[0m"
+    );
+    let text = renderer.render("<i>a <u>b</u></i>").unwrap();
+    assert_eq!(text, "\x1b[3ma \x1b[0m\x1b[3;4mb\x1b[0m");
+}
+
+#[test]
+fn test_render_text_gaiji() {
+    let xml = "<p>\u{e002}<gaiji name='g1'/><ruby>字<rt>\u{e003}</rt></ruby>\u{e0ff}</p>";
+    let mut renderer = TextRenderer::new(&Profile::fixture("SMK8"));
+    assert_eq!(
+        renderer.render(xml).unwrap(),
+        "\u{e002}〓字(\u{e003})\u{e0ff}"
+    );
+    let mut table = GaijiTable::new();
+    table
+        .insert("g1", "𠀋")
+        .insert("U+E002", "⇔")
+        .insert("U+E003", "▽");
+    renderer.gaiji(table);
+    assert_eq!(renderer.render(xml).unwrap(), "⇔𠀋字(▽)\u{e0ff}");
+}
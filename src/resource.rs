@@ -1,12 +1,21 @@
+mod cache;
 mod nrsc;
 mod rsc;
 
-use std::fs;
+use std::{fmt, fs, io, ops::Deref, ops::Range, path::Path, sync::Arc};
 
+use cache::ChunkCache;
+pub use cache::{CacheStats, DEFAULT_CHUNK_CACHE_BYTES};
 pub use nrsc::Nrsc;
+#[cfg(test)]
+pub(crate) use rsc::write_test_rsc;
 pub use rsc::Rsc;
 
-use crate::Error;
+use crate::{
+    diagnostics::{self, Event},
+    error::ResultExt,
+    Error, ErrorKind,
+};
 
 use miniz_oxide::inflate::{core as zlib, TINFLStatus as ZStatus};
 
@@ -15,7 +24,118 @@ struct ResourceFile {
     seqnum: u32,
     len: usize,
     offset: usize,
+    #[cfg(any(unix, windows))]
     file: fs::File,
+    /// Without positioned reads, reads seek the file one at a time.
+    #[cfg(not(any(unix, windows)))]
+    file: std::sync::Mutex<fs::File>,
+}
+
+impl ResourceFile {
+    fn open(path: &Path, seqnum: u32, len: usize) -> Result<Self, Error> {
+        let file = fs::File::open(path).with_path(path)?;
+        Ok(ResourceFile {
+            seqnum,
+            len,
+            offset: 0,
+            #[cfg(any(unix, windows))]
+            file,
+            #[cfg(not(any(unix, windows)))]
+            file: std::sync::Mutex::new(file),
+        })
+    }
+
+    /// Reads at `offset` without moving the file cursor, so the file can be shared
+    /// between threads.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::FileExt::read_exact_at(&self.file, buf, offset)
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::FileExt;
+            let (mut buf, mut offset) = (buf, offset);
+            while !buf.is_empty() {
+                match self.file.seek_read(buf, offset) {
+                    Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(n) => {
+                        buf = &mut buf[n..];
+                        offset += n as u64;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            use io::{Read, Seek, SeekFrom};
+            let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(buf)
+        }
+    }
+}
+
+/// A resource read from an rsc or nrsc file. Cloning it is cheap, as the bytes are
+/// shared with the chunk they were decompressed with.
+#[derive(Clone)]
+pub struct Blob {
+    data: Arc<[u8]>,
+    range: Range<usize>,
+}
+
+impl Blob {
+    pub(crate) fn new(data: Arc<[u8]>, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end && range.end <= data.len());
+        Blob { data, range }
+    }
+
+    /// A part of this blob, `range` being relative to its start.
+    pub(crate) fn slice(&self, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end && range.end <= self.len());
+        let start = self.range.start;
+        Blob::new(self.data.clone(), start + range.start..start + range.end)
+    }
+}
+
+impl From<Vec<u8>> for Blob {
+    fn from(bytes: Vec<u8>) -> Self {
+        let range = 0..bytes.len();
+        Blob::new(bytes.into(), range)
+    }
+}
+
+impl Deref for Blob {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data[self.range.clone()]
+    }
+}
+
+impl AsRef<[u8]> for Blob {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for Blob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Blob").field("len", &self.len()).finish()
+    }
+}
+
+/// Decompresses a zlib stream into a fresh buffer. The state is per call, so that
+/// resources can be read from several threads.
+fn decompress_to_vec(in_buf: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut zlib_state = Box::<zlib::DecompressorOxide>::default();
+    let mut out_buf = vec![0; in_buf.len() * 4];
+    let len = decompress(&mut zlib_state, in_buf, &mut out_buf)?;
+    out_buf.truncate(len);
+    Ok(out_buf)
 }
 
 fn decompress(
@@ -46,11 +166,60 @@ fn decompress(
                 continue;
             }
             Done => break,
-            _ => return Err(Error::ZlibError),
+            _ => return Err(ErrorKind::ZlibError.into()),
         }
     }
     if n_in_total != in_buf.len() {
-        return Err(Error::IncorrectStreamLength);
+        return Err(ErrorKind::IncorrectStreamLength.into());
     }
+    diagnostics::emit(Event::Decompressed {
+        compressed_len: in_buf.len(),
+        decompressed_len: n_out_total,
+    });
     Ok(n_out_total)
 }
+
+/// Wraps `data` in a zlib stream of stored (uncompressed) blocks.
+#[cfg(test)]
+pub(crate) fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = data.chunks(0xffff).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        out.push(u8::from(chunks.peek().is_none()));
+        let len = chunk.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    out
+}
+
+#[test]
+fn test_decompress() {
+    for len in [0, 1, 1000, 70000] {
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        assert_eq!(decompress_to_vec(&zlib_stored(&data)).unwrap(), data);
+    }
+    let mut bad = zlib_stored(b"abc");
+    bad.push(0);
+    let err = decompress_to_vec(&bad).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::IncorrectStreamLength);
+}
+
+#[test]
+fn test_blob() {
+    let blob = Blob::from(b"hello world".to_vec());
+    assert_eq!(&*blob, b"hello world");
+    let world = blob.slice(6..11);
+    assert_eq!(&*world, b"world");
+    assert_eq!(&*world.slice(1..3), b"or");
+}
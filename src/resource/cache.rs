@@ -0,0 +1,150 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::Error;
+
+/// Default size limit of the decompressed chunk cache of each rsc resource.
+pub const DEFAULT_CHUNK_CACHE_BYTES: usize = 4 << 20;
+
+/// Counters of a chunk cache, to help choosing its size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads served from the cache.
+    pub hits: u64,
+    /// Reads that had to decompress a chunk.
+    pub misses: u64,
+    /// Chunks currently cached.
+    pub chunks: usize,
+    /// Decompressed bytes currently cached.
+    pub bytes: usize,
+}
+
+/// A bounded LRU cache of decompressed chunks, keyed by their offset in the rsc files.
+pub(crate) struct ChunkCache {
+    limit: usize,
+    lru: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Lru {
+    /// Chunk and the tick of its last use, by offset
+    chunks: HashMap<usize, (Arc<[u8]>, u64)>,
+    /// Offsets by tick of last use, least recent first
+    order: BTreeMap<u64, usize>,
+    tick: u64,
+    bytes: usize,
+}
+
+impl Lru {
+    fn get(&mut self, offset: usize) -> Option<Arc<[u8]>> {
+        self.tick += 1;
+        let (chunk, used) = self.chunks.get_mut(&offset)?;
+        self.order.remove(used);
+        self.order.insert(self.tick, offset);
+        *used = self.tick;
+        Some(chunk.clone())
+    }
+
+    fn insert(&mut self, offset: usize, chunk: Arc<[u8]>, limit: usize) {
+        if chunk.len() > limit || self.chunks.contains_key(&offset) {
+            return;
+        }
+        while self.bytes + chunk.len() > limit {
+            let Some((_, evicted)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((evicted, _)) = self.chunks.remove(&evicted) {
+                self.bytes -= evicted.len();
+            }
+        }
+        self.tick += 1;
+        self.bytes += chunk.len();
+        self.order.insert(self.tick, offset);
+        self.chunks.insert(offset, (chunk, self.tick));
+    }
+}
+
+impl ChunkCache {
+    /// A cache holding at most `limit` decompressed bytes. Zero disables caching.
+    pub(crate) fn new(limit: usize) -> Self {
+        ChunkCache {
+            limit,
+            lru: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn lru(&self) -> std::sync::MutexGuard<'_, Lru> {
+        // A poisoned lock only means that another thread panicked; the cache is still valid.
+        self.lru.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the chunk at `offset`, calling `load` on a miss. The lock isn't held while
+    /// loading, so threads missing the same chunk at once may both decompress it.
+    pub(crate) fn get_or_load(
+        &self,
+        offset: usize,
+        load: impl FnOnce() -> Result<Arc<[u8]>, Error>,
+    ) -> Result<Arc<[u8]>, Error> {
+        if let Some(chunk) = self.lru().get(offset) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(chunk);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let chunk = load()?;
+        self.lru().insert(offset, chunk.clone(), self.limit);
+        Ok(chunk)
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        let lru = self.lru();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            chunks: lru.chunks.len(),
+            bytes: lru.bytes,
+        }
+    }
+}
+
+#[test]
+fn test_chunk_cache() {
+    let cache = ChunkCache::new(10);
+    let chunk = |len: usize| move || Ok(Arc::from(vec![len as u8; len]));
+    let load = |offset, len| cache.get_or_load(offset, chunk(len)).unwrap().len();
+
+    assert_eq!(load(0, 4), 4);
+    assert_eq!(load(100, 4), 4);
+    assert_eq!(load(0, 4), 4); // hit, and now more recent than 100
+    assert_eq!(load(200, 4), 4); // evicts 100
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 1,
+            misses: 3,
+            chunks: 2,
+            bytes: 8
+        }
+    );
+    let failing = || Err(crate::ErrorKind::ZlibError.into());
+    assert!(cache.get_or_load(0, failing).is_ok());
+    assert!(cache.get_or_load(100, failing).is_err());
+
+    // Too big to be cached
+    assert_eq!(load(300, 11), 11);
+    assert_eq!(cache.stats().chunks, 2);
+
+    let disabled = ChunkCache::new(0);
+    disabled.get_or_load(0, chunk(1)).unwrap();
+    disabled.get_or_load(0, chunk(1)).unwrap();
+    assert_eq!(disabled.stats().misses, 2);
+    assert_eq!(disabled.stats().hits, 0);
+}
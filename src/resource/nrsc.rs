@@ -1,25 +1,25 @@
 use core::mem::size_of;
-use std::{
-    ffi::OsStr,
-    fs::{self, File},
-    io::{Read, Seek, SeekFrom},
-    path::Path,
+use std::{ffi::OsStr, fs, path::Path};
+
+use crate::{
+    abi_utils::TransmuteSafe,
+    diagnostics::{self, Event, IndexKind},
+    error::ResultExt,
+    resource::{decompress_to_vec, Blob},
+    storage::{Buf, IndexFile},
+    Error, ErrorKind, FileFormat, IndexStorage, OpenOptions,
 };
 
-use miniz_oxide::inflate::core as zlib;
-
-use crate::{abi_utils::TransmuteSafe, resource::decompress, Error};
-
 #[derive(Debug, Clone)]
 pub(crate) struct NrscIndex {
-    idx: Vec<NrscIdxRecord>,
-    ids: String, // contains null bytes as substring separators
+    idx: Buf<NrscIdxRecord>,
+    ids: Buf<u8>, // UTF-8, contains null bytes as substring separators
 }
 
 mod abi {
 
     use super::Format;
-    use crate::Error;
+    use crate::{Error, ErrorKind};
 
     // TODO: Use LE16 & LE32?
     #[repr(C)]
@@ -41,7 +41,7 @@ mod abi {
             match u16::from_le(self.format) {
                 0 => Ok(Format::Uncompressed),
                 1 => Ok(Format::Zlib),
-                _ => Err(Error::InvalidAudioFormat),
+                _ => Err(ErrorKind::InvalidAudioFormat.into()),
             }
         }
 
@@ -59,6 +59,7 @@ mod abi {
     }
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn test_audio_index() {
         use super::NrscIndex;
         use std::mem::size_of;
@@ -70,31 +71,43 @@ mod abi {
             len: 0,
         };
         let mut audio_idx = NrscIndex {
-            idx: vec![air(0), air(1), air(3), air(6), air(10)],
-            ids: "\0a\0bb\0ccc\0dddd".to_owned(),
+            idx: vec![air(0), air(1), air(3), air(6), air(10)].into(),
+            ids: b"\0a\0bb\0ccc\0dddd".to_vec().into(),
         };
 
         let diff = 8 + audio_idx.idx.len() * size_of::<NrscIdxRecord>();
         // Fix offsets now that they are known
-        for air in audio_idx.idx.iter_mut() {
+        let mut idx = audio_idx.idx.to_vec();
+        for air in idx.iter_mut() {
             air.id_str_offset += diff as u32;
         }
+        audio_idx.idx = idx.into();
 
         assert_eq!(audio_idx.get_id_at(diff + 0).unwrap(), "");
         assert_eq!(audio_idx.get_id_at(diff + 1).unwrap(), "a");
         assert_eq!(audio_idx.get_id_at(diff + 3).unwrap(), "bb");
-        assert_eq!(audio_idx.get_id_at(diff + 4), Err(Error::InvalidIndex));
+        let kind = |res: Result<_, Error>| res.map_err(|e| e.kind());
+        assert_eq!(
+            kind(audio_idx.get_id_at(diff + 4)),
+            Err(ErrorKind::InvalidIndex)
+        );
         assert_eq!(audio_idx.get_id_at(diff + 6).unwrap(), "ccc");
-        assert_eq!(audio_idx.get_id_at(diff + 10), Err(Error::InvalidIndex));
+        assert_eq!(
+            kind(audio_idx.get_id_at(diff + 10)),
+            Err(ErrorKind::InvalidIndex)
+        );
 
-        audio_idx.ids = "\0a\0bb\0ccc\0dddd\0".to_owned();
+        audio_idx.ids = b"\0a\0bb\0ccc\0dddd\0".to_vec().into();
         let diff = diff as u32;
         assert_eq!(audio_idx.get_by_id("").unwrap(), air(diff + 0));
         assert_eq!(audio_idx.get_by_id("a").unwrap(), air(diff + 1));
         assert_eq!(audio_idx.get_by_id("bb").unwrap(), air(diff + 3));
         assert_eq!(audio_idx.get_by_id("ccc").unwrap(), air(diff + 6));
         assert_eq!(audio_idx.get_by_id("dddd").unwrap(), air(diff + 10));
-        assert_eq!(audio_idx.get_by_id("ddd"), Err(Error::NotFound));
+        assert_eq!(
+            audio_idx.get_by_id("ddd").map_err(|e| e.kind()),
+            Err(ErrorKind::NotFound)
+        );
     }
 }
 
@@ -110,30 +123,44 @@ enum Format {
 unsafe impl TransmuteSafe for NrscIdxRecord {}
 
 impl NrscIndex {
-    pub(crate) fn new(path: &Path) -> Result<Self, Error> {
+    pub(crate) fn new(path: &Path, storage: IndexStorage) -> Result<Self, Error> {
         let path = path.join("index.nidx");
-        let mut file = File::open(path).map_err(|_| Error::FopenError)?;
+        Self::new_inner(&path, storage)
+            .with_path(&path)
+            .in_format(FileFormat::NrscIndex)
+    }
+
+    fn new_inner(path: &Path, storage: IndexStorage) -> Result<Self, Error> {
+        let mut file = IndexFile::open(path, storage)?;
         let mut len = [0; 8];
-        file.read_exact(&mut len).map_err(|_| Error::IOError)?;
+        file.read_exact_at(0, &mut len)?;
         let len = u32::from_le_bytes(len[4..8].try_into().unwrap()) as usize;
-        let file_size = file.metadata().map_err(|_| Error::IOError)?.len() as usize;
         let idx_expected_size = size_of::<NrscIdxRecord>() * len + 8;
-        let mut idx = vec![NrscIdxRecord::default(); len];
-        let mut ids = String::with_capacity(file_size - idx_expected_size);
-        file.read_exact(NrscIdxRecord::slice_as_bytes_mut(idx.as_mut_slice()))
-            .map_err(|_| Error::IOError)?;
-        file.read_to_string(&mut ids).map_err(|_| Error::IOError)?;
+        if file.len() < idx_expected_size {
+            return Err(ErrorKind::IncorrectStreamLength.into());
+        }
+        let idx = file.buf(8..idx_expected_size)?;
+        let ids = file.buf(idx_expected_size..file.len())?;
+        diagnostics::emit(Event::IndexLoaded {
+            kind: IndexKind::Nrsc,
+            path,
+            entries: len,
+            storage,
+        });
         Ok(Self { idx, ids })
     }
 
     fn get_id_at(&self, offset: usize) -> Result<&str, Error> {
         let offset = offset - (size_of::<NrscIdxRecord>() * self.idx.len() + 8);
-        if offset > 0 && &self.ids[offset - 1..offset] != "\0" {
-            return Err(Error::InvalidIndex);
+        if offset > 0 && self.ids.get(offset - 1) != Some(&b'\0') {
+            return Err(ErrorKind::InvalidIndex.into());
         }
-        let tail = &self.ids[offset..];
-        let len = tail.find('\0').ok_or(Error::InvalidIndex)?;
-        Ok(&tail[..len])
+        let tail = self.ids.get(offset..).ok_or(ErrorKind::InvalidIndex)?;
+        let len = tail
+            .iter()
+            .position(|&b| b == b'\0')
+            .ok_or(ErrorKind::InvalidIndex)?;
+        Ok(std::str::from_utf8(&tail[..len])?)
     }
 
     pub fn get_by_id(&self, id: &str) -> Result<NrscIdxRecord, Error> {
@@ -147,29 +174,27 @@ impl NrscIndex {
                     ""
                 }
             })
-            .map_err(|_| Error::NotFound)?;
+            .map_err(|_| ErrorKind::NotFound)?;
         idx_err?;
 
         Ok(self.idx[i])
     }
 
     pub fn get_by_idx(&self, idx: usize) -> Result<(&str, NrscIdxRecord), Error> {
-        let idx_rec = self.idx.get(idx).copied().ok_or(Error::InvalidIndex)?;
-        let item_id = self.get_id_at(idx_rec.id_str_offset())?;
+        let idx_rec = self
+            .idx
+            .get(idx)
+            .copied()
+            .ok_or(ErrorKind::InvalidIndex)
+            .at_record(idx)?;
+        let item_id = self.get_id_at(idx_rec.id_str_offset()).at_record(idx)?;
         Ok((item_id, idx_rec))
     }
 }
 
 pub struct Nrsc {
     index: NrscIndex,
-    data: NrscData,
-}
-
-struct NrscData {
     files: Vec<ResourceFile>,
-    read_buf: Vec<u8>,
-    decomp_buf: Vec<u8>,
-    zlib_state: zlib::DecompressorOxide,
 }
 
 impl Nrsc {
@@ -186,15 +211,20 @@ impl Nrsc {
     fn files(path: &Path) -> Result<Vec<ResourceFile>, Error> {
         let mut files = Vec::new();
 
-        for entry in fs::read_dir(path).map_err(|_| Error::IOError)? {
-            let entry = entry.map_err(|_| Error::IOError)?;
+        for entry in fs::read_dir(path).with_path(path)? {
+            let entry = entry.with_path(path)?;
             let seqnum = Nrsc::parse_fname(&entry.file_name());
             if let Some(seqnum) = seqnum {
-                files.push(ResourceFile {
+                let len = entry.metadata().with_path(entry.path())?.len() as usize;
+                diagnostics::emit(Event::ResourceFileFound {
+                    path: &entry.path(),
                     seqnum,
-                    len: entry.metadata().map_err(|_| Error::IOError)?.len() as usize,
-                    offset: 0,
-                    file: File::open(entry.path()).map_err(|_| Error::IOError)?,
+                    len,
+                });
+                files.push(ResourceFile::open(&entry.path(), seqnum, len)?);
+            } else {
+                diagnostics::emit(Event::ResourceFileIgnored {
+                    path: &entry.path(),
                 });
             }
         }
@@ -202,7 +232,11 @@ impl Nrsc {
         files.sort_by_key(|f| f.seqnum);
         for (i, cf) in files.iter_mut().enumerate() {
             if cf.seqnum != i as u32 {
-                return Err(Error::MissingResourceFile);
+                diagnostics::emit(Event::ResourceFileMissing {
+                    dir: path,
+                    seqnum: i as u32,
+                });
+                return Err(Error::new(ErrorKind::MissingResourceFile).with_path(path));
             }
             cf.offset = offset;
             offset += cf.len;
@@ -211,58 +245,52 @@ impl Nrsc {
     }
 
     pub fn new(path: &Path) -> Result<Self, Error> {
+        Self::new_with_options(path, &OpenOptions::new())
+    }
+
+    pub fn new_with_options(path: &Path, options: &OpenOptions) -> Result<Self, Error> {
         let files = Nrsc::files(path)?;
-        let index = NrscIndex::new(path)?;
-        Ok(Nrsc {
-            index,
-            data: NrscData {
-                files,
-                read_buf: Vec::new(),
-                decomp_buf: Vec::new(),
-                zlib_state: zlib::DecompressorOxide::new(),
-            },
-        })
+        let index = NrscIndex::new(path, options.storage)?;
+        Ok(Nrsc { index, files })
     }
 
-    pub fn get_by_idx(&mut self, idx: usize) -> Result<(&str, &[u8]), Error> {
+    pub fn get_by_idx(&self, idx: usize) -> Result<(&str, Blob), Error> {
         let (id, nidx_rec) = self.index.get_by_idx(idx)?;
-        let item = self.data.get_by_nidx_rec(nidx_rec)?;
+        let item = self.get_by_nidx_rec(nidx_rec)?;
         Ok((id, item))
     }
 
-    pub fn get(&mut self, id: &str) -> Result<&[u8], Error> {
-        self.data.get_by_nidx_rec(self.index.get_by_id(id)?)
+    pub fn get(&self, id: &str) -> Result<Blob, Error> {
+        self.get_by_nidx_rec(self.index.get_by_id(id)?)
     }
 
     pub fn len(&self) -> usize {
         self.index.idx.len()
     }
-}
 
-impl NrscData {
-    fn get_by_nidx_rec(&mut self, idx: NrscIdxRecord) -> Result<&[u8], Error> {
-        let file = &mut self.files[idx.fileseq()];
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-        file.file
-            .seek(SeekFrom::Start(idx.file_offset()))
-            .map_err(|_| Error::IOError)?;
-        if self.read_buf.len() < idx.len() {
-            self.read_buf.resize(idx.len(), 0);
-        }
-        file.file
-            .read_exact(&mut self.read_buf[..idx.len()])
-            .map_err(|_| Error::IOError)?;
+    fn get_by_nidx_rec(&self, idx: NrscIdxRecord) -> Result<Blob, Error> {
+        let offset = idx.file_offset();
+        self.get_by_nidx_rec_inner(idx)
+            .at_offset(offset)
+            .in_format(FileFormat::Nrsc)
+    }
+
+    fn get_by_nidx_rec_inner(&self, idx: NrscIdxRecord) -> Result<Blob, Error> {
+        let file = self
+            .files
+            .get(idx.fileseq())
+            .ok_or(ErrorKind::MissingResourceFile)?;
+
+        let mut read_buf = vec![0; idx.len()];
+        file.read_exact_at(&mut read_buf, idx.file_offset())?;
 
         match idx.format()? {
-            Format::Uncompressed => Ok(&self.read_buf[..idx.len()]),
-            Format::Zlib => {
-                let n_out = decompress(
-                    &mut self.zlib_state,
-                    &self.read_buf[..idx.len()],
-                    &mut self.decomp_buf,
-                )?;
-                Ok(&self.decomp_buf[..n_out])
-            }
+            Format::Uncompressed => Ok(read_buf.into()),
+            Format::Zlib => Ok(decompress_to_vec(&read_buf)?.into()),
         }
     }
 }
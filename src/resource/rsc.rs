@@ -1,16 +1,13 @@
-use core::{cmp::min, mem::size_of, ops::Not, slice};
-use miniz_oxide::inflate::core as zlib;
-use std::{
-    ffi::OsStr,
-    fs::{self, File},
-    io::{Read, Seek, SeekFrom},
-    path::Path,
-};
+use core::{cmp::min, mem::size_of, ops::Not};
+use std::{ffi::OsStr, fs, path::Path, sync::Arc};
 
 use crate::{
     abi_utils::{TransmuteSafe, LE32},
-    resource::decompress,
-    Error,
+    diagnostics::{self, Event, IndexKind},
+    error::ResultExt,
+    resource::{decompress_to_vec, Blob, CacheStats, ChunkCache},
+    storage::{Buf, IndexFile},
+    Error, ErrorKind, FileFormat, IndexStorage, OpenOptions,
 };
 
 mod abi {
@@ -33,7 +30,7 @@ mod abi {
     #[test]
     fn test_get_by_id() {
         use super::RscIndex;
-        use crate::Error;
+        use crate::ErrorKind;
 
         fn idx(id: u32, idx: u32) -> IdxRecord {
             IdxRecord {
@@ -50,59 +47,67 @@ mod abi {
 
         assert_eq!(
             RscIndex {
-                idx: Some(vec![]),
-                map: vec![],
+                idx: Some(vec![].into()),
+                map: vec![].into(),
             }
-            .get_by_id(500),
-            Err(Error::NotFound)
+            .get_by_id(500)
+            .map_err(|e| e.kind()),
+            Err(ErrorKind::NotFound)
         );
 
         assert_eq!(
             RscIndex {
-                idx: Some(vec![idx(1, 0)]),
-                map: vec![map(0, 0)],
+                idx: Some(vec![idx(1, 0)].into()),
+                map: vec![map(0, 0)].into(),
             }
-            .get_by_id(500),
-            Err(Error::NotFound)
+            .get_by_id(500)
+            .map_err(|e| e.kind()),
+            Err(ErrorKind::NotFound)
         );
 
         assert_eq!(
             RscIndex {
-                idx: Some(vec![idx(1, 0), idx(2, 1)]),
-                map: vec![map(0, 0), map(0, 10)],
+                idx: Some(vec![idx(1, 0), idx(2, 1)].into()),
+                map: vec![map(0, 0), map(0, 10)].into(),
             }
-            .get_by_id(500),
-            Err(Error::NotFound)
+            .get_by_id(500)
+            .map_err(|e| e.kind()),
+            Err(ErrorKind::NotFound)
         );
 
         assert_eq!(
             RscIndex {
-                idx: Some(vec![idx(1, 0), idx(2, 1), idx(1000, 2)]),
-                map: vec![map(0, 0), map(0, 10), map(0, 20)],
+                idx: Some(vec![idx(1, 0), idx(2, 1), idx(1000, 2)].into()),
+                map: vec![map(0, 0), map(0, 10), map(0, 20)].into(),
             }
-            .get_by_id(500),
-            Err(Error::NotFound)
+            .get_by_id(500)
+            .map_err(|e| e.kind()),
+            Err(ErrorKind::NotFound)
         );
 
         assert_eq!(
             RscIndex {
-                idx: Some(vec![idx(1, 0), idx(2, 1), idx(500, 2), idx(1000, 3)]),
-                map: vec![map(0, 0), map(0, 10), map(0, 20), map(10, 0)],
+                idx: Some(vec![idx(1, 0), idx(2, 1), idx(500, 2), idx(1000, 3)].into()),
+                map: vec![map(0, 0), map(0, 10), map(0, 20), map(10, 0)].into(),
             }
-            .get_by_id(500),
+            .get_by_id(500)
+            .map_err(|e| e.kind()),
             Ok(map(0, 20))
         );
 
         assert_eq!(
             RscIndex {
-                idx: Some(vec![
-                    idx(1, 0),
-                    idx(2, 1),
-                    idx(499, 2),
-                    idx(500, 3),
-                    idx(501, 4),
-                    idx(1000, 5)
-                ]),
+                idx: Some(
+                    vec![
+                        idx(1, 0),
+                        idx(2, 1),
+                        idx(499, 2),
+                        idx(500, 3),
+                        idx(501, 4),
+                        idx(1000, 5)
+                    ]
+                    .into()
+                ),
                 map: vec![
                     map(0, 0),
                     map(0, 10),
@@ -110,9 +115,11 @@ mod abi {
                     map(10, 0),
                     map(10, 0),
                     map(10, 0)
-                ],
+                ]
+                .into(),
             }
-            .get_by_id(500),
+            .get_by_id(500)
+            .map_err(|e| e.kind()),
             Ok(map(10, 0))
         );
     }
@@ -123,59 +130,61 @@ use super::ResourceFile;
 
 #[derive(Debug, Clone)]
 pub(crate) struct RscIndex {
-    idx: Option<Vec<IdxRecord>>,
-    map: Vec<MapRecord>,
+    idx: Option<Buf<IdxRecord>>,
+    map: Buf<MapRecord>,
 }
 
 unsafe impl TransmuteSafe for MapRecord {}
 unsafe impl TransmuteSafe for IdxRecord {}
 
 impl RscIndex {
-    fn load_idx(path: &Path) -> Result<Option<Vec<IdxRecord>>, Error> {
+    fn load_idx(path: &Path, storage: IndexStorage) -> Result<Option<Buf<IdxRecord>>, Error> {
         let path = path.with_extension("idx");
         if path.exists().not() {
             return Ok(None);
         };
-        let mut idx_file = File::open(path)?;
+        let mut idx_file = IndexFile::open(&path, storage)?;
         let mut len = [0; 4];
-        idx_file.read_exact(&mut len)?;
+        idx_file.read_exact_at(0, &mut len)?;
         let len = u32::from_le_bytes(len) as usize;
-        idx_file.seek(SeekFrom::Start(8))?;
-        let idx_size = idx_file.metadata().map_err(|_| Error::IOError)?.len();
-        let idx_expected_size = (size_of::<IdxRecord>() * len + 8) as u64;
-        if idx_size != idx_expected_size {
-            return Err(Error::IncorrectStreamLength);
+        let idx_expected_size = size_of::<IdxRecord>() * len + 8;
+        if idx_file.len() != idx_expected_size {
+            return Err(ErrorKind::IncorrectStreamLength.into());
         }
-        let mut idx = vec![IdxRecord::default(); len];
-        idx_file
-            .read_exact(IdxRecord::slice_as_bytes_mut(idx.as_mut_slice()))
-            .map_err(|_| Error::IOError)?;
+        let idx = idx_file.buf(8..idx_expected_size)?;
+        diagnostics::emit(Event::IndexLoaded {
+            kind: IndexKind::RscIdx,
+            path: &path,
+            entries: len,
+            storage,
+        });
         Ok(Some(idx))
     }
 
-    fn load_map(path: &Path) -> Result<Vec<MapRecord>, Error> {
+    fn load_map(path: &Path, storage: IndexStorage) -> Result<Buf<MapRecord>, Error> {
         let path = path.with_extension("map");
-        let mut map_file = File::open(path)?;
+        let mut map_file = IndexFile::open(&path, storage)?;
         let mut len = [0; 4];
-        map_file.seek(SeekFrom::Start(4))?;
-        map_file.read_exact(&mut len)?;
+        map_file.read_exact_at(4, &mut len)?;
         let len = u32::from_le_bytes(len) as usize;
-        map_file.seek(SeekFrom::Start(8))?;
-        let map_size = map_file.metadata().map_err(|_| Error::IOError)?.len();
-        let map_expected_size = (size_of::<MapRecord>() * len + 8) as u64;
-        if map_size != map_expected_size {
-            return Err(Error::IncorrectStreamLength);
+        let map_expected_size = size_of::<MapRecord>() * len + 8;
+        if map_file.len() != map_expected_size {
+            return Err(ErrorKind::IncorrectStreamLength.into());
         }
-        let mut map = vec![MapRecord::default(); len];
-        map_file
-            .read_exact(MapRecord::slice_as_bytes_mut(map.as_mut_slice()))
-            .map_err(|_| Error::IOError)?;
+        let map = map_file.buf(8..map_expected_size)?;
+        diagnostics::emit(Event::IndexLoaded {
+            kind: IndexKind::RscMap,
+            path: &path,
+            entries: len,
+            storage,
+        });
         Ok(map)
     }
-    pub(crate) fn new(path: &Path, rsc_name: &str) -> Result<Self, Error> {
+
+    pub(crate) fn new(path: &Path, rsc_name: &str, storage: IndexStorage) -> Result<Self, Error> {
         let path = path.join(rsc_name); // filename stem
-        let idx = Self::load_idx(&path)?;
-        let map = Self::load_map(&path)?;
+        let idx = Self::load_idx(&path, storage).in_format(FileFormat::RscIdx)?;
+        let map = Self::load_map(&path, storage).in_format(FileFormat::RscMap)?;
         Ok(RscIndex { idx, map })
     }
 
@@ -184,7 +193,7 @@ impl RscIndex {
             return Ok(id as usize);
         };
         if idx_list.is_empty() {
-            return Err(Error::NotFound);
+            return Err(ErrorKind::NotFound.into());
         }
 
         // Let's guess first, since usually the IDs are completely predictable, without gaps.
@@ -201,30 +210,35 @@ impl RscIndex {
         let map_idx = idx_list
             .binary_search_by_key(&id, |r| r.item_id.read())
             .map(|idx| idx_list[idx].map_idx.us())
-            .map_err(|_| Error::NotFound)?;
+            .map_err(|_| ErrorKind::NotFound)?;
         if map_idx >= self.map.len() {
-            return Err(Error::IndexMismach);
+            return Err(ErrorKind::IndexMismach.into());
         }
         Ok(map_idx)
     }
 
     pub fn get_by_id(&self, id: u32) -> Result<MapRecord, Error> {
         let idx = self.get_map_idx_by_id(id)?;
-        let record = self.map[idx];
+        let record = self.map.get(idx).copied().ok_or(ErrorKind::NotFound)?;
         Ok(record)
     }
 
     pub fn get_by_idx(&self, idx: usize) -> Result<(u32, MapRecord), Error> {
         let item_id = if let Some(indexes) = &self.idx {
-            let idx_rec = indexes.get(idx).copied().ok_or(Error::InvalidIndex)?;
+            let invalid = || Error::new(ErrorKind::InvalidIndex).at_record(idx);
+            let idx_rec = indexes.get(idx).copied().ok_or_else(invalid)?;
             if idx_rec.map_idx.us() != idx {
-                return Err(Error::InvalidIndex);
+                return Err(invalid());
             };
             idx_rec.item_id.read()
         } else {
             idx as u32
         };
-        let map_rec = self.map.get(idx).copied().ok_or(Error::InvalidIndex)?;
+        let map_rec = self
+            .map
+            .get(idx)
+            .copied()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidIndex).at_record(idx))?;
         Ok((item_id, map_rec))
     }
 }
@@ -232,11 +246,7 @@ impl RscIndex {
 pub struct Rsc {
     index: RscIndex,
     files: Vec<ResourceFile>,
-    zlib_buf: Vec<u8>,
-    zlib_state: zlib::DecompressorOxide,
-    contents_buf: Vec<u8>,
-    current_offset: usize,
-    current_len: usize,
+    chunks: ChunkCache,
 }
 
 impl Rsc {
@@ -259,7 +269,7 @@ impl Rsc {
         }
 
         // Handle old format: contents1.rsc
-        if middle_part.len() > 0 && middle_part.chars().next()? != '-' {
+        if !middle_part.is_empty() && middle_part.chars().next()? != '-' {
             return middle_part.parse().ok();
         }
 
@@ -269,35 +279,32 @@ impl Rsc {
     fn files(path: &Path, rsc_name: &str) -> Result<Vec<ResourceFile>, Error> {
         let mut files = Vec::new();
 
-        for entry in fs::read_dir(path).map_err(|_| Error::IOError)? {
-            let entry = entry.map_err(|_| Error::IOError)?;
+        for entry in fs::read_dir(path).with_path(path)? {
+            let entry = entry.with_path(path)?;
             let seqnum = Self::parse_fname(rsc_name, &entry.file_name());
-            println!("File: {:?}, Parsed seqnum: {:?}", entry.file_name(), seqnum); // DEBUG
             if let Some(seqnum) = seqnum {
-                files.push(ResourceFile {
+                let len = entry.metadata()?.len() as usize;
+                diagnostics::emit(Event::ResourceFileFound {
+                    path: &entry.path(),
                     seqnum,
-                    len: entry.metadata().map_err(|_| Error::IOError)?.len() as usize,
-                    offset: 0,
-                    file: File::open(entry.path()).map_err(|_| Error::IOError)?,
+                    len,
+                });
+                files.push(ResourceFile::open(&entry.path(), seqnum, len)?);
+            } else {
+                diagnostics::emit(Event::ResourceFileIgnored {
+                    path: &entry.path(),
                 });
             }
         }
         files.sort_by_key(|f| f.seqnum);
-        println!(
-            "Sorted sequence numbers: {:?}",
-            files.iter().map(|f| f.seqnum).collect::<Vec<_>>()
-        ); // DEBUG
         let mut offset = 0;
         for (i, cf) in files.iter_mut().enumerate() {
-            println!(
-                "Checking: index {} expects seqnum {}, got {}",
-                i,
-                i as u32 + 1,
-                cf.seqnum
-            ); // DEBUG
             if cf.seqnum != i as u32 + 1 {
-                println!("ERROR: Sequence number mismatch!"); // DEBUG
-                return Err(Error::MissingResourceFile);
+                diagnostics::emit(Event::ResourceFileMissing {
+                    dir: path,
+                    seqnum: i as u32 + 1,
+                });
+                return Err(Error::new(ErrorKind::MissingResourceFile).with_path(path));
             }
             cf.offset = offset;
             offset += cf.len;
@@ -306,81 +313,161 @@ impl Rsc {
     }
 
     pub fn new(path: &Path, rsc_name: &str) -> Result<Self, Error> {
+        Self::new_with_options(path, rsc_name, &OpenOptions::new())
+    }
+
+    pub fn new_with_options(
+        path: &Path,
+        rsc_name: &str,
+        options: &OpenOptions,
+    ) -> Result<Self, Error> {
         let files = Rsc::files(path, rsc_name)?;
-        let index = RscIndex::new(path, rsc_name)?;
+        let index = RscIndex::new(path, rsc_name, options.storage)?;
         Ok(Self {
             index,
             files,
-            zlib_buf: Vec::new(),
-            zlib_state: zlib::DecompressorOxide::new(),
-            contents_buf: Vec::new(),
-            current_offset: 0,
-            current_len: 0,
+            chunks: ChunkCache::new(options.chunk_cache_limit()),
         })
     }
 
-    fn load_contents(&mut self, zoffset: usize) -> Result<(), Error> {
-        let (file, file_offset) = file_offset(&mut self.files, zoffset)?;
+    fn load_chunk(&self, zoffset: usize) -> Result<Arc<[u8]>, Error> {
+        self.load_chunk_inner(zoffset)
+            .at_offset(zoffset as u64)
+            .in_format(FileFormat::Rsc)
+    }
+
+    fn load_chunk_inner(&self, zoffset: usize) -> Result<Arc<[u8]>, Error> {
+        let (file, file_offset) = file_offset(&self.files, zoffset)?;
 
         let mut len = [0_u8; 4];
-        file.seek(SeekFrom::Start(file_offset))
-            .map_err(|_| Error::IOError)?;
-        file.read_exact(&mut len).map_err(|_| Error::IOError)?;
+        file.read_exact_at(&mut len, file_offset)?;
         let len = u32::from_le_bytes(len) as usize;
-        if self.zlib_buf.len() < len {
-            self.zlib_buf.resize(len, 0);
-        }
-        file.read_exact(&mut self.zlib_buf[..len])
-            .map_err(|_| Error::IOError)?;
-
-        let n_out = decompress(
-            &mut self.zlib_state,
-            &self.zlib_buf[..len],
-            &mut self.contents_buf,
-        )?;
+        let mut zlib_buf = vec![0; len];
+        file.read_exact_at(&mut zlib_buf, file_offset + 4)?;
+        Ok(decompress_to_vec(&zlib_buf)?.into())
+    }
 
-        self.current_len = n_out;
-        self.current_offset = zoffset;
+    fn chunk(&self, zoffset: usize) -> Result<Arc<[u8]>, Error> {
+        self.chunks
+            .get_or_load(zoffset, || self.load_chunk(zoffset))
+    }
 
-        Ok(())
+    /// Hit and miss counters of the decompressed chunk cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.chunks.stats()
     }
 
-    pub fn get(&mut self, id: u32) -> Result<&[u8], Error> {
+    pub fn get(&self, id: u32) -> Result<Blob, Error> {
         self.get_by_map(self.index.get_by_id(id)?)
     }
 
-    pub fn get_by_idx(&mut self, idx: usize) -> Result<(u32, &[u8]), Error> {
+    pub fn get_by_idx(&self, idx: usize) -> Result<(u32, Blob), Error> {
         let (id, map_rec) = self.index.get_by_idx(idx)?;
         let item = self.get_by_map(map_rec)?;
         Ok((id, item))
     }
 
-    fn get_by_map(&mut self, idx: MapRecord) -> Result<&[u8], Error> {
-        if self.contents_buf.is_empty() || idx.zoffset.us() != self.current_offset {
-            self.load_contents(idx.zoffset.us())?;
-        }
-
-        let contents = &self.contents_buf[idx.ioffset.us()..self.current_len];
+    fn get_by_map(&self, idx: MapRecord) -> Result<Blob, Error> {
+        let chunk = self.chunk(idx.zoffset.us())?;
+        let start = idx.ioffset.us();
+        let contents = chunk.get(start..).ok_or(ErrorKind::IndexMismach)?;
         let (len, contents_tail) = LE32::from(contents)?;
-        Ok(&contents_tail[..len.us()])
+        if contents_tail.len() < len.us() {
+            return Err(ErrorKind::IncorrectStreamLength.into());
+        }
+        let start = start + size_of::<LE32>();
+        Ok(Blob::new(chunk, start..start + len.us()))
     }
 
     pub fn len(&self) -> usize {
         self.index.map.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
-fn file_offset(contents: &mut [ResourceFile], offset: usize) -> Result<(&mut File, u64), Error> {
+fn file_offset(contents: &[ResourceFile], offset: usize) -> Result<(&ResourceFile, u64), Error> {
     let file_idx = contents
         .binary_search_by(|cf| cmp_range(offset, cf.offset..cf.offset + cf.len).reverse())
-        .map_err(|_| Error::InvalidIndex)?;
-    let cf = &mut contents[file_idx];
-    let file = &mut cf.file;
+        .map_err(|_| ErrorKind::InvalidIndex)?;
+    let cf = &contents[file_idx];
     let file_offset = (offset - cf.offset) as u64;
-    Ok((file, file_offset))
+    Ok((cf, file_offset))
+}
+
+/// Writes `items` to `dir` as the rsc files `rsc_name`, sorted by ID, with `per_chunk`
+/// items per zlib chunk, and with an idx file as the IDs aren't the item numbers.
+#[cfg(test)]
+pub(crate) fn write_test_rsc(dir: &Path, rsc_name: &str, items: &[(u32, &[u8])], per_chunk: usize) {
+    let mut items = items.to_vec();
+    items.sort();
+    let (mut rsc, mut map, mut idx) = (Vec::new(), Vec::new(), Vec::new());
+    for chunk in items.chunks(per_chunk) {
+        let mut data = Vec::new();
+        for (id, item) in chunk {
+            idx.push((*id, (map.len() / 8) as u32));
+            map.extend_from_slice(&(rsc.len() as u32).to_le_bytes());
+            map.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(item.len() as u32).to_le_bytes());
+            data.extend_from_slice(item);
+        }
+        let zlib = super::zlib_stored(&data);
+        rsc.extend_from_slice(&(zlib.len() as u32).to_le_bytes());
+        rsc.extend_from_slice(&zlib);
+    }
+    let len = (items.len() as u32).to_le_bytes();
+    let map = [&[0; 4], &len, &*map].concat();
+    let idx: Vec<u8> = [len, [0; 4]]
+        .into_iter()
+        .flatten()
+        .chain(
+            idx.iter()
+                .flat_map(|(id, i)| [id.to_le_bytes(), i.to_le_bytes()].concat()),
+        )
+        .collect();
+    fs::create_dir_all(dir).unwrap();
+    fs::write(dir.join(format!("{rsc_name}-0001.rsc")), rsc).unwrap();
+    fs::write(dir.join(format!("{rsc_name}.map")), map).unwrap();
+    fs::write(dir.join(format!("{rsc_name}.idx")), idx).unwrap();
 }
 
 #[test]
+fn test_rsc_get() {
+    let tmp = tempfile::tempdir().unwrap();
+    let items: &[(u32, &[u8])] = &[(10, b"ten"), (20, b""), (5, b"five"), (30, b"thirty")];
+    write_test_rsc(tmp.path(), "contents", items, 3);
+    let rsc = Rsc::new(tmp.path(), "contents").unwrap();
+    assert_eq!(rsc.len(), 4);
+    for (id, item) in items {
+        assert_eq!(&*rsc.get(*id).unwrap(), *item);
+    }
+    let (id, item) = rsc.get_by_idx(0).unwrap();
+    assert_eq!((id, &*item), (5, &b"five"[..]));
+    assert_eq!(rsc.get(11).unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(rsc.get_by_idx(4).is_err());
+    // Two chunks, each decompressed once
+    let stats = rsc.cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.chunks), (3, 2, 2));
+
+    let options = OpenOptions::new().chunk_cache(0).clone();
+    let uncached = Rsc::new_with_options(tmp.path(), "contents", &options).unwrap();
+    for (id, item) in items {
+        assert_eq!(&*uncached.get(*id).unwrap(), *item);
+    }
+    let stats = uncached.cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.chunks), (0, 4, 0));
+
+    // Without an index, IDs are positions in the map.
+    fs::remove_file(tmp.path().join("contents.idx")).unwrap();
+    let unindexed = Rsc::new(tmp.path(), "contents").unwrap();
+    assert_eq!(&*unindexed.get(3).unwrap(), b"thirty");
+    assert_eq!(unindexed.get(4).unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+#[test]
+#[cfg(any(unix, windows))]
 fn test_file_offset() {
     #[cfg(unix)]
     use std::os::unix::prelude::AsRawFd;
@@ -388,15 +475,18 @@ fn test_file_offset() {
     use std::os::windows::prelude::AsRawHandle;
 
     #[cfg(unix)]
-    fn raw_file_id(f: &File) -> i32 {
+    fn raw_file_id(f: &fs::File) -> i32 {
         f.as_raw_fd()
     }
     #[cfg(windows)]
-    fn raw_file_id(f: &File) -> std::os::windows::raw::HANDLE {
+    fn raw_file_id(f: &fs::File) -> std::os::windows::raw::HANDLE {
         f.as_raw_handle()
     }
 
-    assert_eq!(file_offset(&mut [], 0).err(), Some(Error::InvalidIndex));
+    assert_eq!(
+        file_offset(&[], 0).err().map(|e| e.kind()),
+        Some(ErrorKind::InvalidIndex)
+    );
 
     use tempfile::tempfile;
     let mock_file = || {
@@ -405,7 +495,7 @@ fn test_file_offset() {
         (f, id)
     };
     let (f1, f1_fd) = mock_file();
-    let one_file = &mut vec![ResourceFile {
+    let one_file = &[ResourceFile {
         seqnum: 1,
         len: 100,
         offset: 0,
@@ -413,22 +503,34 @@ fn test_file_offset() {
     }];
 
     let result = file_offset(one_file, 101);
-    assert_eq!(result.err(), Some(Error::InvalidIndex));
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(ErrorKind::InvalidIndex)
+    );
 
     let result = file_offset(one_file, 100);
-    assert_eq!(result.err(), Some(Error::InvalidIndex));
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(ErrorKind::InvalidIndex)
+    );
 
     let result = file_offset(one_file, 0);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f1_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(0));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f1_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(0));
 
     let result = file_offset(one_file, 99);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f1_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(99));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f1_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(99));
 
     let (f1, f1_fd) = mock_file();
     let (f2, f2_fd) = mock_file();
-    let two_files = &mut vec![
+    let two_files = &mut [
         ResourceFile {
             seqnum: 1,
             len: 100,
@@ -444,31 +546,49 @@ fn test_file_offset() {
     ];
 
     let result = file_offset(two_files, 301);
-    assert_eq!(result.err(), Some(Error::InvalidIndex));
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(ErrorKind::InvalidIndex)
+    );
 
     let result = file_offset(two_files, 300);
-    assert_eq!(result.err(), Some(Error::InvalidIndex));
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(ErrorKind::InvalidIndex)
+    );
 
     let result = file_offset(two_files, 0);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f1_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(0));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f1_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(0));
 
     let result = file_offset(two_files, 99);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f1_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(99));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f1_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(99));
 
     let result = file_offset(two_files, 100);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f2_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(0));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f2_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(0));
 
     let result = file_offset(two_files, 299);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f2_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(199));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f2_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(199));
 
     let (f1, f1_fd) = mock_file();
     let (f2, f2_fd) = mock_file();
     let (f3, f3_fd) = mock_file();
-    let three_files = &mut vec![
+    let three_files = &mut [
         ResourceFile {
             seqnum: 1,
             len: 100,
@@ -490,34 +610,58 @@ fn test_file_offset() {
     ];
 
     let result = file_offset(three_files, 401);
-    assert_eq!(result.err(), Some(Error::InvalidIndex));
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(ErrorKind::InvalidIndex)
+    );
 
     let result = file_offset(three_files, 400);
-    assert_eq!(result.err(), Some(Error::InvalidIndex));
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(ErrorKind::InvalidIndex)
+    );
 
     let result = file_offset(three_files, 0);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f1_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(0));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f1_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(0));
 
     let result = file_offset(three_files, 99);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f1_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(99));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f1_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(99));
 
     let result = file_offset(three_files, 100);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f2_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(0));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f2_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(0));
 
     let result = file_offset(three_files, 299);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f2_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(199));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f2_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(199));
 
     let result = file_offset(three_files, 300);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f3_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(0));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f3_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(0));
 
     let result = file_offset(three_files, 399);
-    assert_eq!(result.as_ref().map(|f| raw_file_id(&f.0)), Ok(f3_fd));
-    assert_eq!(result.as_ref().map(|f| f.1), Ok(99));
+    assert_eq!(
+        result.as_ref().ok().map(|f| raw_file_id(&f.0.file)),
+        Some(f3_fd)
+    );
+    assert_eq!(result.as_ref().ok().map(|f| f.1), Some(99));
 }
 
 fn cmp_range(num: usize, range: core::ops::Range<usize>) -> core::cmp::Ordering {
@@ -546,14 +690,31 @@ fn test_cmp_to_range() {
     assert_eq!(cmp_range(100, 100..100), Ordering::Greater);
 }
 
-pub struct RscIter<'a> {
-    map: slice::Iter<'a, MapRecord>,
-}
-
-impl<'a> Iterator for RscIter<'a> {
-    type Item = MapRecord;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.map.next().copied()
-    }
+#[test]
+fn test_files_diagnostics() {
+    use crate::diagnostics::{Level, Recording};
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("contents-0001.rsc"), [0; 10]).unwrap();
+    fs::write(dir.path().join("contents-0002.rsc"), [0; 20]).unwrap();
+    fs::write(dir.path().join("contents.map"), [0; 8]).unwrap();
+
+    let recording = Recording::start();
+    let files = Rsc::files(dir.path(), "contents").unwrap();
+    assert_eq!(files.iter().map(|f| f.offset).collect::<Vec<_>>(), [0, 10]);
+
+    fs::remove_file(dir.path().join("contents-0001.rsc")).unwrap();
+    assert_eq!(
+        Rsc::files(dir.path(), "contents").err().map(|e| e.kind()),
+        Some(ErrorKind::MissingResourceFile)
+    );
+
+    let events = recording.events();
+    let count = |level| events.iter().filter(|(l, _)| *l == level).count();
+    assert_eq!(count(Level::Debug), 3);
+    assert_eq!(count(Level::Trace), 2);
+    assert_eq!(count(Level::Warn), 1);
+    assert!(events
+        .iter()
+        .any(|(_, msg)| msg.starts_with("resource file #2") && msg.ends_with("(20 bytes)")));
 }
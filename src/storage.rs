@@ -0,0 +1,242 @@
+use core::{
+    fmt,
+    marker::PhantomData,
+    mem::{align_of, size_of},
+    ops::{Deref, Range},
+    slice,
+};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use memmap2::Mmap;
+
+use crate::{abi_utils::TransmuteSafe, error::ResultExt, Error, ErrorKind, IndexStorage};
+
+trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+enum Source {
+    Reader(Box<dyn ReadSeek>),
+    Mapped(Arc<Mmap>),
+}
+
+/// An index file that is being loaded, either by reading it into owned buffers
+/// or by mapping it into memory.
+pub(crate) struct IndexFile {
+    src: Source,
+    len: usize,
+    path: Option<PathBuf>,
+}
+
+impl IndexFile {
+    pub(crate) fn open(path: &Path, storage: IndexStorage) -> Result<Self, Error> {
+        let file = File::open(path).with_path(path)?;
+        let len = file.metadata().with_path(path)?.len() as usize;
+        let src = match storage {
+            IndexStorage::Owned => Source::Reader(Box::new(file)),
+            // SAFETY: the product files are treated as read-only. Modifying them
+            // while a dictionary is open is undefined behaviour, as with any mmap.
+            IndexStorage::Mmap => Source::Mapped(Arc::new(
+                unsafe { Mmap::map(&file) }
+                    .map_err(|e| Error::io(ErrorKind::MmapError, e).with_path(path))?,
+            )),
+        };
+        Ok(IndexFile {
+            src,
+            len,
+            path: Some(path.to_owned()),
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_bytes(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        IndexFile {
+            src: Source::Reader(Box::new(std::io::Cursor::new(bytes))),
+            len,
+            path: None,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    fn context(&self, err: impl Into<Error>, offset: usize) -> Error {
+        let err = err.into().at_offset(offset as u64);
+        match &self.path {
+            Some(path) => err.with_path(path),
+            None => err,
+        }
+    }
+
+    pub(crate) fn read_exact_at(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Error> {
+        self.read_exact_at_inner(offset, buf)
+            .map_err(|e| self.context(e, offset))
+    }
+
+    fn read_exact_at_inner(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Error> {
+        if offset + buf.len() > self.len {
+            return Err(ErrorKind::IncorrectStreamLength.into());
+        }
+        match &mut self.src {
+            Source::Reader(r) => {
+                r.seek(SeekFrom::Start(offset as u64))?;
+                r.read_exact(buf)?;
+            }
+            Source::Mapped(map) => buf.copy_from_slice(&map[offset..offset + buf.len()]),
+        }
+        Ok(())
+    }
+
+    /// Returns the records in the byte range `range`. If the length of the range
+    /// isn't a multiple of the record size, the last record extends past the range.
+    pub(crate) fn buf<T: TransmuteSafe>(&mut self, range: Range<usize>) -> Result<Buf<T>, Error> {
+        let start = range.start;
+        self.buf_inner(range).map_err(|e| self.context(e, start))
+    }
+
+    fn buf_inner<T: TransmuteSafe>(&mut self, range: Range<usize>) -> Result<Buf<T>, Error> {
+        let n = range.len().div_ceil(size_of::<T>());
+        if range.start > range.end || range.start + n * size_of::<T>() > self.len {
+            return Err(ErrorKind::IncorrectStreamLength.into());
+        }
+        match &mut self.src {
+            Source::Mapped(map) => {
+                let bytes = &map[range.start..];
+                if T::slice_from_buf(bytes, n).is_ok() {
+                    return Ok(Buf::Mapped {
+                        map: map.clone(),
+                        offset: range.start,
+                        len: n,
+                        _marker: PhantomData,
+                    });
+                }
+                // Misaligned region: fall back to copying it.
+                let mut vec = vec![T::default(); n];
+                T::slice_as_bytes_mut(&mut vec).copy_from_slice(&bytes[..n * size_of::<T>()]);
+                Ok(Buf::Owned(vec))
+            }
+            Source::Reader(r) => {
+                let mut vec = vec![T::default(); n];
+                r.seek(SeekFrom::Start(range.start as u64))?;
+                r.read_exact(T::slice_as_bytes_mut(&mut vec))?;
+                Ok(Buf::Owned(vec))
+            }
+        }
+    }
+}
+
+/// A slice of records that is either owned or borrowed from a memory-mapped file.
+pub(crate) enum Buf<T> {
+    Owned(Vec<T>),
+    Mapped {
+        map: Arc<Mmap>,
+        offset: usize,
+        len: usize,
+        _marker: PhantomData<T>,
+    },
+}
+
+impl<T: TransmuteSafe> Deref for Buf<T> {
+    type Target = [T];
+
+    // `usize::is_multiple_of` would raise the MSRV to 1.87.
+    #[allow(clippy::manual_is_multiple_of)]
+    fn deref(&self) -> &[T] {
+        match self {
+            Buf::Owned(vec) => vec,
+            Buf::Mapped {
+                map, offset, len, ..
+            } => {
+                // USE INVARIANT: bounds and alignment of the mapped region were checked
+                // with `T::slice_from_buf` when the `Buf` was created.
+                debug_assert!((map.as_ptr() as usize + offset) % align_of::<T>() == 0);
+                unsafe { slice::from_raw_parts(map.as_ptr().add(*offset) as *const T, *len) }
+            }
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Buf<T> {
+    fn from(vec: Vec<T>) -> Self {
+        Buf::Owned(vec)
+    }
+}
+
+impl<T: Clone> Clone for Buf<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Buf::Owned(vec) => Buf::Owned(vec.clone()),
+            Buf::Mapped {
+                map, offset, len, ..
+            } => Buf::Mapped {
+                map: map.clone(),
+                offset: *offset,
+                len: *len,
+                _marker: PhantomData,
+            },
+        }
+    }
+}
+
+impl<T: TransmuteSafe + fmt::Debug> fmt::Debug for Buf<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Buf::Owned(_) => f.write_str("Owned")?,
+            Buf::Mapped { .. } => f.write_str("Mapped")?,
+        }
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[test]
+fn test_index_file_storage() {
+    use crate::abi_utils::LE32;
+    use std::io::Write;
+
+    let mut bytes = Vec::new();
+    for i in 0..16_u32 {
+        bytes.extend_from_slice(&i.to_le_bytes());
+    }
+    let mut tmp = tempfile::NamedTempFile::new().unwrap();
+    tmp.write_all(&bytes).unwrap();
+
+    for storage in [IndexStorage::Owned, IndexStorage::Mmap] {
+        let mut file = IndexFile::open(tmp.path(), storage).unwrap();
+        assert_eq!(file.len(), 64);
+
+        let buf: Buf<LE32> = file.buf(8..24).unwrap();
+        assert_eq!(
+            buf.iter().map(|v| v.read()).collect::<Vec<_>>(),
+            [2, 3, 4, 5]
+        );
+        assert_eq!(
+            matches!(buf, Buf::Mapped { .. }),
+            storage == IndexStorage::Mmap
+        );
+
+        // Misaligned regions are copied
+        let buf: Buf<LE32> = file.buf(1..5).unwrap();
+        assert!(matches!(buf, Buf::Owned(_)));
+        assert_eq!(buf[0].read(), 1 << 24);
+
+        let buf: Buf<u8> = file.buf(60..64).unwrap();
+        assert_eq!(&*buf, &[15, 0, 0, 0]);
+
+        let mut word = [0; 4];
+        file.read_exact_at(4, &mut word).unwrap();
+        assert_eq!(word, [1, 0, 0, 0]);
+
+        let err = file.buf::<LE32>(60..65).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::IncorrectStreamLength);
+        assert_eq!(err.location(), Some(crate::Location::Offset(60)));
+        assert_eq!(err.path(), Some(tmp.path()));
+        let err = file.read_exact_at(62, &mut word).unwrap_err();
+        assert_eq!(err.location(), Some(crate::Location::Offset(62)));
+    }
+}